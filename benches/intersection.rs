@@ -0,0 +1,105 @@
+// Scalar against SIMD intersection of a random-scene sized set of spheres, on their own and in
+// a BVH. Compare with the portable fallback using `cargo bench --features scalar`.
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use parallel_raytracing_rs::utils::bvh::Bvh;
+use parallel_raytracing_rs::utils::camera::Camera;
+use parallel_raytracing_rs::utils::geometry::{Hittable, HittableList, Sphere, SphereBatch};
+use parallel_raytracing_rs::utils::material::Material;
+use parallel_raytracing_rs::utils::ray::Ray;
+use parallel_raytracing_rs::utils::real::Real;
+use parallel_raytracing_rs::utils::sampler::SamplerKind;
+use parallel_raytracing_rs::utils::simd::{Realx4, RayPacket};
+use parallel_raytracing_rs::utils::vector::Vec3;
+
+// Small spheres scattered over a ground sphere, laid out like `scene::random_scene`
+fn spheres(count: usize) -> Vec<Sphere> {
+    let mut rng = StdRng::seed_from_u64(0);
+    let material = Material::Lambertian { albedo: Vec3::new(0.5, 0.5, 0.5) };
+    let mut spheres = vec![Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, material.clone())];
+    while spheres.len() < count {
+        let center = Vec3::new(rng.gen_range(-11.0..11.0), 0.2, rng.gen_range(-11.0..11.0));
+        spheres.push(Sphere::new(center, 0.2, material.clone()));
+    }
+    spheres
+}
+
+fn list(spheres: Vec<Sphere>) -> HittableList {
+    HittableList::new(spheres.into_iter().map(|s| Box::new(s) as Box<dyn Hittable + Sync + Send>).collect())
+}
+
+// Camera rays through a 2x2 pixel block for each of `count` blocks across the image
+fn primary_packets(count: usize) -> Vec<[Ray; 4]> {
+    let camera = Camera::new(Vec3::new(13.0, 2.0, 3.0), Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 20.0, 1.5, 0.0, 10.0);
+    let mut sampler = SamplerKind::Independent.create(1, 0);
+    let (width, height) = (600.0, 400.0);
+    (0..count).map(|i| {
+        let (x, y) = ((i * 37 % 300) as Real * 2.0, (i * 53 % 200) as Real * 2.0);
+        std::array::from_fn(|j| {
+            let (u, v) = ((x + (j % 2) as Real) / width, (y + (j / 2) as Real) / height);
+            camera.get_ray(u, v, sampler.as_mut()).unwrap()
+        })
+    }).collect()
+}
+
+fn scattered_rays(count: usize) -> Vec<Ray> {
+    let mut rng = StdRng::seed_from_u64(1);
+    (0..count).map(|_| {
+        let origin = Vec3::new(rng.gen_range(-11.0..11.0), rng.gen_range(0.0..2.0), rng.gen_range(-11.0..11.0));
+        let direction = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+        Ray::new(origin, direction)
+    }).collect()
+}
+
+fn single_ray(c: &mut Criterion) {
+    let rays = scattered_rays(256);
+    let mut group = c.benchmark_group("single_ray");
+    group.throughput(Throughput::Elements(rays.len() as u64));
+
+    for count in [16, 128, 485] {
+        let scalar = list(spheres(count));
+        let batch = SphereBatch::new(spheres(count));
+        group.bench_with_input(BenchmarkId::new("scalar", count), &rays, |b, rays| {
+            b.iter(|| rays.iter().filter(|r| scalar.hit(black_box(r), 0.0, Real::INFINITY).is_some()).count())
+        });
+        group.bench_with_input(BenchmarkId::new("sphere_batch", count), &rays, |b, rays| {
+            b.iter(|| rays.iter().filter(|r| batch.hit(black_box(r), 0.0, Real::INFINITY).is_some()).count())
+        });
+    }
+    group.finish();
+}
+
+fn primary_packet(c: &mut Criterion) {
+    let packets = primary_packets(64);
+    let mut group = c.benchmark_group("primary_packet");
+    group.throughput(Throughput::Elements(4 * packets.len() as u64));
+
+    for count in [16, 128, 485] {
+        let scalar = list(spheres(count));
+        let batch = SphereBatch::new(spheres(count));
+        let bvh = Bvh::new(spheres(count));
+        group.bench_with_input(BenchmarkId::new("scalar", count), &packets, |b, packets| {
+            b.iter(|| packets.iter().flatten().filter(|r| scalar.hit(black_box(r), 0.0, Real::INFINITY).is_some()).count())
+        });
+        group.bench_with_input(BenchmarkId::new("ray_packet", count), &packets, |b, packets| {
+            b.iter(|| packets.iter().map(|rays| {
+                let packet = RayPacket::new(*rays);
+                batch.hit_packet(black_box(&packet), 0.0, Realx4::splat(Real::INFINITY)).iter().filter(|r| r.is_some()).count()
+            }).sum::<usize>())
+        });
+        group.bench_with_input(BenchmarkId::new("bvh", count), &packets, |b, packets| {
+            b.iter(|| packets.iter().flatten().filter(|r| bvh.hit(black_box(r), 0.0, Real::INFINITY).is_some()).count())
+        });
+        group.bench_with_input(BenchmarkId::new("bvh_packet", count), &packets, |b, packets| {
+            b.iter(|| packets.iter().map(|rays| {
+                let packet = RayPacket::new(*rays);
+                bvh.hit_packet(black_box(&packet), 0.0, Realx4::splat(Real::INFINITY)).iter().filter(|r| r.is_some()).count()
+            }).sum::<usize>())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, single_ray, primary_packet);
+criterion_main!(benches);
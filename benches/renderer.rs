@@ -0,0 +1,97 @@
+// Micro benchmarks of the pieces a path is made of, plus a small end to end render. For
+// throughput and thread scaling of full renders use `cargo run --release -- render-bench`.
+use std::sync::Arc;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use parallel_raytracing_rs::utils::geometry::{HitRecord, Hittable, Sphere};
+use parallel_raytracing_rs::utils::material::{scatter, Material};
+use parallel_raytracing_rs::utils::ray::Ray;
+use parallel_raytracing_rs::utils::real::Real;
+use parallel_raytracing_rs::utils::renderer::{render, RenderSettings};
+use parallel_raytracing_rs::utils::sampler::SamplerKind;
+use parallel_raytracing_rs::utils::scene;
+use parallel_raytracing_rs::utils::vector::Vec3;
+use parallel_raytracing_rs::utils::wavefront::Integrator;
+
+fn sphere_hit(c: &mut Criterion) {
+    let sphere = Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5, Material::Lambertian { albedo: Vec3::new(0.5, 0.5, 0.5) });
+    let hit = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.1, 0.1, -1.0));
+    let miss = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.1, -1.0));
+
+    c.bench_function("sphere_hit/hit", |b| b.iter(|| sphere.hit(black_box(&hit), 0.0, Real::INFINITY)));
+    c.bench_function("sphere_hit/miss", |b| b.iter(|| sphere.hit(black_box(&miss), 0.0, Real::INFINITY)));
+}
+
+fn hittable_list_hit(c: &mut Criterion) {
+    let scene = scene::default_scene(1.5, 20.0);
+    let ray = Ray::new(Vec3::new(3.0, 3.0, 2.0), Vec3::new(-3.0, -3.0, -3.0));
+    c.bench_function("hittable_list_hit/default_scene", |b| b.iter(|| scene.world.hit(black_box(&ray), 0.0, Real::INFINITY)));
+}
+
+fn scatter_materials(c: &mut Criterion) {
+    let materials = [
+        ("lambertian", Material::Lambertian { albedo: Vec3::new(0.7, 0.3, 0.3) }),
+        ("metal", Material::Metal { albedo: Vec3::new(0.8, 0.6, 0.2), fuzz: 0.0 }),
+        ("fuzzy_metal", Material::Metal { albedo: Vec3::new(0.8, 0.6, 0.2), fuzz: 0.5 }),
+        ("dielectric", Material::Dielctric { ir: 1.5 }),
+    ];
+    let r_in = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.1, 0.1, -1.0));
+    let mut sampler = SamplerKind::Independent.create(1, 0);
+
+    for (name, material) in materials {
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5, material.clone());
+        let rec: HitRecord = sphere.hit(&r_in, 0.0, Real::INFINITY).unwrap();
+        c.bench_function(&format!("scatter/{}", name), |b| {
+            b.iter(|| scatter(black_box(&material), &r_in, &rec, sampler.as_mut()))
+        });
+    }
+}
+
+fn camera_get_ray(c: &mut Criterion) {
+    let scene = scene::default_scene(1.5, 20.0);
+    let mut sampler = SamplerKind::Independent.create(1, 0);
+    c.bench_function("camera_get_ray/thin_lens", |b| {
+        b.iter(|| scene.camera.get_ray(black_box(0.3), black_box(0.6), sampler.as_mut()))
+    });
+}
+
+fn small_render(c: &mut Criterion) {
+    let scene = Arc::new(scene::default_scene(1.5, 20.0));
+    let settings = RenderSettings {
+        image_width: 60,
+        image_height: 40,
+        samples_per_pixel: 4,
+        max_depth: 5,
+        num_threads: 1,
+        sampler: SamplerKind::Sobol,
+        seed: 0,
+        ..RenderSettings::default()
+    };
+
+    let mut group = c.benchmark_group("render");
+    group.sample_size(20);
+    group.bench_function("default_scene_60x40_4spp", |b| b.iter(|| render(scene.clone(), &settings).unwrap()));
+    group.finish();
+}
+
+// The recursive megakernel against the wavefront integrator on a scene with every material
+fn integrators(c: &mut Criterion) {
+    let scene = Arc::new(scene::seeded_random_scene(0, 1.5, 20.0));
+    let mut group = c.benchmark_group("integrator");
+    group.sample_size(10);
+    for (name, integrator) in [("megakernel", Integrator::Megakernel), ("wavefront", Integrator::Wavefront)] {
+        let settings = RenderSettings {
+            image_width: 120,
+            image_height: 80,
+            samples_per_pixel: 8,
+            max_depth: 5,
+            num_threads: 1,
+            integrator,
+            ..RenderSettings::default()
+        };
+        group.bench_function(format!("random_scene_120x80_8spp/{}", name), |b| b.iter(|| render(scene.clone(), &settings).unwrap()));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, sphere_hit, hittable_list_hit, scatter_materials, camera_get_ray, small_render, integrators);
+criterion_main!(benches);
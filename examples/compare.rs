@@ -0,0 +1,36 @@
+// Compares two renders of the same scene, such as the images `render-bench --image` writes
+// from the f32 and f64 builds: the RMSE between them and how far apart their means are, over
+// gamma encoded channels from 0 to 1. Run with
+// `cargo run --release --example compare -- a.png b.png`.
+use image::RgbImage;
+use parallel_raytracing_rs::RenderError;
+
+fn open(path: &str) -> Result<RgbImage, RenderError> {
+    Ok(image::open(path).map_err(|e| RenderError::image(path, e))?.to_rgb8())
+}
+
+fn channels(image: &RgbImage) -> impl Iterator<Item = f64> + '_ {
+    image.pixels().flat_map(|p| p.0).map(|c| c as f64 / 255.0)
+}
+
+fn mean(image: &RgbImage) -> f64 {
+    channels(image).sum::<f64>() / (3 * image.width() * image.height()) as f64
+}
+
+fn main() -> Result<(), RenderError> {
+    let args: Vec<String> = std::env::args().collect();
+    let [_, a, b] = args.as_slice() else {
+        return Err(RenderError::InvalidSettings("usage: compare a.png b.png".to_string()));
+    };
+    let (a_image, b_image) = (open(a)?, open(b)?);
+    if a_image.dimensions() != b_image.dimensions() {
+        return Err(RenderError::InvalidSettings(format!("{} is {:?} but {} is {:?}", a, a_image.dimensions(), b, b_image.dimensions())));
+    }
+
+    let squared: f64 = channels(&a_image).zip(channels(&b_image)).map(|(a, b)| (a - b) * (a - b)).sum();
+    let rmse = (squared / (3 * a_image.width() * a_image.height()) as f64).sqrt();
+    let (a_mean, b_mean) = (mean(&a_image), mean(&b_image));
+    println!("RMSE: {:.4}", rmse);
+    println!("Means: {:.4} and {:.4}, {:.2}% apart", a_mean, b_mean, 100.0 * (a_mean - b_mean).abs() / a_mean);
+    Ok(())
+}
@@ -0,0 +1,40 @@
+// Builds a scene from scratch and renders it in memory, as a program embedding the renderer
+// would. Run with `cargo run --release --example embed`.
+use parallel_raytracing_rs::{render, CameraBuilder, Hittable, HittableList, Light, Material, RenderError, RenderSettings, Scene, Sphere, Vec3};
+
+fn main() -> Result<(), RenderError> {
+    let aspect_ratio = 16.0 / 9.0;
+    let ground = Material::Lambertian { albedo: Vec3::new(0.4, 0.45, 0.5) };
+    let objects: Vec<Box<dyn Hittable + Sync + Send>> = vec![
+        Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground)),
+        Box::new(Sphere::new(Vec3::new(-1.1, 0.5, 0.0), 0.5, Material::Lambertian { albedo: Vec3::new(0.8, 0.2, 0.1) })),
+        Box::new(Sphere::new(Vec3::new(0.0, 0.5, 0.0), 0.5, Material::Dielctric { ir: 1.5 })),
+        Box::new(Sphere::new(Vec3::new(1.1, 0.5, 0.0), 0.5, Material::Metal { albedo: Vec3::new(0.9, 0.9, 0.9), fuzz: 0.05 })),
+    ];
+
+    let camera = CameraBuilder::new(Vec3::new(0.0, 1.2, 4.0))
+        .with_look_at(Vec3::new(0.0, 0.5, 0.0))
+        .with_aspect_ratio(aspect_ratio)
+        .with_focal_length(35.0)
+        .with_f_number(4.0)
+        .build();
+    let scene = Scene::new(HittableList::new(objects), camera)
+        .with_light(Light::Point { position: Vec3::new(2.0, 3.0, 2.0), intensity: Vec3::new(6.0, 6.0, 6.0) });
+
+    let settings = RenderSettings {
+        image_width: 480,
+        image_height: (480.0 / aspect_ratio) as u32,
+        samples_per_pixel: 64,
+        ..RenderSettings::default()
+    };
+    let output = render(scene, &settings)?;
+
+    // The radiance is available directly, e.g. to meter the image or feed another stage
+    let pixels = output.image.pixels();
+    let mean = pixels.iter().map(|c| (c.x + c.y + c.z) / 3.0).sum::<f32>() / pixels.len() as f32;
+    println!("Mean radiance {:.3} from {} rays", mean, output.stats.total_rays());
+
+    output.image.save_png("embed.png")?;
+    println!("Wrote embed.png");
+    Ok(())
+}
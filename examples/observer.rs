@@ -0,0 +1,39 @@
+// Streams tiles into a preview as they finish, the way a UI would show a render coming
+// together, and saves the preview at every progress milestone. Run with
+// `cargo run --release --example observer`.
+use std::sync::Mutex;
+use parallel_raytracing_rs::{render_with_observer, scene, Framebuffer, Progress, RenderError, RenderObserver, RenderSettings, TileUpdate};
+
+struct Preview {
+    image: Mutex<Framebuffer>,
+}
+
+impl RenderObserver for Preview {
+    fn tile_completed(&self, tile: &TileUpdate) {
+        self.image.lock().unwrap().blit(tile.pixels, tile.x0, tile.y0);
+    }
+
+    fn progress(&self, progress: Progress) {
+        let percent = (progress.fraction() * 100.0).round();
+        let file = format!("preview_{:03}.png", percent);
+        // A preview that can't be written shouldn't stop the render
+        match self.image.lock().unwrap().save_png(&file) {
+            Ok(()) => println!("{}% after {:?}, wrote {}", percent, progress.elapsed, file),
+            Err(e) => eprintln!("{}% after {:?}, {}", percent, progress.elapsed, e),
+        }
+    }
+}
+
+fn main() -> Result<(), RenderError> {
+    let settings = RenderSettings {
+        image_width: 600,
+        image_height: 400,
+        samples_per_pixel: 32,
+        ..RenderSettings::default()
+    };
+    let preview = Preview { image: Mutex::new(Framebuffer::new(settings.image_width, settings.image_height)) };
+    let output = render_with_observer(scene::random_scene(1.5, 20.0), &settings, &preview)?;
+    output.image.save_png("observer.png")?;
+    println!("Wrote observer.png");
+    Ok(())
+}
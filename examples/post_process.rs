@@ -0,0 +1,33 @@
+// Renders the default scene with a depth pass and post-processes the in-memory result: fog
+// that thickens with distance beyond the nearest surface, then a Reinhard tone map of the
+// linear radiance. Run with `cargo run --release --example post_process`.
+use image::{Rgb, RgbImage};
+use parallel_raytracing_rs::{render, scene, Pass, RenderError, RenderSettings, Vec3};
+
+fn main() -> Result<(), RenderError> {
+    let settings = RenderSettings {
+        image_width: 450,
+        image_height: 300,
+        samples_per_pixel: 64,
+        passes: vec![Pass::Depth],
+        ..RenderSettings::default()
+    };
+    let output = render(scene::default_scene(1.5, 20.0), &settings)?;
+    let depth = output.aov(Pass::Depth).unwrap();
+    let nearest = depth.iter().copied().filter(|d| d.is_finite() && *d > 0.0).fold(f32::INFINITY, f32::min);
+
+    let fog = Vec3::new(0.75, 0.8, 0.85);
+    let image = RgbImage::from_fn(output.image.width(), output.image.height(), |x, y| {
+        let i = (y * output.image.width() + x) as usize;
+        // Background pixels have no depth, so they disappear into the fog completely
+        let distance = if depth[i].is_finite() && depth[i] > 0.0 { depth[i] } else { f32::INFINITY };
+        let visibility = (-0.8 * (distance - nearest)).exp();
+        let c = output.image.pixel(x, y) * visibility + fog * (1.0 - visibility);
+        let tone_map = |v: f32| ((v / (1.0 + v)).powf(1.0 / 2.2) * 255.0) as u8;
+        Rgb([tone_map(c.x), tone_map(c.y), tone_map(c.z)])
+    });
+
+    image.save("post_process.png").map_err(|e| RenderError::image("post_process.png", e))?;
+    println!("Wrote post_process.png");
+    Ok(())
+}
@@ -0,0 +1,36 @@
+// A multi-threaded path tracer. Build a `Scene` from `Hittable` objects with `Material`s and a
+// `Camera`, pick `RenderSettings`, and `render` returns the image in memory:
+//
+//     let scene = scene::default_scene(3.0 / 2.0, 20.0);
+//     let settings = RenderSettings { image_width: 300, image_height: 200, ..RenderSettings::default() };
+//     let image = render(scene, &settings)?.image.to_rgb_image();
+//
+// The items re-exported here are the supported API. `utils` exposes the renderer's internals
+// for benchmarks, tests and experiments, and changes more freely.
+pub mod utils;
+
+pub use utils::aov::{AovBuffers, AovOutput, Pass};
+pub use utils::bvh::{Aabb, Bvh};
+pub use utils::camera::{Camera, CameraBuilder, EquirectangularCamera, FisheyeCamera, OmniStereoCamera, OrthographicCamera, PerspectiveCamera, StereoEye};
+pub use utils::cancel::CancellationToken;
+pub use utils::denoise::DenoiseSettings;
+pub use utils::environment::{Environment, EnvironmentMap};
+pub use utils::error::{RenderError, Result};
+pub use utils::film::Filter;
+pub use utils::framebuffer::Framebuffer;
+pub use utils::generators::Generator;
+pub use utils::geometry::{HitRecord, Hittable, HittableList, Sphere, SphereBatch};
+pub use utils::lens::{Aperture, ApertureMask};
+pub use utils::light::{Light, LightList};
+pub use utils::material::Material;
+pub use utils::observer::{ConsoleProgress, Progress, RenderObserver, TileUpdate};
+pub use utils::parallel::{RenderPool, Strategy};
+pub use utils::ray::Ray;
+pub use utils::real::Real;
+pub use utils::renderer::{render, render_with_observer, RenderOutput, RenderSettings, RenderStatus};
+pub use utils::sampler::SamplerKind;
+pub use utils::scene::{self, Scene};
+pub use utils::sky::Sky;
+pub use utils::stats::RenderStats;
+pub use utils::vector::Vec3;
+pub use utils::wavefront::Integrator;
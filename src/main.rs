@@ -1,14 +1,38 @@
-mod utils;
-use std::io::prelude::*;
 use std::time::Instant;
-use utils::vector::*;
-use crate::utils::geometry::*;
-use crate::utils::scene;
-use crate::utils::renderer::render;
+use parallel_raytracing_rs::{render_with_observer, scene, AovOutput, ConsoleProgress, Filter, Generator, Integrator, Real, RenderError, RenderPool, RenderSettings, RenderStatus, SamplerKind, Strategy};
+//use parallel_raytracing_rs::{Environment, EnvironmentMap, Sky, Vec3};
+//use parallel_raytracing_rs::{Camera, EquirectangularCamera, FisheyeCamera};
+use parallel_raytracing_rs::utils::benchmark::render_bench;
 
-fn main() {
-    // Multi-threading
+fn main() -> Result<(), RenderError> {
+    // `[--scene NAME] [--seed N]` renders one of the procedural scenes in `Generator::NAMES`, and
+    // `render-bench [--wavefront] [--scene NAME] [--seed N] [--image FILE.png] [output.json]`
+    // times a smaller render across thread counts instead, optionally keeping its image
+    let args: Vec<String> = std::env::args().collect();
+    let bench = args.get(1).is_some_and(|arg| arg == "render-bench");
+    let mut wavefront = false;
+    let mut scene_name = None;
+    let mut scene_seed = 0;
+    let mut bench_file = "render-bench.json";
+    let mut bench_image = None;
+    let mut rest = args.iter().skip(if bench { 2 } else { 1 });
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--wavefront" => wavefront = true,
+            "--scene" => scene_name = Some(rest.next().ok_or_else(|| RenderError::InvalidSettings("--scene needs a name".to_string()))?),
+            "--seed" => {
+                let seed = rest.next().and_then(|seed| seed.parse().ok());
+                scene_seed = seed.ok_or_else(|| RenderError::InvalidSettings("--seed needs a whole number".to_string()))?;
+            }
+            "--image" if bench => bench_image = Some(rest.next().ok_or_else(|| RenderError::InvalidSettings("--image needs a file name".to_string()))?),
+            file if bench && !file.starts_with("--") => bench_file = file,
+            other => return Err(RenderError::InvalidSettings(format!("unknown argument '{}'", other))),
+        }
+    }
+
+    // Multi-threading. One pool builds the scene and renders it.
     let num_threads = num_cpus::get() as u32;
+    let pool = RenderPool::new(num_threads, false)?;
 
     // Image
     let aspect_ratio = 3.0 / 2.0;
@@ -18,40 +42,107 @@ fn main() {
     let max_depth: i32 = 5;
     let output_file = "default_scene_rel_mt.png";
 
-    let mut image_height = (image_width as f32 / aspect_ratio) as u32;
+    let mut image_height = (image_width as Real / aspect_ratio) as u32;
     image_height = if image_height < 1 { 1 } else { image_height };
 
     // World
     let scene_start = Instant::now();
 
-    //let (world, camera) = scene::random_scene(aspect_ratio, fov);
-    let (world, camera) = scene::default_scene(aspect_ratio, fov);
+    //let scene = pool.install(|| scene::random_scene(aspect_ratio, fov));
+    let scene = match scene_name {
+        Some(name) => {
+            let generator = Generator::from_name(name)?;
+            pool.install(|| generator.generate(scene_seed, aspect_ratio))?
+        }
+        None => pool.install(|| scene::default_scene(aspect_ratio, fov)),
+    };
+    // Light the scene with an HDR image instead of the gradient sky
+    //let scene = scene.with_environment(Environment::Image(EnvironmentMap::open("studio.hdr")?));
+    // Or with a daylight sky: sun direction, turbidity and ground albedo
+    //let scene = scene.with_environment(Environment::Sky(Sky::new(Vec3::new(1.0, 0.6, 0.5), 3.0, Vec3::new(0.3, 0.3, 0.3))));
+    // Render a 360 degree panorama (use a 2:1 aspect ratio) or a 180 degree fisheye instead
+    //let scene = scene.with_camera(Camera::Equirectangular(EquirectangularCamera::new(Vec3::new(0.0, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0))));
+    //let scene = scene.with_camera(Camera::Fisheye(FisheyeCamera::new(Vec3::new(0.0, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0), 180.0, aspect_ratio)));
 
     let scene_duration = scene_start.elapsed();
 
     println!("Scene generation took: {:?}", scene_duration);
 
-    println!("Beginning render!");
-    println!("Image size: {}x{}", image_width, image_height);
-    println!("Samples per pixel: {}", samples_per_pixel);
-    println!("Max Bounces: {}", max_depth);
-    println!("Number of Threads Threads: {}", num_threads);
-
     // Render
-    render(
-        camera,
-        world,
+    let settings = RenderSettings {
         image_width,
         image_height,
         samples_per_pixel,
         max_depth,
         num_threads,
-        output_file
-    );
+        // Pin the threads to cores when the render starts its own pool
+        pin_threads: false,
+        pool: Some(pool),
+        // Or Strategy::Tiles / Strategy::Rows, which finish each block before starting another,
+        // or Strategy::Samples, which gives every thread the whole frame and a share of the samples
+        strategy: Strategy::SamplePasses,
+        // Or Integrator::Wavefront, which traces paths in waves, stage by stage
+        integrator: Integrator::Megakernel,
+        output_file: output_file.to_string(),
+        // Auxiliary passes for compositing, e.g. vec![Pass::Depth, Pass::Normal, Pass::Albedo]
+        passes: vec![],
+        aov_output: AovOutput::MultiLayerExr,
+        // Denoise low sample count previews, e.g. Some(DenoiseSettings::default())
+        denoise: None,
+        sampler: SamplerKind::Sobol,
+        seed: 0,
+        // Sharper than the default box, e.g. Filter::Mitchell { radius: 2.0, b: 1.0 / 3.0, c: 1.0 / 3.0 }
+        filter: Filter::default(),
+        // Counters are always printed after the render, e.g. Some("stats.json".to_string()) to keep them
+        stats_file: None,
+        // Stopped from another thread through a CancellationToken, e.g. by a UI
+        cancel: None,
+        // Render as many samples as fit in a budget, e.g. Some(Duration::from_secs(30)) with samples_per_pixel: u32::MAX
+        time_limit: None,
+    };
+
+    if bench {
+        // Small enough that the single threaded run stays short
+        let settings = RenderSettings {
+            image_width: 400,
+            image_height: (400.0 / aspect_ratio) as u32,
+            samples_per_pixel: 32,
+            integrator: if wavefront { Integrator::Wavefront } else { settings.integrator },
+            ..settings
+        };
+        let report = render_bench(scene, scene_name.map_or("default", String::as_str), &settings, num_threads)?;
+        report.print_table();
+        std::fs::write(bench_file, report.to_json()).map_err(|e| RenderError::io(bench_file, e))?;
+        println!("Wrote {}", bench_file);
+        // e.g. to compare the f32 and f64 builds with `--example compare`
+        if let Some(file) = bench_image {
+            report.image.save_png(file)?;
+            println!("Wrote {}", file);
+        }
+        return Ok(());
+    }
+
+    println!("Beginning render!");
+    println!("Image size: {}x{}", image_width, image_height);
+    println!("Samples per pixel: {}", samples_per_pixel);
+    println!("Max Bounces: {}", max_depth);
+    println!("Number of Threads Threads: {}", num_threads);
+
+    let output = render_with_observer(scene, &settings, &ConsoleProgress)?;
+    println!("Finished Rendering!");
+    if output.status != RenderStatus::Complete {
+        let fewest = output.sample_counts.iter().min().unwrap_or(&0);
+        let most = output.sample_counts.iter().max().unwrap_or(&0);
+        println!("Stopped early ({:?}) with {} to {} samples per pixel", output.status, fewest, most);
+    }
+    output.stats.print_table();
+    for path in output.save(&settings)? {
+        println!("Wrote {}", path.display());
+    }
 
     let render_duration = scene_start.elapsed() - scene_duration;
     println!("Render took: {:?}", render_duration);
     println!("Output file: {}", output_file);
-
+    Ok(())
 }
 
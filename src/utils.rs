@@ -1,8 +1,32 @@
+pub mod real;
 pub mod vector;
 pub mod color;
 pub mod ray;
 pub mod geometry;
+pub mod bvh;
 pub mod camera;
-pub(crate) mod material;
+pub mod material;
 pub mod scene;
-pub mod renderer;
\ No newline at end of file
+pub mod generators;
+pub mod renderer;
+pub mod cancel;
+pub mod parallel;
+pub mod distribution;
+pub mod environment;
+pub mod sky;
+pub mod light;
+pub mod aov;
+pub mod denoise;
+pub mod sampler;
+pub mod film;
+pub mod framebuffer;
+pub mod error;
+pub mod observer;
+pub mod lens;
+pub mod simd;
+pub mod stats;
+pub mod wavefront;
+pub mod benchmark;
+// Test helpers for tests/materials.rs, not part of the supported API
+#[doc(hidden)]
+pub mod validation;
@@ -0,0 +1,278 @@
+use std::path::{Path, PathBuf};
+use exr::prelude::{AnyChannel, AnyChannels, Encoding, FlatSamples, Image, ImageAttributes, IntegerBounds, Layer, LayerAttributes, SmallVec, Vec2, WritableImage};
+use image::{Rgb, Rgb32FImage};
+use crate::utils::error::{RenderError, Result};
+use crate::utils::geometry::HitRecord;
+use crate::utils::material::Material;
+use crate::utils::ray::Ray;
+use crate::utils::real::{to_f32, Real};
+use crate::utils::vector::Vec3;
+
+// Auxiliary buffers ("arbitrary output variables") recorded at each camera ray's first hit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    // Distance from the camera to the first hit
+    Depth,
+    // World space shading normal
+    Normal,
+    Albedo,
+    // World space position
+    Position,
+    // Index of the hit object within the scene's `HittableList`, or -1 for the background
+    ObjectId,
+    // Hash of the hit material's parameters, shared by identical materials, or -1 for the background
+    MaterialId,
+    SampleCount,
+}
+
+impl Pass {
+    pub const ALL: [Pass; 7] = [Pass::Depth, Pass::Normal, Pass::Albedo, Pass::Position, Pass::ObjectId, Pass::MaterialId, Pass::SampleCount];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pass::Depth => "depth",
+            Pass::Normal => "normal",
+            Pass::Albedo => "albedo",
+            Pass::Position => "position",
+            Pass::ObjectId => "object_id",
+            Pass::MaterialId => "material_id",
+            Pass::SampleCount => "sample_count",
+        }
+    }
+
+    fn channels(&self) -> &'static [&'static str] {
+        match self {
+            Pass::Depth => &["Z"],
+            Pass::Normal | Pass::Albedo | Pass::Position => &["R", "G", "B"],
+            Pass::ObjectId | Pass::MaterialId | Pass::SampleCount => &["Y"],
+        }
+    }
+
+    // Identifiers are not averaged across a pixel's samples, the first hit wins
+    fn is_identifier(&self) -> bool {
+        matches!(self, Pass::ObjectId | Pass::MaterialId)
+    }
+}
+
+// How the selected passes are written next to the beauty image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AovOutput {
+    // One single-layer EXR per pass, named `<output>.<pass>.exr`
+    SeparateFiles,
+    // A single `<output>.exr` holding the linear beauty image and one layer per pass
+    MultiLayerExr,
+}
+
+// What a camera ray saw at its first hit, `None` when it escaped to the environment. Stored
+// as f32 like the buffers it goes into.
+pub struct FirstHit {
+    pub distance: f32,
+    pub normal: Vec3<f32>,
+    pub albedo: Vec3<f32>,
+    pub position: Vec3<f32>,
+    pub object_id: usize,
+    pub material_id: u32,
+}
+
+impl FirstHit {
+    pub fn new(ray: &Ray, rec: &HitRecord) -> Self {
+        FirstHit {
+            distance: to_f32(rec.t * ray.direction.length()),
+            normal: rec.normal.to_f32(),
+            albedo: rec.material.as_ref().map_or(Vec3::new(0.0, 0.0, 0.0), |m| m.albedo().to_f32()),
+            position: rec.p.to_f32(),
+            object_id: rec.object_id,
+            material_id: rec.material.as_ref().map_or(0, material_id),
+        }
+    }
+}
+
+// Per-pixel accumulation of every selected pass, row-major like the beauty image
+#[derive(Clone)]
+pub struct AovBuffers {
+    pub width: u32,
+    pub height: u32,
+    pub passes: Vec<Pass>,
+    data: Vec<Vec<f32>>,
+    hits: Vec<u32>,
+}
+
+impl AovBuffers {
+    pub fn new(width: u32, height: u32, passes: &[Pass]) -> Self {
+        let pixels = (width * height) as usize;
+        let data = passes.iter().map(|p| {
+            let fill = if p.is_identifier() { -1.0 } else { 0.0 };
+            vec![fill; pixels * p.channels().len()]
+        }).collect();
+
+        AovBuffers { width, height, passes: passes.to_vec(), data, hits: vec![0; pixels] }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    // Adds one camera sample's first hit to the running sums for pixel (x, y)
+    pub fn add_sample(&mut self, x: u32, y: u32, hit: Option<&FirstHit>) {
+        let index = (y * self.width + x) as usize;
+        let first = self.hits[index] == 0;
+        if hit.is_some() {
+            self.hits[index] += 1;
+        }
+
+        for (pass, data) in self.passes.iter().zip(self.data.iter_mut()) {
+            let n = pass.channels().len();
+            let pixel = &mut data[index * n..(index + 1) * n];
+            match (pass, hit) {
+                (Pass::SampleCount, _) => pixel[0] += 1.0,
+                (_, None) => {}
+                (Pass::Depth, Some(h)) => pixel[0] += h.distance,
+                (Pass::Normal, Some(h)) => add3(pixel, h.normal),
+                (Pass::Albedo, Some(h)) => add3(pixel, h.albedo),
+                (Pass::Position, Some(h)) => add3(pixel, h.position),
+                (Pass::ObjectId, Some(h)) if first => pixel[0] = h.object_id as f32,
+                (Pass::MaterialId, Some(h)) if first => pixel[0] = h.material_id as f32,
+                _ => {}
+            }
+        }
+    }
+
+    // Turns the sums into averages over the samples that hit something. Pixels that only
+    // saw the background keep zero, except for depth which becomes infinite.
+    pub fn resolve(&mut self) {
+        for (pass, data) in self.passes.iter().zip(self.data.iter_mut()) {
+            if pass.is_identifier() || *pass == Pass::SampleCount {
+                continue;
+            }
+
+            let n = pass.channels().len();
+            for (pixel, &hits) in data.chunks_mut(n).zip(self.hits.iter()) {
+                if hits == 0 {
+                    if *pass == Pass::Depth {
+                        pixel[0] = f32::INFINITY;
+                    }
+                    continue;
+                }
+                for value in pixel.iter_mut() {
+                    *value /= hits as f32;
+                }
+            }
+        }
+    }
+
+    // Adds a buffer rendered for the block with its top left corner at (x0, y0) into this one.
+    // Identifiers are kept from whichever buffer hit something first.
+    pub fn add_block(&mut self, other: &AovBuffers, x0: u32, y0: u32) {
+        for y in 0..other.height {
+            for x in 0..other.width {
+                let src = (y * other.width + x) as usize;
+                let dst = ((y + y0) * self.width + x + x0) as usize;
+                let first = self.hits[dst] == 0;
+                self.hits[dst] += other.hits[src];
+                for (pass, (data, other_data)) in self.passes.iter().zip(self.data.iter_mut().zip(other.data.iter())) {
+                    let n = pass.channels().len();
+                    let (to, from) = (&mut data[dst * n..(dst + 1) * n], &other_data[src * n..(src + 1) * n]);
+                    if !pass.is_identifier() {
+                        to.iter_mut().zip(from).for_each(|(a, b)| *a += b);
+                    } else if first && other.hits[src] > 0 {
+                        to.copy_from_slice(from);
+                    }
+                }
+            }
+        }
+    }
+
+    // Drops every pass not in `passes`, such as buffers only recorded for the denoiser
+    pub fn retain(&mut self, passes: &[Pass]) {
+        let mut kept = vec![];
+        for (pass, data) in self.passes.drain(..).zip(self.data.drain(..)) {
+            if passes.contains(&pass) {
+                kept.push((pass, data));
+            }
+        }
+        (self.passes, self.data) = kept.into_iter().unzip();
+    }
+
+    pub fn channel_data(&self, pass: Pass) -> Option<&[f32]> {
+        self.passes.iter().position(|p| *p == pass).map(|i| self.data[i].as_slice())
+    }
+
+    pub fn save(&self, output: AovOutput, output_file: &str, beauty: &[Vec3<f32>]) -> Result<Vec<PathBuf>> {
+        match output {
+            AovOutput::SeparateFiles => self.save_separate(output_file),
+            AovOutput::MultiLayerExr => {
+                let path = Path::new(output_file).with_extension("exr");
+                self.save_multi_layer(&path, beauty).map_err(|e| {
+                    RenderError::image(&path, image::ImageError::IoError(std::io::Error::other(e.to_string())))
+                })?;
+                Ok(vec![path])
+            }
+        }
+    }
+
+    fn save_separate(&self, output_file: &str) -> Result<Vec<PathBuf>> {
+        let mut written = vec![];
+        for (pass, data) in self.passes.iter().zip(self.data.iter()) {
+            let n = pass.channels().len();
+            let img = Rgb32FImage::from_fn(self.width, self.height, |x, y| {
+                let i = (y * self.width + x) as usize * n;
+                if n == 3 { Rgb([data[i], data[i + 1], data[i + 2]]) } else { Rgb([data[i]; 3]) }
+            });
+
+            let path = Path::new(output_file).with_extension(format!("{}.exr", pass.name()));
+            img.save_with_format(&path, image::ImageFormat::OpenExr).map_err(|e| RenderError::image(&path, e))?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+
+    fn save_multi_layer(&self, path: &Path, beauty: &[Vec3<f32>]) -> exr::error::Result<()> {
+        let size = Vec2(self.width as usize, self.height as usize);
+
+        let beauty_channels = vec![
+            AnyChannel::new("R", FlatSamples::F32(beauty.iter().map(|c| c.x).collect())),
+            AnyChannel::new("G", FlatSamples::F32(beauty.iter().map(|c| c.y).collect())),
+            AnyChannel::new("B", FlatSamples::F32(beauty.iter().map(|c| c.z).collect())),
+        ];
+        let mut layers = vec![Layer::new(size, LayerAttributes::named("beauty"), Encoding::FAST_LOSSLESS, AnyChannels::sort(SmallVec::from_vec(beauty_channels)))];
+
+        for (pass, data) in self.passes.iter().zip(self.data.iter()) {
+            let names = pass.channels();
+            let channels = names.iter().enumerate().map(|(c, name)| {
+                let samples = data.iter().skip(c).step_by(names.len()).copied().collect();
+                AnyChannel::new(*name, FlatSamples::F32(samples))
+            }).collect();
+            layers.push(Layer::new(size, LayerAttributes::named(pass.name()), Encoding::FAST_LOSSLESS, AnyChannels::sort(SmallVec::from_vec(channels))));
+        }
+
+        Image::from_layers(ImageAttributes::new(IntegerBounds::from_dimensions(size)), layers)
+            .write()
+            .to_file(path)
+    }
+}
+
+fn add3(pixel: &mut [f32], v: Vec3<f32>) {
+    pixel[0] += v.x;
+    pixel[1] += v.y;
+    pixel[2] += v.z;
+}
+
+// FNV-1a over the material's variant and parameters, truncated to 24 bits so that it is
+// stored exactly in a float channel. The parameters are hashed as f32 so that the ids are the
+// same at either precision.
+fn material_id(material: &Material) -> u32 {
+    let (variant, params): (u32, Vec<Real>) = match material {
+        Material::Lambertian { albedo } => (0, vec![albedo.x, albedo.y, albedo.z]),
+        Material::Metal { albedo, fuzz } => (1, vec![albedo.x, albedo.y, albedo.z, *fuzz]),
+        Material::Dielctric { ir } => (2, vec![*ir]),
+    };
+
+    let mut hash: u32 = 0x811c9dc5;
+    for word in std::iter::once(variant).chain(params.iter().map(|&p| to_f32(p).to_bits())) {
+        for byte in word.to_le_bytes() {
+            hash ^= byte as u32;
+            hash = hash.wrapping_mul(0x01000193);
+        }
+    }
+    hash & 0x00ff_ffff
+}
@@ -0,0 +1,108 @@
+use std::sync::Arc;
+use std::time::Instant;
+use crate::utils::error::Result;
+use crate::utils::framebuffer::Framebuffer;
+use crate::utils::real::Real;
+use crate::utils::renderer::{render, RenderSettings};
+use crate::utils::scene::Scene;
+use crate::utils::stats::RenderStats;
+use crate::utils::wavefront::Integrator;
+
+// One render of the benchmark at a given thread count
+pub struct BenchRun {
+    pub threads: u32,
+    pub seconds: f64,
+    pub stats: RenderStats,
+}
+
+impl BenchRun {
+    pub fn rays_per_second(&self) -> f64 {
+        self.stats.total_rays() as f64 / self.seconds
+    }
+}
+
+// Timings of the same render with 1, 2, 4, ... threads, up to the number asked for
+pub struct BenchReport {
+    pub scene: String,
+    pub image_width: u32,
+    pub image_height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: i32,
+    pub integrator: Integrator,
+    pub runs: Vec<BenchRun>,
+    // The last run's image, which every thread count renders the same
+    pub image: Framebuffer,
+}
+
+impl BenchReport {
+    // Time with one thread divided by time with `run.threads`
+    pub fn speedup(&self, run: &BenchRun) -> f64 {
+        self.runs.first().map_or(1.0, |single| single.seconds / run.seconds)
+    }
+
+    // Speedup per thread, 1.0 being perfect scaling
+    pub fn efficiency(&self, run: &BenchRun) -> f64 {
+        self.speedup(run) / run.threads as f64
+    }
+
+    pub fn print_table(&self) {
+        println!("{:>8} {:>10} {:>14} {:>14} {:>14} {:>12} {:>8} {:>10}", "threads", "seconds", "primary", "secondary", "shadow", "Mrays/s", "speedup", "efficiency");
+        for run in self.runs.iter() {
+            println!(
+                "{:>8} {:>10.3} {:>14} {:>14} {:>14} {:>12.2} {:>8.2} {:>10.2}",
+                run.threads, run.seconds, run.stats.primary_rays(), run.stats.secondary_rays(), run.stats.shadow_rays,
+                run.rays_per_second() / 1e6, self.speedup(run), self.efficiency(run)
+            );
+        }
+    }
+
+    // Written by hand to keep serde out of the dependencies for one small, flat document
+    pub fn to_json(&self) -> String {
+        let runs: Vec<String> = self.runs.iter().map(|run| {
+            format!(
+                "    {{\"threads\": {}, \"seconds\": {:.6}, \"primary_rays\": {}, \"secondary_rays\": {}, \"shadow_rays\": {}, \"rays_per_second\": {:.1}, \"speedup\": {:.4}, \"efficiency\": {:.4}}}",
+                run.threads, run.seconds, run.stats.primary_rays(), run.stats.secondary_rays(), run.stats.shadow_rays,
+                run.rays_per_second(), self.speedup(run), self.efficiency(run)
+            )
+        }).collect();
+
+        format!(
+            "{{\n  \"scene\": \"{}\",\n  \"image_width\": {},\n  \"image_height\": {},\n  \"samples_per_pixel\": {},\n  \"max_depth\": {},\n  \"integrator\": \"{:?}\",\n  \"precision\": \"{}\",\n  \"runs\": [\n{}\n  ]\n}}\n",
+            self.scene.replace('\\', "\\\\").replace('"', "\\\""), self.image_width, self.image_height,
+            self.samples_per_pixel, self.max_depth, self.integrator, std::any::type_name::<Real>(), runs.join(",\n")
+        )
+    }
+}
+
+// Thread counts to try: powers of two below `max_threads`, then `max_threads` itself
+pub fn thread_counts(max_threads: u32) -> Vec<u32> {
+    let mut counts: Vec<u32> = std::iter::successors(Some(1u32), |n| n.checked_mul(2)).take_while(|&n| n < max_threads).collect();
+    counts.push(max_threads.max(1));
+    counts
+}
+
+// Renders `scene` once per thread count, ignoring `settings.num_threads`. Nothing is written
+// to disk.
+pub fn render_bench(scene: Scene, name: &str, settings: &RenderSettings, max_threads: u32) -> Result<BenchReport> {
+    let scene = Arc::new(scene);
+    let mut image = Framebuffer::new(settings.image_width, settings.image_height);
+    let runs = thread_counts(max_threads).into_iter().map(|threads| {
+        let settings = RenderSettings { num_threads: threads, pool: None, ..settings.clone() };
+        let start = Instant::now();
+        let output = render(scene.clone(), &settings)?;
+        let seconds = start.elapsed().as_secs_f64();
+        image = output.image;
+        Ok(BenchRun { threads, seconds, stats: output.stats })
+    }).collect::<Result<_>>()?;
+
+    Ok(BenchReport {
+        scene: name.to_string(),
+        image_width: settings.image_width,
+        image_height: settings.image_height,
+        samples_per_pixel: settings.samples_per_pixel,
+        max_depth: settings.max_depth,
+        integrator: settings.integrator,
+        runs,
+        image,
+    })
+}
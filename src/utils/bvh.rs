@@ -0,0 +1,208 @@
+use crate::utils::geometry::{HitRecord, Hittable, Sphere, SphereBatch};
+use crate::utils::ray::Ray;
+use crate::utils::simd::{Realx4, RayPacket};
+use crate::utils::stats::count_bvh_node;
+use crate::utils::vector::Vec3;
+use crate::utils::real::{gamma, Real};
+
+// Spheres per leaf, intersected together by a `SphereBatch`
+const LEAF_SIZE: usize = 8;
+
+// Subtrees over at least this many spheres are built in parallel
+const PARALLEL_BUILD_SIZE: usize = 256;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vec3<Real>,
+    pub max: Vec3<Real>,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3<Real>, max: Vec3<Real>) -> Self {
+        Aabb { min, max }
+    }
+
+    pub fn around_sphere(sphere: &Sphere) -> Self {
+        // Hollow glass is made of spheres with negative radii
+        let r = sphere.radius.abs();
+        let extent = Vec3::new(r, r, r);
+        Aabb::new(sphere.center - extent, sphere.center + extent)
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        Aabb::new(
+            Vec3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            Vec3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+        )
+    }
+
+    pub fn centre(&self) -> Vec3<Real> {
+        (self.min + self.max) * 0.5
+    }
+
+    // Slab test: whether the ray passes through the box somewhere in (t_min, t_max)
+    pub fn hit(&self, r: &Ray, t_min: Real, t_max: Real) -> bool {
+        let (mut t0, mut t1) = (t_min, t_max);
+        for axis in 0..3 {
+            let inv_d = 1.0 / component(r.direction, axis);
+            let mut near = (component(self.min, axis) - component(r.origin, axis)) * inv_d;
+            let mut far = (component(self.max, axis) - component(r.origin, axis)) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut near, &mut far);
+            }
+            // Widened by its rounding error, so a ray grazing the box isn't culled
+            far *= 1.0 + 2.0 * gamma(3);
+            t0 = t0.max(near);
+            t1 = t1.min(far);
+            if t1 < t0 {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn component(v: Vec3<Real>, axis: usize) -> Real {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+enum Node {
+    Leaf {
+        bounds: Aabb,
+        batch: SphereBatch,
+        // Where each of the batch's spheres was in the list given to `Bvh::new`
+        ids: Vec<usize>,
+    },
+    Interior {
+        bounds: Aabb,
+        children: Box<[Node; 2]>,
+    },
+}
+
+impl Node {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+// A bounding volume hierarchy over spheres, so that a ray only tests the few near its path.
+// Object ids are the spheres' positions in the list it was built from, as with `SphereBatch`.
+pub struct Bvh {
+    root: Option<Node>,
+    len: usize,
+}
+
+impl Bvh {
+    // Large subtrees are built with `rayon::join`, so the build runs on whichever thread pool
+    // it is called from, e.g. the render's own inside `RenderPool::install`
+    pub fn new(spheres: Vec<Sphere>) -> Self {
+        let len = spheres.len();
+        let items: Vec<(usize, Sphere)> = spheres.into_iter().enumerate().collect();
+        Bvh { root: (!items.is_empty()).then(|| build(items)), len }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+// Splits at the median along the axis the centres are most spread out on
+fn build(mut items: Vec<(usize, Sphere)>) -> Node {
+    let boxes: Vec<Aabb> = items.iter().map(|(_, sphere)| Aabb::around_sphere(sphere)).collect();
+    let bounds = boxes.iter().skip(1).fold(boxes[0], |acc, b| acc.union(b));
+    if items.len() <= LEAF_SIZE {
+        let (ids, spheres) = items.into_iter().unzip();
+        return Node::Leaf { bounds, batch: SphereBatch::new(spheres), ids };
+    }
+
+    let centres = boxes.iter().map(|b| Aabb::new(b.centre(), b.centre())).reduce(|acc, b| acc.union(&b)).unwrap_or(bounds);
+    let extent = centres.max - centres.min;
+    let axis = if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+    items.sort_by(|(_, a), (_, b)| component(a.center, axis).total_cmp(&component(b.center, axis)));
+
+    let right = items.split_off(items.len() / 2);
+    let (left, right) = if items.len() + right.len() >= PARALLEL_BUILD_SIZE {
+        rayon::join(|| build(items), || build(right))
+    } else {
+        (build(items), build(right))
+    };
+    Node::Interior { bounds, children: Box::new([left, right]) }
+}
+
+fn hit_node(node: &Node, r: &Ray, t_min: Real, t_max: Real) -> Option<HitRecord> {
+    count_bvh_node();
+    if !node.bounds().hit(r, t_min, t_max) {
+        return None;
+    }
+
+    match node {
+        Node::Leaf { batch, ids, .. } => batch.hit(r, t_min, t_max).map(|mut rec| {
+            rec.object_id = ids[rec.object_id];
+            rec
+        }),
+        Node::Interior { children, .. } => {
+            // Anything the second child has must be closer than what the first one found
+            let first = hit_node(&children[0], r, t_min, t_max);
+            let t_max = first.as_ref().map_or(t_max, |rec| rec.t);
+            hit_node(&children[1], r, t_min, t_max).or(first)
+        }
+    }
+}
+
+// The packet version of `hit_node`: a node is entered if any lane's ray passes through its
+// box, and each lane keeps its own nearest hit so far
+fn hit_node_packet(node: &Node, packet: &RayPacket, t_min: Real, t_max: &mut [Real; 4], records: &mut [Option<HitRecord>; 4]) {
+    count_bvh_node();
+    if (0..Realx4::LANES).all(|lane| !node.bounds().hit(&packet.ray(lane), t_min, t_max[lane])) {
+        return;
+    }
+
+    match node {
+        Node::Leaf { batch, ids, .. } => {
+            for (lane, rec) in batch.hit_packet(packet, t_min, Realx4::from_array(*t_max)).into_iter().enumerate() {
+                if let Some(mut rec) = rec {
+                    rec.object_id = ids[rec.object_id];
+                    t_max[lane] = rec.t;
+                    records[lane] = Some(rec);
+                }
+            }
+        }
+        Node::Interior { children, .. } => {
+            for child in children.iter() {
+                hit_node_packet(child, packet, t_min, t_max, records);
+            }
+        }
+    }
+}
+
+impl Hittable for Bvh {
+    fn hit(&self, r: &Ray, t_min: Real, t_max: Real) -> Option<HitRecord> {
+        self.root.as_ref().and_then(|root| hit_node(root, r, t_min, t_max))
+    }
+
+    fn hit_packet(&self, packet: &RayPacket, t_min: Real, t_max: Realx4) -> [Option<HitRecord>; 4] {
+        let mut records: [Option<HitRecord>; 4] = Default::default();
+        if let Some(root) = &self.root {
+            hit_node_packet(root, packet, t_min, &mut t_max.to_array(), &mut records);
+        }
+        records
+    }
+
+    fn packets_pay_off(&self) -> bool {
+        true
+    }
+
+    fn object_count(&self) -> usize {
+        self.len
+    }
+}
@@ -1,58 +1,509 @@
+use crate::utils::real::consts::PI;
+use crate::utils::geometry::Hittable;
+use crate::utils::lens::Aperture;
 use crate::utils::ray::Ray;
-use crate::utils::vector::Vec3;
+use crate::utils::sampler::Sampler;
+use crate::utils::sky::DEFAULT_INTENSITY;
+use crate::utils::vector::{Dot, Vec3};
+use crate::utils::real::Real;
 
-#[derive(Clone, Copy)]
-pub struct Camera {
-    origin: Vec3<f32>,
-    pub upper_left_corner: Vec3<f32>,
-    pub horizontal: Vec3<f32>,
-    pub vertical: Vec3<f32>,
-    u: Vec3<f32>,
-    v: Vec3<f32>,
-    lens_radius: f32,
+// Every camera maps the image position (u, v), with u running left to right and v top to
+// bottom over [0, 1], to a ray. Cameras that do not cover the whole image, such as a
+// circular fisheye, return `None` outside of it and those samples are black.
+#[derive(Clone)]
+pub enum Camera {
+    Perspective(PerspectiveCamera),
+    Orthographic(OrthographicCamera),
+    Equirectangular(EquirectangularCamera),
+    Fisheye(FisheyeCamera),
+    OmniStereo(OmniStereoCamera),
 }
 
-
 impl Camera {
+    // Thin lens perspective camera looking from `origin` towards the point `look_at`, with a
+    // vertical field of view in degrees and an aperture diameter in world units. See
+    // `CameraBuilder` for the other ways to set these up.
+    pub fn new(
+        origin: Vec3<Real>,
+        look_at: Vec3<Real>,
+        up: Vec3<Real>, vfov: Real,
+        aspect_ratio: Real,
+        aperture: Real,
+        focus_dist: Real
+    ) -> Self {
+        Camera::Perspective(PerspectiveCamera::new(origin, look_at, up, vfov, aspect_ratio, aperture, focus_dist))
+    }
+
+    pub fn get_ray(&self, u: Real, v: Real, sampler: &mut dyn Sampler) -> Option<Ray> {
+        match self {
+            Camera::Perspective(camera) => camera.get_ray(u, v, sampler),
+            Camera::Orthographic(camera) => Some(camera.get_ray(u, v)),
+            Camera::Equirectangular(camera) => Some(camera.get_ray(u, v)),
+            Camera::Fisheye(camera) => camera.get_ray(u, v),
+            Camera::OmniStereo(camera) => Some(camera.get_ray(u, v)),
+        }
+    }
+
+    // Multiplier applied to the rendered radiance before it is written out
+    pub fn exposure(&self) -> Real {
+        match self {
+            Camera::Perspective(camera) => camera.exposure,
+            _ => 1.0,
+        }
+    }
+}
+
+// Right, up and backward unit vectors of a camera at `origin` looking at `look_at`
+fn look_at_basis(origin: Vec3<Real>, look_at: Vec3<Real>, up: Vec3<Real>) -> (Vec3<Real>, Vec3<Real>, Vec3<Real>) {
+    let w = (origin - look_at).normalise();
+    let u = up.cross(w).normalise();
+    let v = w.cross(u);
+    (u, v, w)
+}
+
+// Thin lens camera. The lens can have a polygonal or image based aperture, an anamorphic
+// squeeze, a shifted viewport and a tilted plane of focus, and its barrel can clip the
+// aperture towards the corners of the frame.
+#[derive(Clone)]
+pub struct PerspectiveCamera {
+    origin: Vec3<Real>,
+    u: Vec3<Real>,
+    v: Vec3<Real>,
+    w: Vec3<Real>,
+    // Size of the viewport one unit in front of the camera
+    viewport_width: Real,
+    viewport_height: Real,
+    focus_dist: Real,
+    // Normal of the plane of focus, `w` unless the lens is tilted
+    focus_normal: Vec3<Real>,
+    lens_radius: Real,
+    aperture: Aperture,
+    squeeze: Real,
+    shift: (Real, Real),
+    vignetting: Real,
+    exposure: Real,
+}
+
+impl PerspectiveCamera {
     pub fn new(
-        origin: Vec3<f32>,
-        direction: Vec3<f32>,
-        up: Vec3<f32>, vfov: f32,
-        aspect_ratio: f32,
-        aperture: f32,
-        focus_dist: f32
+        origin: Vec3<Real>,
+        look_at: Vec3<Real>,
+        up: Vec3<Real>, vfov: Real,
+        aspect_ratio: Real,
+        aperture: Real,
+        focus_dist: Real
     ) -> Self {
         let theta = vfov.to_radians();
         let viewport_height = 2.0 * (theta / 2.0).tan();
         let viewport_width = aspect_ratio * viewport_height;
 
-        let w = (origin - direction).normalise();
-        let u = up.cross(w).normalise();
-        let v = w.cross(u);
+        let (u, v, w) = look_at_basis(origin, look_at, up);
+
+        PerspectiveCamera {
+            origin,
+            u,
+            v,
+            w,
+            viewport_width,
+            viewport_height,
+            focus_dist,
+            focus_normal: w,
+            lens_radius: aperture / 2.0,
+            aperture: Aperture::Circle,
+            squeeze: 1.0,
+            shift: (0.0, 0.0),
+            vignetting: 0.0,
+            exposure: 1.0,
+        }
+    }
+
+    pub fn with_exposure(mut self, exposure: Real) -> Self {
+        self.exposure = exposure;
+        self
+    }
+
+    pub fn with_aperture(mut self, aperture: Aperture) -> Self {
+        self.aperture = aperture;
+        self
+    }
+
+    // Anamorphic lenses squeeze the aperture horizontally, so a squeeze of 2 makes out of
+    // focus highlights ovals twice as tall as they are wide
+    pub fn with_anamorphic_squeeze(mut self, squeeze: Real) -> Self {
+        self.squeeze = squeeze;
+        self
+    }
+
+    // Moves the viewport parallel to the sensor by fractions of its width and height, which
+    // keeps vertical lines parallel when framing tall buildings
+    pub fn with_shift(mut self, x: Real, y: Real) -> Self {
+        self.shift = (x, y);
+        self
+    }
 
-        let horizontal = u * viewport_width * focus_dist;
-        let vertical = v * -viewport_height * focus_dist;
-        let upper_left_corner = origin - horizontal / 2.0 - vertical / 2.0 - w * focus_dist;
+    // Tilts the plane of focus by `tilt` degrees about the horizontal axis and `swing` degrees
+    // about the vertical one. Positive tilt brings the focus closer at the bottom of the frame,
+    // keeping a receding ground plane sharp.
+    pub fn with_tilt(mut self, tilt: Real, swing: Real) -> Self {
+        let (tilt, swing) = (tilt.to_radians(), swing.to_radians());
+        self.focus_normal = (self.w * (tilt.cos() * swing.cos()) + self.v * tilt.sin() + self.u * (tilt.cos() * swing.sin())).normalise();
+        self
+    }
 
-        Camera {
+    // Optical vignetting from a lens barrel `length` aperture radii long in front of the
+    // aperture. Off axis, the barrel's opening covers part of the aperture, darkening the
+    // corners and clipping bokeh into cat's eye shapes.
+    pub fn with_vignetting(mut self, length: Real) -> Self {
+        self.vignetting = length;
+        self
+    }
+
+    // Autofocus: moves the plane of focus onto whatever is seen through image position (u, v)
+    // and returns its distance, or `None` if the ray hits nothing
+    pub fn focus_on(&mut self, world: &dyn Hittable, u: Real, v: Real) -> Option<Real> {
+        let ray = Ray::new(self.origin, self.pinhole_direction(u, v));
+        let rec = world.hit(&ray, 0.0, Real::INFINITY)?;
+        let to_hit = rec.p - self.origin;
+        self.focus_dist = -self.focus_normal.dot(to_hit) / self.focus_normal.dot(self.w);
+        Some(self.focus_dist)
+    }
+
+    pub fn get_ray(&self, u: Real, v: Real, sampler: &mut dyn Sampler) -> Option<Ray> {
+        let (x, y) = self.aperture.sample(sampler.get_2d());
+        let offset = (self.u * (x / self.squeeze) + self.v * y) * self.lens_radius;
+        let origin = self.origin + offset;
+
+        // Every ray through the lens meets the pinhole ray on the plane of focus
+        let pinhole = self.pinhole_direction(u, v);
+        let facing = self.focus_normal.dot(pinhole);
+        let direction = if facing < 0.0 {
+            let t = -self.focus_dist * self.focus_normal.dot(self.w) / facing;
+            self.origin + pinhole * t - origin
+        } else {
+            // A tilted plane of focus that this ray never reaches
+            pinhole
+        };
+
+        if self.vignetting > 0.0 && self.lens_radius > 0.0 {
+            let length = self.vignetting * self.lens_radius;
+            let at_opening = offset + direction * (length / -direction.dot(self.w)) + self.w * length;
+            if at_opening.length_squared() > self.lens_radius * self.lens_radius {
+                return None;
+            }
+        }
+
+        Some(Ray::new(origin, direction))
+    }
+
+    fn pinhole_direction(&self, u: Real, v: Real) -> Vec3<Real> {
+        let x = (u - 0.5 + self.shift.0) * self.viewport_width;
+        let y = (0.5 - v + self.shift.1) * self.viewport_height;
+        self.u * x + self.v * y - self.w
+    }
+}
+
+// Luminance in cd/m^2 of one unit of scene radiance, following the sky model's calibration
+const NITS_PER_UNIT: Real = 1000.0 / DEFAULT_INTENSITY;
+
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    Point(Vec3<Real>),
+    Direction(Vec3<Real>),
+}
+
+// Sets up a perspective camera either the way `Camera::new` does, with a field of view and
+// an aperture in world units, or like a real camera, with a focal length and f-number on a
+// sensor of a given size. The two can be mixed, e.g. a field of view with an f-number.
+// Lengths on the camera side are in millimetres and the scene is assumed to be modelled in
+// metres unless `with_units_per_metre` says otherwise.
+//
+// When a shutter speed or ISO is given, the image is exposed like a photograph taken with
+// those settings (sunny 16 gives a well exposed daylight sky). Otherwise it is left as is.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraBuilder {
+    origin: Vec3<Real>,
+    target: Target,
+    up: Vec3<Real>,
+    aspect_ratio: Real,
+    vfov: Option<Real>,
+    focal_length: Option<Real>,
+    sensor_width: Real,
+    aperture: Option<Real>,
+    f_number: Option<Real>,
+    focus_dist: Option<Real>,
+    units_per_metre: Real,
+    shutter_speed: Option<Real>,
+    iso: Option<Real>,
+}
+
+impl CameraBuilder {
+    pub fn new(origin: Vec3<Real>) -> Self {
+        CameraBuilder {
             origin,
-            upper_left_corner,
+            target: Target::Direction(Vec3::new(0.0, 0.0, -1.0)),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            aspect_ratio: 16.0 / 9.0,
+            vfov: None,
+            focal_length: None,
+            sensor_width: 36.0,
+            aperture: None,
+            f_number: None,
+            focus_dist: None,
+            units_per_metre: 1.0,
+            shutter_speed: None,
+            iso: None,
+        }
+    }
+
+    pub fn with_look_at(mut self, point: Vec3<Real>) -> Self {
+        self.target = Target::Point(point);
+        self
+    }
+
+    pub fn with_look_direction(mut self, direction: Vec3<Real>) -> Self {
+        self.target = Target::Direction(direction);
+        self
+    }
+
+    pub fn with_up(mut self, up: Vec3<Real>) -> Self {
+        self.up = up;
+        self
+    }
+
+    pub fn with_aspect_ratio(mut self, aspect_ratio: Real) -> Self {
+        self.aspect_ratio = aspect_ratio;
+        self
+    }
+
+    // Vertical field of view in degrees
+    pub fn with_vfov(mut self, vfov: Real) -> Self {
+        self.vfov = Some(vfov);
+        self.focal_length = None;
+        self
+    }
+
+    // Focal length in millimetres, which gives the field of view together with the sensor size
+    pub fn with_focal_length(mut self, focal_length: Real) -> Self {
+        self.focal_length = Some(focal_length);
+        self.vfov = None;
+        self
+    }
+
+    // Sensor width in millimetres, 36 for full frame. The height follows from the aspect ratio.
+    pub fn with_sensor_width(mut self, sensor_width: Real) -> Self {
+        self.sensor_width = sensor_width;
+        self
+    }
+
+    // Aperture diameter in world units, 0 for a pinhole
+    pub fn with_aperture(mut self, aperture: Real) -> Self {
+        self.aperture = Some(aperture);
+        self.f_number = None;
+        self
+    }
+
+    pub fn with_f_number(mut self, f_number: Real) -> Self {
+        self.f_number = Some(f_number);
+        self.aperture = None;
+        self
+    }
+
+    // Defaults to the distance to the look-at point, or 1 when given a direction
+    pub fn with_focus_distance(mut self, focus_dist: Real) -> Self {
+        self.focus_dist = Some(focus_dist);
+        self
+    }
+
+    pub fn with_units_per_metre(mut self, units_per_metre: Real) -> Self {
+        self.units_per_metre = units_per_metre;
+        self
+    }
+
+    // Exposure time in seconds, e.g. 1.0 / 125.0
+    pub fn with_shutter_speed(mut self, seconds: Real) -> Self {
+        self.shutter_speed = Some(seconds);
+        self
+    }
+
+    pub fn with_iso(mut self, iso: Real) -> Self {
+        self.iso = Some(iso);
+        self
+    }
+
+    pub fn build(&self) -> Camera {
+        Camera::Perspective(self.build_perspective())
+    }
+
+    pub fn build_perspective(&self) -> PerspectiveCamera {
+        let look_at = match self.target {
+            Target::Point(point) => point,
+            Target::Direction(direction) => self.origin + direction,
+        };
+        let focus_dist = self.focus_dist.unwrap_or_else(|| match self.target {
+            Target::Point(point) => (point - self.origin).length(),
+            Target::Direction(_) => 1.0,
+        });
+
+        let focal_length = self.focal_length();
+        let vfov = self.vfov.unwrap_or_else(|| 2.0 * (self.sensor_height() / (2.0 * focal_length)).atan().to_degrees());
+        let aperture = self.aperture.unwrap_or_else(|| {
+            self.f_number.map_or(0.0, |n| focal_length / n / 1000.0 * self.units_per_metre)
+        });
+
+        PerspectiveCamera::new(self.origin, look_at, self.up, vfov, self.aspect_ratio, aperture, focus_dist)
+            .with_exposure(self.exposure())
+    }
+
+    // Photographic exposure H = t * ISO / (K * N^2) with the usual calibration constant
+    // K = 120, i.e. the reciprocal of the saturation based 1.2 * 2^EV100. A pinhole is
+    // metered as f/16.
+    pub fn exposure(&self) -> Real {
+        if self.shutter_speed.is_none() && self.iso.is_none() {
+            return 1.0;
+        }
+        let t = self.shutter_speed.unwrap_or(1.0 / 100.0);
+        let iso = self.iso.unwrap_or(100.0);
+        let n = self.f_number.unwrap_or_else(|| match self.aperture {
+            Some(aperture) if aperture > 0.0 => self.focal_length() / (aperture / self.units_per_metre * 1000.0),
+            _ => 16.0,
+        });
+        t * iso / (120.0 * n * n) * NITS_PER_UNIT
+    }
+
+    fn sensor_height(&self) -> Real {
+        self.sensor_width / self.aspect_ratio
+    }
+
+    fn focal_length(&self) -> Real {
+        self.focal_length.unwrap_or_else(|| {
+            let vfov = self.vfov.unwrap_or(90.0).to_radians();
+            self.sensor_height() / (2.0 * (vfov / 2.0).tan())
+        })
+    }
+}
+
+// Parallel rays through a `view_height` world units tall window centred on `origin`
+#[derive(Clone, Copy)]
+pub struct OrthographicCamera {
+    upper_left_corner: Vec3<Real>,
+    horizontal: Vec3<Real>,
+    vertical: Vec3<Real>,
+    direction: Vec3<Real>,
+}
+
+impl OrthographicCamera {
+    pub fn new(origin: Vec3<Real>, look_at: Vec3<Real>, up: Vec3<Real>, view_height: Real, aspect_ratio: Real) -> Self {
+        let (u, v, w) = look_at_basis(origin, look_at, up);
+        let horizontal = u * view_height * aspect_ratio;
+        let vertical = v * -view_height;
+
+        OrthographicCamera {
+            upper_left_corner: origin - horizontal / 2.0 - vertical / 2.0,
             horizontal,
             vertical,
-            u,
-            v,
-            lens_radius: aperture / 2.0
+            direction: -w,
         }
     }
 
-    pub fn get_ray(&self, u: f32, v: f32) -> Ray {
-        let rd = Vec3::random_in_unit_disk() * self.lens_radius;
-        let offset = self.u * rd.x + self.v * rd.y;
-        Ray {
-            origin: self.origin + offset,
-            direction: self.upper_left_corner + self.horizontal * u + self.vertical * v - self.origin - offset
+    pub fn get_ray(&self, u: Real, v: Real) -> Ray {
+        Ray::new(self.upper_left_corner + self.horizontal * u + self.vertical * v, self.direction)
+    }
+}
+
+// The full sphere of directions around `origin`: longitude runs across the image with
+// `look_at` in the centre, and latitude from straight up at the top to straight down at the
+// bottom. Rendered at a 2:1 aspect ratio this is an environment map or a mono VR panorama.
+#[derive(Clone, Copy)]
+pub struct EquirectangularCamera {
+    origin: Vec3<Real>,
+    right: Vec3<Real>,
+    up: Vec3<Real>,
+    forward: Vec3<Real>,
+}
+
+impl EquirectangularCamera {
+    pub fn new(origin: Vec3<Real>, look_at: Vec3<Real>, up: Vec3<Real>) -> Self {
+        let (u, v, w) = look_at_basis(origin, look_at, up);
+        EquirectangularCamera { origin, right: u, up: v, forward: -w }
+    }
+
+    pub fn get_ray(&self, u: Real, v: Real) -> Ray {
+        let (phi, theta) = ((u - 0.5) * 2.0 * PI, v * PI);
+        let horizontal = self.forward * phi.cos() + self.right * phi.sin();
+        Ray::new(self.origin, horizontal * theta.sin() + self.up * theta.cos())
+    }
+}
+
+// Equidistant fisheye: the angle from the view direction grows linearly with the distance
+// from the image centre, reaching `fov / 2` at the edge of a circle inscribed in the image
+// height. Fields of view above 180 degrees see behind the camera.
+#[derive(Clone, Copy)]
+pub struct FisheyeCamera {
+    origin: Vec3<Real>,
+    right: Vec3<Real>,
+    up: Vec3<Real>,
+    forward: Vec3<Real>,
+    half_fov: Real,
+    aspect_ratio: Real,
+}
+
+impl FisheyeCamera {
+    pub fn new(origin: Vec3<Real>, look_at: Vec3<Real>, up: Vec3<Real>, fov: Real, aspect_ratio: Real) -> Self {
+        let (u, v, w) = look_at_basis(origin, look_at, up);
+        FisheyeCamera { origin, right: u, up: v, forward: -w, half_fov: fov.to_radians() / 2.0, aspect_ratio }
+    }
+
+    pub fn get_ray(&self, u: Real, v: Real) -> Option<Ray> {
+        let x = (2.0 * u - 1.0) * self.aspect_ratio;
+        let y = 1.0 - 2.0 * v;
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+            return None;
         }
+
+        let theta = r * self.half_fov;
+        let phi = y.atan2(x);
+        let sideways = self.right * phi.cos() + self.up * phi.sin();
+        Some(Ray::new(self.origin, self.forward * theta.cos() + sideways * theta.sin()))
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StereoEye {
+    Left,
+    Right,
+    // Left eye panorama in the top half of the image and right eye in the bottom half, the
+    // usual layout for stereo VR video. Render at a 1:1 aspect ratio.
+    TopBottom,
+}
 
+// Omni-directional stereo (ODS): an equirectangular panorama where every ray starts on a
+// circle of diameter `ipd` around `origin`, offset sideways from its direction the way an eye
+// would be when turning the head to look that way
+#[derive(Clone, Copy)]
+pub struct OmniStereoCamera {
+    panorama: EquirectangularCamera,
+    // Interpupillary distance in world units, around 0.064 for a scene modelled in metres
+    ipd: Real,
+    eye: StereoEye,
+}
+
+impl OmniStereoCamera {
+    pub fn new(origin: Vec3<Real>, look_at: Vec3<Real>, up: Vec3<Real>, ipd: Real, eye: StereoEye) -> Self {
+        OmniStereoCamera { panorama: EquirectangularCamera::new(origin, look_at, up), ipd, eye }
+    }
+
+    pub fn get_ray(&self, u: Real, v: Real) -> Ray {
+        let (v, side) = match self.eye {
+            StereoEye::Left => (v, -1.0),
+            StereoEye::Right => (v, 1.0),
+            StereoEye::TopBottom if v < 0.5 => (v * 2.0, -1.0),
+            StereoEye::TopBottom => (v * 2.0 - 1.0, 1.0),
+        };
+
+        let ray = self.panorama.get_ray(u, v);
+        let phi = (u - 0.5) * 2.0 * PI;
+        let eye_offset = self.panorama.right * phi.cos() - self.panorama.forward * phi.sin();
+        Ray::new(ray.origin + eye_offset * (side * self.ipd / 2.0), ray.direction)
+    }
+}
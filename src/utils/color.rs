@@ -1,5 +1,4 @@
 use crate::utils::vector::Vec3;
-use num::clamp; 
 
 #[derive(Debug, Clone, Copy)]
 pub struct Color {
@@ -33,7 +32,7 @@ impl Color {
         }
     }
 
-    pub fn to_rgb(&self) -> [u8; 3] {
+    pub fn to_rgb(self) -> [u8; 3] {
         [self.r(), self.g(), self.b()]
     }
 
@@ -0,0 +1,98 @@
+use crate::utils::vector::{Dot, Vec3};
+
+// Edge-avoiding a-trous wavelet filter (Dammertz et al. 2010) guided by first-hit albedo
+// and normal buffers. Each iteration applies a 5x5 B3-spline kernel with holes, doubling the
+// spacing between taps, and weights every tap by how similar it is to the centre pixel.
+#[derive(Debug, Clone, Copy)]
+pub struct DenoiseSettings {
+    pub iterations: u32,
+    // Tolerance for differences in demodulated color, halved every iteration
+    pub color_sigma: f32,
+    pub normal_sigma: f32,
+    pub albedo_sigma: f32,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        DenoiseSettings {
+            iterations: 5,
+            color_sigma: 1.0,
+            normal_sigma: 0.3,
+            albedo_sigma: 0.1,
+        }
+    }
+}
+
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// `albedo` and `normal` hold three floats per pixel, as produced by the AOV passes
+pub fn denoise(color: &[Vec3<f32>], albedo: &[f32], normal: &[f32], width: u32, height: u32, settings: &DenoiseSettings) -> Vec<Vec3<f32>> {
+    let albedo: Vec<Vec3<f32>> = albedo.chunks(3).map(|a| Vec3::new(a[0], a[1], a[2])).collect();
+    let normal: Vec<Vec3<f32>> = normal.chunks(3).map(|n| Vec3::new(n[0], n[1], n[2])).collect();
+
+    // Filter irradiance rather than radiance so that texture detail is not blurred away
+    let mut irradiance: Vec<Vec3<f32>> = color.iter().zip(albedo.iter()).map(|(c, a)| demodulate(*c, *a)).collect();
+    let mut filtered = irradiance.clone();
+
+    let (w, h) = (width as i32, height as i32);
+    for iteration in 0..settings.iterations {
+        let step = 1 << iteration;
+        let color_sigma = settings.color_sigma / (1 << iteration) as f32;
+
+        for y in 0..h {
+            for x in 0..w {
+                let p = (y * w + x) as usize;
+                let mut sum = Vec3::new(0.0, 0.0, 0.0);
+                let mut weight_sum = 0.0;
+
+                for (j, ky) in KERNEL.iter().enumerate() {
+                    let qy = y + (j as i32 - 2) * step;
+                    if qy < 0 || qy >= h {
+                        continue;
+                    }
+                    for (i, kx) in KERNEL.iter().enumerate() {
+                        let qx = x + (i as i32 - 2) * step;
+                        if qx < 0 || qx >= w {
+                            continue;
+                        }
+                        let q = (qy * w + qx) as usize;
+
+                        let dc = irradiance[p] - irradiance[q];
+                        let dn = normal[p] - normal[q];
+                        let da = albedo[p] - albedo[q];
+                        let weight = kx * ky
+                            * (-dc.dot(dc) / (color_sigma * color_sigma)).exp()
+                            * (-dn.dot(dn) / (settings.normal_sigma * settings.normal_sigma)).exp()
+                            * (-da.dot(da) / (settings.albedo_sigma * settings.albedo_sigma)).exp();
+
+                        sum = sum + irradiance[q] * weight;
+                        weight_sum += weight;
+                    }
+                }
+
+                filtered[p] = if weight_sum > 0.0 { sum / weight_sum } else { irradiance[p] };
+            }
+        }
+
+        std::mem::swap(&mut irradiance, &mut filtered);
+    }
+
+    irradiance.iter().zip(albedo.iter()).map(|(e, a)| remodulate(*e, *a)).collect()
+}
+
+fn demodulate(color: Vec3<f32>, albedo: Vec3<f32>) -> Vec3<f32> {
+    Vec3::new(safe_div(color.x, albedo.x), safe_div(color.y, albedo.y), safe_div(color.z, albedo.z))
+}
+
+fn remodulate(irradiance: Vec3<f32>, albedo: Vec3<f32>) -> Vec3<f32> {
+    Vec3::new(
+        if albedo.x > 1e-3 { irradiance.x * albedo.x } else { irradiance.x },
+        if albedo.y > 1e-3 { irradiance.y * albedo.y } else { irradiance.y },
+        if albedo.z > 1e-3 { irradiance.z * albedo.z } else { irradiance.z },
+    )
+}
+
+// Background pixels and black surfaces have no albedo to divide out
+fn safe_div(value: f32, albedo: f32) -> f32 {
+    if albedo > 1e-3 { value / albedo } else { value }
+}
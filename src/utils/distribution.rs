@@ -0,0 +1,99 @@
+use crate::utils::real::Real;
+// Piecewise-constant distributions used to importance sample tabulated functions such as
+// environment maps. See PBRT 3rd edition, section 13.6.7.
+
+#[derive(Clone)]
+pub struct Distribution1D {
+    func: Vec<Real>,
+    cdf: Vec<Real>,
+    func_int: Real,
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<Real>) -> Self {
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 1..=n {
+            cdf[i] = cdf[i - 1] + func[i - 1].abs() / n as Real;
+        }
+
+        let func_int = cdf[n];
+        if func_int == 0.0 {
+            // Degenerate function: fall back to a uniform distribution
+            for (i, c) in cdf.iter_mut().enumerate().skip(1) {
+                *c = i as Real / n as Real;
+            }
+        } else {
+            for c in cdf.iter_mut().skip(1) {
+                *c /= func_int;
+            }
+        }
+
+        Distribution1D { func, cdf, func_int }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn integral(&self) -> Real {
+        self.func_int
+    }
+
+    // Returns the sampled value in [0, 1), its density and the index of the segment it fell in
+    pub fn sample_continuous(&self, u: Real) -> (Real, Real, usize) {
+        let offset = self.cdf.partition_point(|&c| c <= u).saturating_sub(1).min(self.count() - 1);
+
+        let mut du = u - self.cdf[offset];
+        let segment = self.cdf[offset + 1] - self.cdf[offset];
+        if segment > 0.0 {
+            du /= segment;
+        }
+
+        let pdf = if self.func_int > 0.0 { self.func[offset] / self.func_int } else { 1.0 };
+        (((offset as Real) + du) / self.count() as Real, pdf, offset)
+    }
+
+    pub fn pdf(&self, index: usize) -> Real {
+        if self.func_int > 0.0 { self.func[index] / self.func_int } else { 1.0 }
+    }
+}
+
+// Samples (u, v) proportionally to a function tabulated on a `width` x `height` grid, by
+// first choosing a row from the marginal distribution and then a column within that row.
+#[derive(Clone)]
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[Real], width: usize, height: usize) -> Self {
+        let conditional: Vec<Distribution1D> = func
+            .chunks(width)
+            .take(height)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|c| c.integral()).collect());
+
+        Distribution2D { conditional, marginal }
+    }
+
+    pub fn sample_continuous(&self, u: (Real, Real)) -> ((Real, Real), Real) {
+        let (v, pdf_v, row) = self.marginal.sample_continuous(u.1);
+        let (u, pdf_u, _) = self.conditional[row].sample_continuous(u.0);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: Real, v: Real) -> Real {
+        let width = self.conditional[0].count();
+        let height = self.marginal.count();
+        let column = ((u * width as Real) as usize).min(width - 1);
+        let row = ((v * height as Real) as usize).min(height - 1);
+
+        if self.marginal.integral() == 0.0 {
+            return 1.0;
+        }
+        self.conditional[row].func[column] / self.marginal.integral()
+    }
+}
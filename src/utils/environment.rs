@@ -0,0 +1,211 @@
+use crate::utils::real::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use image::codecs::hdr::HdrDecoder;
+use image::ImageResult;
+use crate::utils::distribution::Distribution2D;
+use crate::utils::error::{RenderError, Result};
+use crate::utils::sky::Sky;
+use crate::utils::vector::Vec3;
+use crate::utils::real::Real;
+
+// Radiance arriving from infinitely far away, seen by every ray that escapes the scene
+#[derive(Clone)]
+// The sky's tables double in size in the f64 build; a scene only has the one environment
+#[cfg_attr(feature = "f64", allow(clippy::large_enum_variant))]
+pub enum Environment {
+    Constant(Vec3<Real>),
+    Gradient {
+        bottom: Vec3<Real>,
+        top: Vec3<Real>,
+    },
+    Image(EnvironmentMap),
+    Sky(Sky),
+}
+
+impl Default for Environment {
+    // The white-to-blue sky the renderer has always used
+    fn default() -> Self {
+        Environment::Gradient {
+            bottom: Vec3::new(1.0, 1.0, 1.0),
+            top: Vec3::new(0.5, 0.7, 1.0),
+        }
+    }
+}
+
+impl Environment {
+    pub fn radiance(&self, direction: Vec3<Real>) -> Vec3<Real> {
+        match self {
+            Environment::Constant(color) => *color,
+            Environment::Gradient { bottom, top } => {
+                let unit_direction = direction.normalise();
+                let t = 0.5 * (unit_direction.y + 1.0);
+                *bottom * (1.0 - t) + *top * t
+            }
+            Environment::Image(map) => map.radiance(direction),
+            Environment::Sky(sky) => sky.radiance(direction),
+        }
+    }
+
+    // Whether directions can be drawn from this environment for next event estimation
+    pub fn is_sampled(&self) -> bool {
+        match self {
+            Environment::Image(map) => map.distribution.is_some(),
+            Environment::Sky(_) => true,
+            _ => false,
+        }
+    }
+
+    // Returns a direction towards the environment, the radiance arriving from it and its solid angle density
+    pub fn sample(&self, u: (Real, Real)) -> Option<(Vec3<Real>, Vec3<Real>, Real)> {
+        match self {
+            Environment::Image(map) => map.sample(u),
+            Environment::Sky(sky) => sky.sample(u),
+            _ => None,
+        }
+    }
+
+    pub fn pdf(&self, direction: Vec3<Real>) -> Real {
+        match self {
+            Environment::Image(map) => map.pdf(direction),
+            Environment::Sky(sky) => sky.pdf(direction),
+            _ => 0.0,
+        }
+    }
+}
+
+// Equirectangular (latitude-longitude) radiance map with +y up
+#[derive(Clone)]
+pub struct EnvironmentMap {
+    width: u32,
+    height: u32,
+    pixels: Vec<Vec3<Real>>,
+    rotation: Real,
+    intensity: Real,
+    distribution: Option<Distribution2D>,
+}
+
+impl EnvironmentMap {
+    // Loads a Radiance .hdr file, or any other format the image crate can decode such as OpenEXR
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        EnvironmentMap::decode(path).map_err(|e| RenderError::Scene(format!("environment map {}: {}", path.display(), e)))
+    }
+
+    fn decode(path: &Path) -> ImageResult<Self> {
+        let is_radiance = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("hdr"));
+        if is_radiance {
+            // The generic loader tone maps Radiance files down to 8 bits, so decode them directly
+            let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+            let meta = decoder.metadata();
+            let pixels = decoder.read_image_hdr()?.iter().map(|p| Vec3::new(p[0], p[1], p[2]).to_real()).collect();
+            return Ok(EnvironmentMap::from_pixels(meta.width, meta.height, pixels));
+        }
+
+        let img = image::open(path)?.into_rgb32f();
+        let pixels = img.pixels().map(|p| Vec3::new(p[0], p[1], p[2]).to_real()).collect();
+        Ok(EnvironmentMap::from_pixels(img.width(), img.height(), pixels))
+    }
+
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<Vec3<Real>>) -> Self {
+        assert_eq!(pixels.len(), (width * height) as usize, "environment map size mismatch");
+
+        let mut map = EnvironmentMap {
+            width,
+            height,
+            pixels,
+            rotation: 0.0,
+            intensity: 1.0,
+            distribution: None,
+        };
+        map.distribution = Some(map.build_distribution());
+        map
+    }
+
+    // Rotates the map about the vertical axis
+    pub fn with_rotation(mut self, degrees: Real) -> Self {
+        self.rotation = degrees.to_radians();
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: Real) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    // Disabling importance sampling leaves the map to be found by scattered rays alone
+    pub fn with_importance_sampling(mut self, enabled: bool) -> Self {
+        self.distribution = if enabled { Some(self.build_distribution()) } else { None };
+        self
+    }
+
+    // Weight each texel by its luminance and the solid angle it covers
+    fn build_distribution(&self) -> Distribution2D {
+        let func: Vec<Real> = self.pixels.iter().enumerate().map(|(i, p)| {
+            let row = i as u32 / self.width;
+            let sin_theta = (PI * (row as Real + 0.5) / self.height as Real).sin();
+            luminance(*p) * sin_theta
+        }).collect();
+
+        Distribution2D::new(&func, self.width as usize, self.height as usize)
+    }
+
+    fn direction_to_uv(&self, direction: Vec3<Real>) -> (Real, Real) {
+        let d = direction.normalise();
+        let phi = d.z.atan2(d.x) - self.rotation;
+        let theta = d.y.clamp(-1.0, 1.0).acos();
+        ((phi / (2.0 * PI)).rem_euclid(1.0), theta / PI)
+    }
+
+    fn uv_to_direction(&self, u: Real, v: Real) -> Vec3<Real> {
+        let phi = u * 2.0 * PI + self.rotation;
+        let theta = v * PI;
+        Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin())
+    }
+
+    fn lookup(&self, u: Real, v: Real) -> Vec3<Real> {
+        let x = ((u * self.width as Real) as u32).min(self.width - 1);
+        let y = ((v * self.height as Real) as u32).min(self.height - 1);
+        self.pixels[(y * self.width + x) as usize] * self.intensity
+    }
+
+    pub fn radiance(&self, direction: Vec3<Real>) -> Vec3<Real> {
+        let (u, v) = self.direction_to_uv(direction);
+        self.lookup(u, v)
+    }
+
+    pub fn sample(&self, u: (Real, Real)) -> Option<(Vec3<Real>, Vec3<Real>, Real)> {
+        let distribution = self.distribution.as_ref()?;
+        let ((mu, mv), map_pdf) = distribution.sample_continuous(u);
+        if map_pdf == 0.0 {
+            return None;
+        }
+
+        let sin_theta = (mv * PI).sin();
+        if sin_theta == 0.0 {
+            return None;
+        }
+
+        // Convert the density from image space to solid angle
+        let pdf = map_pdf / (2.0 * PI * PI * sin_theta);
+        Some((self.uv_to_direction(mu, mv), self.lookup(mu, mv), pdf))
+    }
+
+    pub fn pdf(&self, direction: Vec3<Real>) -> Real {
+        let Some(distribution) = self.distribution.as_ref() else {
+            return 0.0;
+        };
+
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = (v * PI).sin();
+        if sin_theta == 0.0 {
+            return 0.0;
+        }
+        distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+pub fn luminance(c: Vec3<Real>) -> Real {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
@@ -0,0 +1,75 @@
+use std::any::Any;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use image::ImageError;
+
+// Everything that can stop a render or the saving of its results
+#[derive(Debug)]
+pub enum RenderError {
+    // Reading or writing a file that isn't an image, e.g. the statistics
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    // Encoding or writing an image. `fallback` is where the image was saved instead, if it could be.
+    Image {
+        path: PathBuf,
+        source: ImageError,
+        fallback: Option<PathBuf>,
+    },
+    // A scene, or a file it needs such as an environment map, could not be loaded
+    Scene(String),
+    // Settings the renderer can't work with, e.g. a zero image width
+    InvalidSettings(String),
+    // A render thread panicked. Holds the panic's message.
+    Worker(String),
+}
+
+pub type Result<T> = std::result::Result<T, RenderError>;
+
+impl RenderError {
+    pub fn io(path: impl AsRef<Path>, source: io::Error) -> Self {
+        RenderError::Io { path: path.as_ref().to_path_buf(), source }
+    }
+
+    pub fn image(path: impl AsRef<Path>, source: ImageError) -> Self {
+        RenderError::Image { path: path.as_ref().to_path_buf(), source, fallback: None }
+    }
+
+    // From the payload `catch_unwind` or `join` hands back
+    pub fn worker(payload: &(dyn Any + Send)) -> Self {
+        let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        RenderError::Worker(message)
+    }
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderError::Io { path, source } => write!(f, "could not access {}: {}", path.display(), source),
+            RenderError::Image { path, source, fallback } => {
+                write!(f, "could not save image to {}: {}", path.display(), source)?;
+                match fallback {
+                    Some(fallback) => write!(f, " (saved to {} instead)", fallback.display()),
+                    None => Ok(()),
+                }
+            }
+            RenderError::Scene(message) => write!(f, "could not load scene: {}", message),
+            RenderError::InvalidSettings(message) => write!(f, "invalid render settings: {}", message),
+            RenderError::Worker(message) => write!(f, "render thread failed: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RenderError::Io { source, .. } => Some(source),
+            RenderError::Image { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
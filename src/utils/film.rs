@@ -0,0 +1,195 @@
+use crate::utils::real::consts::PI;
+use crate::utils::vector::Vec3;
+use crate::utils::real::Real;
+
+// Pixel reconstruction filters. Each sample is splatted into every pixel whose centre lies
+// within `radius` of it, weighted by the filter. A box of radius 0.5 gives each sample to
+// its own pixel only, which is how the renderer has always averaged samples.
+#[derive(Debug, Clone, Copy)]
+pub enum Filter {
+    Box {
+        radius: Real,
+    },
+    Tent {
+        radius: Real,
+    },
+    Gaussian {
+        radius: Real,
+        sigma: Real,
+    },
+    // Mitchell-Netravali cubic, B = C = 1/3 is the recommended compromise between blur and ringing
+    Mitchell {
+        radius: Real,
+        b: Real,
+        c: Real,
+    },
+    // Windowed sinc, `tau` is the number of sinc lobes under the window
+    Lanczos {
+        radius: Real,
+        tau: Real,
+    },
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::Box { radius: 0.5 }
+    }
+}
+
+impl Filter {
+    pub fn radius(&self) -> Real {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius, .. } => radius,
+        }
+    }
+
+    // Weight of a sample offset by (dx, dy) pixels from a pixel centre. The filters are separable.
+    pub fn evaluate(&self, dx: Real, dy: Real) -> Real {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    // `offset` is the pixel centre minus the sample position
+    fn evaluate_1d(&self, offset: Real) -> Real {
+        let x = offset.abs();
+        match *self {
+            // Half-open, so that a sample on the edge between two pixels only counts for the one
+            // covering it, as pixel i covers [i, i + 1)
+            Filter::Box { radius } => if -radius < offset && offset <= radius { 1.0 } else { 0.0 },
+            Filter::Tent { radius } => (radius - x).max(0.0),
+            Filter::Gaussian { radius, sigma } => {
+                let gaussian = |v: Real| (-v * v / (2.0 * sigma * sigma)).exp();
+                // Shifted down so that the filter reaches zero at its radius
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            }
+            Filter::Mitchell { radius, b, c } => {
+                let x = 2.0 * x / radius;
+                if x >= 2.0 {
+                    0.0
+                } else if x >= 1.0 {
+                    ((-b - 6.0 * c) * x.powi(3) + (6.0 * b + 30.0 * c) * x.powi(2)
+                        + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+                } else {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3) + (-18.0 + 12.0 * b + 6.0 * c) * x.powi(2)
+                        + (6.0 - 2.0 * b)) / 6.0
+                }
+            }
+            Filter::Lanczos { radius, tau } => {
+                if x > radius {
+                    0.0
+                } else {
+                    sinc(x) * sinc(x / tau)
+                }
+            }
+        }
+    }
+}
+
+fn sinc(x: Real) -> Real {
+    if x < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+// Weighted sums of radiance and filter weights for a rectangle of the image. Every block of
+// the image is rendered into its own film covering it plus the filter's reach, and the films
+// are merged into the full image afterwards.
+#[derive(Clone)]
+pub struct Film {
+    x0: i32,
+    y0: i32,
+    width: u32,
+    height: u32,
+    filter: Filter,
+    sums: Vec<Vec3<Real>>,
+    weights: Vec<Real>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32, filter: Filter) -> Self {
+        Film::tile(0, 0, width, height, filter)
+    }
+
+    // A film for pixels x0..x0 + width, y0..y0 + height of a larger image
+    pub fn tile(x0: i32, y0: i32, width: u32, height: u32, filter: Filter) -> Self {
+        let pixels = (width * height) as usize;
+        Film { x0, y0, width, height, filter, sums: vec![Vec3::new(0.0, 0.0, 0.0); pixels], weights: vec![0.0; pixels] }
+    }
+
+    // The tile that samples taken in pixels x0..x0 + width, y0..y0 + height splat into
+    pub fn tile_for_block(x0: u32, y0: u32, width: u32, height: u32, filter: Filter) -> Self {
+        let reach = filter.radius().ceil() as i32;
+        Film::tile(x0 as i32 - reach, y0 as i32 - reach, width + 2 * reach as u32, height + 2 * reach as u32, filter)
+    }
+
+    // Splats a sample taken at continuous image position (x, y), where pixel (i, j) covers
+    // [i, i + 1) x [j, j + 1)
+    pub fn add_sample(&mut self, x: Real, y: Real, radiance: Vec3<Real>) {
+        let radius = self.filter.radius();
+        let x_min = (x - 0.5 - radius).ceil() as i32;
+        let x_max = (x - 0.5 + radius).floor() as i32;
+        let y_min = (y - 0.5 - radius).ceil() as i32;
+        let y_max = (y - 0.5 + radius).floor() as i32;
+
+        for py in y_min.max(self.y0)..=y_max.min(self.y0 + self.height as i32 - 1) {
+            for px in x_min.max(self.x0)..=x_max.min(self.x0 + self.width as i32 - 1) {
+                let weight = self.filter.evaluate(px as Real + 0.5 - x, py as Real + 0.5 - y);
+                if weight == 0.0 {
+                    continue;
+                }
+                let index = ((py - self.y0) as u32 * self.width + (px - self.x0) as u32) as usize;
+                self.sums[index] = self.sums[index] + radiance * weight;
+                self.weights[index] += weight;
+            }
+        }
+    }
+
+    // Adds the overlapping part of `tile` into this film
+    pub fn merge(&mut self, tile: &Film) {
+        for ty in 0..tile.height as i32 {
+            let y = tile.y0 + ty - self.y0;
+            if y < 0 || y >= self.height as i32 {
+                continue;
+            }
+            for tx in 0..tile.width as i32 {
+                let x = tile.x0 + tx - self.x0;
+                if x < 0 || x >= self.width as i32 {
+                    continue;
+                }
+                let src = (ty as u32 * tile.width + tx as u32) as usize;
+                let dst = (y as u32 * self.width + x as u32) as usize;
+                self.sums[dst] = self.sums[dst] + tile.sums[src];
+                self.weights[dst] += tile.weights[src];
+            }
+        }
+    }
+
+    // Final linear pixel values. Filters with negative lobes can produce negative radiance
+    // around sharp edges, which is clamped away.
+    pub fn resolve(&self) -> Vec<Vec3<Real>> {
+        self.sums.iter().zip(self.weights.iter()).map(|(&sum, &weight)| resolved(sum, weight)).collect()
+    }
+
+    // The value of pixel (x, y) of the larger image from the samples this film holds so far
+    pub fn resolve_pixel(&self, x: i32, y: i32) -> Vec3<Real> {
+        let (tx, ty) = (x - self.x0, y - self.y0);
+        if tx < 0 || ty < 0 || tx >= self.width as i32 || ty >= self.height as i32 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let index = (ty as u32 * self.width + tx as u32) as usize;
+        resolved(self.sums[index], self.weights[index])
+    }
+}
+
+fn resolved(sum: Vec3<Real>, weight: Real) -> Vec3<Real> {
+    if weight == 0.0 {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    let c = sum / weight;
+    Vec3::new(c.x.max(0.0), c.y.max(0.0), c.z.max(0.0))
+}
@@ -0,0 +1,89 @@
+use std::path::Path;
+use image::{Rgb, Rgb32FImage, RgbImage};
+use crate::utils::color::Color;
+use crate::utils::error::{RenderError, Result};
+use crate::utils::vector::Vec3;
+
+// A float image of linear radiance, row-major with (0, 0) at the top left
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Vec3<f32>>,
+}
+
+impl Framebuffer {
+    // A black image
+    pub fn new(width: u32, height: u32) -> Self {
+        Framebuffer::from_pixels(width, height, vec![Vec3::new(0.0, 0.0, 0.0); (width * height) as usize])
+    }
+
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<Vec3<f32>>) -> Self {
+        assert_eq!(pixels.len(), (width * height) as usize, "framebuffer size mismatch");
+        Framebuffer { width, height, pixels }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Vec3<f32> {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, value: Vec3<f32>) {
+        self.pixels[(y * self.width + x) as usize] = value;
+    }
+
+    pub fn pixels(&self) -> &[Vec3<f32>] {
+        &self.pixels
+    }
+
+    pub fn into_pixels(self) -> Vec<Vec3<f32>> {
+        self.pixels
+    }
+
+    // Copies `tile` in with its top left corner at (x0, y0)
+    pub fn blit(&mut self, tile: &Framebuffer, x0: u32, y0: u32) {
+        for y in 0..tile.height.min(self.height.saturating_sub(y0)) {
+            for x in 0..tile.width.min(self.width.saturating_sub(x0)) {
+                self.set_pixel(x0 + x, y0 + y, tile.pixel(x, y));
+            }
+        }
+    }
+
+    // Clamped and gamma corrected for display
+    pub fn to_rgb_image(&self) -> RgbImage {
+        RgbImage::from_fn(self.width, self.height, |x, y| Rgb(Color::from_vec3_float(self.pixel(x, y), 1).to_rgb()))
+    }
+
+    // The linear values unchanged, e.g. for writing OpenEXR or further processing
+    pub fn to_rgb32f_image(&self) -> Rgb32FImage {
+        Rgb32FImage::from_fn(self.width, self.height, |x, y| {
+            let c = self.pixel(x, y);
+            Rgb([c.x, c.y, c.z])
+        })
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        self.to_rgb_image().save_with_format(path, image::ImageFormat::Png).map_err(|e| RenderError::image(path, e))
+    }
+
+    // Linear and unclamped, as a single-layer OpenEXR file
+    pub fn save_exr<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        self.to_rgb32f_image().save_with_format(path, image::ImageFormat::OpenExr).map_err(|e| RenderError::image(path, e))
+    }
+}
+
+impl From<&Rgb32FImage> for Framebuffer {
+    fn from(image: &Rgb32FImage) -> Self {
+        let pixels = image.pixels().map(|p| Vec3::new(p[0], p[1], p[2])).collect();
+        Framebuffer::from_pixels(image.width(), image.height(), pixels)
+    }
+}
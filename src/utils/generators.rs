@@ -0,0 +1,294 @@
+use std::ops::Range;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::utils::bvh::Bvh;
+use crate::utils::camera::Camera;
+use crate::utils::environment::Environment;
+use crate::utils::error::{RenderError, Result};
+use crate::utils::geometry::{Hittable, HittableList, Sphere};
+use crate::utils::light::Light;
+use crate::utils::material::Material;
+use crate::utils::real::{consts::PI, to_f32, Real};
+use crate::utils::scene::{self, Scene};
+use crate::utils::vector::Vec3;
+
+// Deepest sphereflake `generate` builds: 597,871 spheres
+pub const MAX_FLAKE_DEPTH: u32 = 6;
+// Largest material grid `generate` builds, per side
+pub const MAX_GRID_SIZE: u32 = 16;
+// Most spheres `generate` builds a stress scene with, about the size of the deepest sphereflake
+pub const MAX_STRESS_SPHERES: u32 = 1_000_000;
+
+// A procedural scene that can be picked by name at runtime, e.g. from the command line. The
+// same generator and seed always give the same scene; generators with nothing random in them
+// ignore the seed. Everything is built from spheres, as that is the only primitive there is,
+// so the books' boxes and quads are stood in for by spheres and their area lights by spot lights.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Generator {
+    // `scene::default_scene`: three spheres on a yellow ground
+    Default,
+    // The final scene of Ray Tracing in One Weekend, `scene::seeded_random_scene`
+    OneWeekend,
+    // The final scene of The Next Week: a ground of raised blocks, glass, metal, a sphere of
+    // glass around a blue one where the book has fog, and a rotated cluster of 1000 spheres
+    NextWeek,
+    // The final scene of The Rest of Your Life: a Cornell box with an aluminium sphere where
+    // the book has a box, and a glass sphere
+    RestOfYourLife,
+    // The Cornell box, with walls made of spheres large enough to be flat, a light under the
+    // ceiling and two white spheres in place of the blocks
+    CornellBox,
+    // `size` x `size` spheres: a metal core whose roughness rises from row to row, under a
+    // clear coat whose index of refraction rises from column to column
+    MaterialGrid { size: u32 },
+    // Haines' sphereflake: nine spheres a third of the size on every sphere, `depth` times
+    SphereFlake { depth: u32 },
+    // `spheres` randomly placed spheres of random materials, for scaling benchmarks
+    Stress { spheres: u32 },
+}
+
+impl Generator {
+    // Accepted by `from_name`. A parameter can follow the name after a colon.
+    pub const NAMES: [&'static str; 8] = [
+        "default", "one-weekend", "next-week", "rest-of-your-life", "cornell-box",
+        "material-grid[:size]", "sphere-flake[:depth]", "stress[:spheres]",
+    ];
+
+    // Parses a name such as "sphere-flake" or "sphere-flake:4"
+    pub fn from_name(name: &str) -> Result<Self> {
+        let (base, parameter) = match name.split_once(':') {
+            Some((base, parameter)) => (base, Some(parameter)),
+            None => (name, None),
+        };
+        let number = |default: u32| -> Result<u32> {
+            match parameter {
+                Some(parameter) => parameter.parse::<u32>().map_err(|_| RenderError::Scene(format!("'{}' needs a whole number after the colon", name))),
+                None => Ok(default),
+            }
+        };
+        let generator = match base {
+            "default" => Generator::Default,
+            "one-weekend" => Generator::OneWeekend,
+            "next-week" => Generator::NextWeek,
+            "rest-of-your-life" => Generator::RestOfYourLife,
+            "cornell-box" => Generator::CornellBox,
+            "material-grid" => Generator::MaterialGrid { size: number(5)? },
+            "sphere-flake" => Generator::SphereFlake { depth: number(3)? },
+            "stress" => Generator::Stress { spheres: number(10_000)? },
+            _ => return Err(RenderError::Scene(format!("unknown scene '{}', expected one of {}", name, Generator::NAMES.join(", ")))),
+        };
+        // Only the parameterised scenes take a parameter
+        if parameter.is_some() && !matches!(generator, Generator::MaterialGrid { .. } | Generator::SphereFlake { .. } | Generator::Stress { .. }) {
+            return Err(RenderError::Scene(format!("'{}' takes no parameter", base)));
+        }
+        generator.check()?;
+        Ok(generator)
+    }
+
+    // Fails with a scene error if the parameter is outside the limits above
+    pub fn generate(&self, seed: u64, aspect_ratio: Real) -> Result<Scene> {
+        self.check()?;
+        let mut rng = StdRng::seed_from_u64(seed);
+        Ok(match *self {
+            Generator::Default => scene::default_scene(aspect_ratio, 20.0),
+            Generator::OneWeekend => scene::seeded_random_scene(seed, aspect_ratio, 20.0),
+            Generator::NextWeek => next_week(&mut rng, aspect_ratio),
+            Generator::RestOfYourLife => rest_of_your_life(aspect_ratio),
+            Generator::CornellBox => cornell_box(aspect_ratio),
+            Generator::MaterialGrid { size } => material_grid(size, aspect_ratio),
+            Generator::SphereFlake { depth } => sphere_flake(depth, aspect_ratio),
+            Generator::Stress { spheres } => stress(&mut rng, spheres, aspect_ratio),
+        })
+    }
+
+    // Larger scenes take too long to build or too much memory to be worth rendering, and a
+    // material grid or stress scene with nothing in it renders only the background
+    fn check(&self) -> Result<()> {
+        let (what, value, range) = match *self {
+            Generator::MaterialGrid { size } => ("material-grid size", size, 1..MAX_GRID_SIZE + 1),
+            Generator::SphereFlake { depth } => ("sphere-flake depth", depth, 0..MAX_FLAKE_DEPTH + 1),
+            Generator::Stress { spheres } => ("stress sphere count", spheres, 1..MAX_STRESS_SPHERES + 1),
+            _ => return Ok(()),
+        };
+        if !range.contains(&value) {
+            return Err(RenderError::Scene(format!("{} {} is out of range, expected {} to {}", what, value, range.start, range.end - 1)));
+        }
+        Ok(())
+    }
+}
+
+// Drawn as f32 whatever `Real` is, as in `scene::seeded_random_scene`
+fn uniform(rng: &mut StdRng, range: Range<f32>) -> Real {
+    rng.gen_range(range) as Real
+}
+
+fn lambertian(r: Real, g: Real, b: Real) -> Material {
+    Material::Lambertian { albedo: Vec3::new(r, g, b) }
+}
+
+fn black() -> Environment {
+    Environment::Constant(Vec3::new(0.0, 0.0, 0.0))
+}
+
+// Stands in for a ceiling light of `radiance` and `area`: a spot shining over the half space
+// below it, with the same power
+fn area_light(position: Vec3<Real>, radiance: Real, area: Real) -> Light {
+    Light::spot(position, position - Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 1.0, 1.0), radiance * area * PI, 80.0, 90.0)
+}
+
+fn next_week(rng: &mut StdRng, aspect_ratio: Real) -> Scene {
+    let mut spheres = vec![];
+
+    // Blocks 100 wide and 1 to 101 high, each a sphere sunk until its top is at that height
+    let ground = lambertian(0.48, 0.83, 0.53);
+    let block_radius = 75.0;
+    for i in 0..20 {
+        for j in 0..20 {
+            let x = -1000.0 + 50.0 + i as Real * 100.0;
+            let z = -1000.0 + 50.0 + j as Real * 100.0;
+            let height = uniform(rng, 1.0..101.0);
+            spheres.push(Sphere::new(Vec3::new(x, height - block_radius, z), block_radius, ground.clone()));
+        }
+    }
+
+    // The book's moving sphere, standing still
+    spheres.push(Sphere::new(Vec3::new(415.0, 400.0, 200.0), 50.0, lambertian(0.7, 0.3, 0.1)));
+    spheres.push(Sphere::new(Vec3::new(260.0, 150.0, 45.0), 50.0, Material::Dielctric { ir: 1.5 }));
+    spheres.push(Sphere::new(Vec3::new(0.0, 150.0, 145.0), 50.0, Material::Metal { albedo: Vec3::new(0.8, 0.8, 0.9), fuzz: 1.0 }));
+    spheres.push(Sphere::new(Vec3::new(360.0, 150.0, 145.0), 70.0, Material::Dielctric { ir: 1.5 }));
+    spheres.push(Sphere::new(Vec3::new(360.0, 150.0, 145.0), 55.0, lambertian(0.2, 0.4, 0.9)));
+    // The textured earth and noise spheres, in plain colours
+    spheres.push(Sphere::new(Vec3::new(400.0, 200.0, 400.0), 100.0, lambertian(0.2, 0.35, 0.6)));
+    spheres.push(Sphere::new(Vec3::new(220.0, 280.0, 300.0), 80.0, lambertian(0.73, 0.73, 0.73)));
+
+    // 1000 small spheres in a cube of 165, turned 15 degrees about the vertical and moved
+    let white = lambertian(0.73, 0.73, 0.73);
+    let angle: Real = 15.0;
+    let (sin, cos) = angle.to_radians().sin_cos();
+    for _ in 0..1000 {
+        let p = Vec3::new(uniform(rng, 0.0..165.0), uniform(rng, 0.0..165.0), uniform(rng, 0.0..165.0));
+        let turned = Vec3::new(cos * p.x + sin * p.z, p.y, -sin * p.x + cos * p.z);
+        spheres.push(Sphere::new(turned + Vec3::new(-100.0, 270.0, 395.0), 10.0, white.clone()));
+    }
+
+    let world = HittableList::new(vec![Box::new(Bvh::new(spheres))]);
+    let camera = Camera::new(Vec3::new(478.0, 278.0, -600.0), Vec3::new(278.0, 278.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 40.0, aspect_ratio, 0.0, 10.0);
+    Scene::new(world, camera)
+        .with_environment(black())
+        .with_light(area_light(Vec3::new(273.0, 550.0, 279.5), 7.0, 300.0 * 265.0))
+}
+
+// Walls of the box from 0 to 555 on every axis, open at the front. Spheres this large are flat
+// to within half a unit across the box.
+fn cornell_walls() -> Vec<Sphere> {
+    let radius = 1e5;
+    let wall = |point: Vec3<Real>, inward: Vec3<Real>, material: Material| Sphere::new(point - inward * radius, radius, material);
+    let white = lambertian(0.73, 0.73, 0.73);
+    vec![
+        wall(Vec3::new(0.0, 277.5, 277.5), Vec3::new(1.0, 0.0, 0.0), lambertian(0.65, 0.05, 0.05)),
+        wall(Vec3::new(555.0, 277.5, 277.5), Vec3::new(-1.0, 0.0, 0.0), lambertian(0.12, 0.45, 0.15)),
+        wall(Vec3::new(277.5, 0.0, 277.5), Vec3::new(0.0, 1.0, 0.0), white.clone()),
+        wall(Vec3::new(277.5, 555.0, 277.5), Vec3::new(0.0, -1.0, 0.0), white.clone()),
+        wall(Vec3::new(277.5, 277.5, 555.0), Vec3::new(0.0, 0.0, -1.0), white),
+    ]
+}
+
+fn cornell_scene(contents: Vec<Sphere>, aspect_ratio: Real) -> Scene {
+    let mut objects: Vec<Box<dyn Hittable + Sync + Send>> = vec![];
+    for sphere in cornell_walls().into_iter().chain(contents) {
+        objects.push(Box::new(sphere));
+    }
+    let camera = Camera::new(Vec3::new(278.0, 278.0, -800.0), Vec3::new(278.0, 278.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 40.0, aspect_ratio, 0.0, 10.0);
+    Scene::new(HittableList::new(objects), camera)
+        .with_environment(black())
+        .with_light(area_light(Vec3::new(278.0, 545.0, 279.5), 15.0, 130.0 * 105.0))
+}
+
+fn cornell_box(aspect_ratio: Real) -> Scene {
+    let white = lambertian(0.73, 0.73, 0.73);
+    cornell_scene(vec![
+        // Where the short and the tall block stand
+        Sphere::new(Vec3::new(212.5, 82.5, 147.5), 82.5, white.clone()),
+        Sphere::new(Vec3::new(347.5, 120.0, 377.5), 120.0, white),
+    ], aspect_ratio)
+}
+
+fn rest_of_your_life(aspect_ratio: Real) -> Scene {
+    let aluminium = Material::Metal { albedo: Vec3::new(0.8, 0.85, 0.88), fuzz: 0.0 };
+    cornell_scene(vec![
+        Sphere::new(Vec3::new(347.5, 100.0, 377.5), 100.0, aluminium),
+        Sphere::new(Vec3::new(190.0, 90.0, 190.0), 90.0, Material::Dielctric { ir: 1.5 }),
+    ], aspect_ratio)
+}
+
+fn material_grid(size: u32, aspect_ratio: Real) -> Scene {
+    let mut objects: Vec<Box<dyn Hittable + Sync + Send>> = vec![
+        Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, lambertian(0.5, 0.5, 0.5))),
+    ];
+    let fraction = |i: u32| if size > 1 { i as Real / (size - 1) as Real } else { 0.0 };
+    let offset = (size - 1) as Real / 2.0;
+    for row in 0..size {
+        for column in 0..size {
+            let center = Vec3::new(column as Real - offset, 0.4, row as Real - offset);
+            // At an index of one the coat all but vanishes, leaving the bare metal
+            let coat = Material::Dielctric { ir: 1.0 + 1.5 * fraction(column) };
+            let core = Material::Metal { albedo: Vec3::new(0.9, 0.6, 0.3), fuzz: fraction(row) };
+            objects.push(Box::new(Sphere::new(center, 0.4, coat)));
+            objects.push(Box::new(Sphere::new(center, 0.3, core)));
+        }
+    }
+
+    let extent = size as Real;
+    let camera = Camera::new(Vec3::new(0.0, 1.2 * extent + 1.0, 1.4 * extent + 1.0), Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 40.0, aspect_ratio, 0.0, 10.0);
+    Scene::new(HittableList::new(objects), camera)
+        .with_light(Light::directional(Vec3::new(-0.5, -1.0, -0.7), Vec3::new(1.0, 1.0, 1.0), 1.5))
+}
+
+fn sphere_flake(depth: u32, aspect_ratio: Real) -> Scene {
+    let mut spheres = vec![Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, lambertian(0.5, 0.5, 0.5))];
+    flake(&mut spheres, Vec3::new(0.0, 1.0, 0.0), 1.0, Vec3::new(0.0, 1.0, 0.0), depth);
+
+    let world = HittableList::new(vec![Box::new(Bvh::new(spheres))]);
+    let camera = Camera::new(Vec3::new(3.5, 2.5, 3.5), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 35.0, aspect_ratio, 0.0, 10.0);
+    Scene::new(world, camera)
+        .with_light(Light::directional(Vec3::new(-1.0, -1.5, -0.5), Vec3::new(1.0, 1.0, 1.0), 1.5))
+}
+
+// A sphere and, `depth` levels down, its children: six around its equator about `axis` and
+// three above, each touching it and pointing away from its centre
+fn flake(spheres: &mut Vec<Sphere>, center: Vec3<Real>, radius: Real, axis: Vec3<Real>, depth: u32) {
+    spheres.push(Sphere::new(center, radius, Material::Metal { albedo: Vec3::new(0.8, 0.8, 0.85), fuzz: 0.05 }));
+    if depth == 0 {
+        return;
+    }
+    let (u, v) = axis.orthonormal_basis();
+    let child = radius / 3.0;
+    let directions = (0..6).map(|k| (k as Real * PI / 3.0, 0.0))
+        .chain((0..3).map(|k| (PI / 6.0 + k as Real * 2.0 * PI / 3.0, PI / 3.0)));
+    for (azimuth, elevation) in directions {
+        let direction = (u * azimuth.cos() + v * azimuth.sin()) * elevation.cos() + axis * elevation.sin();
+        flake(spheres, center + direction * (radius + child), child, direction, depth - 1);
+    }
+}
+
+fn stress(rng: &mut StdRng, count: u32, aspect_ratio: Real) -> Scene {
+    // Unit spheres about four apart on average, so density stays the same as the count grows
+    let side = 4.0 * (count as Real).cbrt();
+    let half = to_f32(side) / 2.0;
+    let spheres: Vec<Sphere> = (0..count).map(|_| {
+        let center = Vec3::new(uniform(rng, -half..half), uniform(rng, -half..half), uniform(rng, -half..half));
+        let choose = uniform(rng, 0.0..1.0);
+        let material = if choose < 0.8 {
+            Material::Lambertian { albedo: Vec3::random_with(rng, 0.0..1.0) }
+        } else if choose < 0.95 {
+            Material::Metal { albedo: Vec3::random_with(rng, 0.5..1.0), fuzz: uniform(rng, 0.0..0.5) }
+        } else {
+            Material::Dielctric { ir: 1.5 }
+        };
+        Sphere::new(center, 1.0, material)
+    }).collect();
+
+    let world = HittableList::new(vec![Box::new(Bvh::new(spheres))]);
+    let camera = Camera::new(Vec3::new(1.2, 0.8, 1.6) * side, Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 40.0, aspect_ratio, 0.0, 10.0);
+    Scene::new(world, camera)
+}
@@ -1,28 +1,55 @@
-use std::rc::Rc;
-use std::sync::Arc;
-use crate::utils::color::Color;
-use crate::utils::ray::Ray;
+use crate::utils::ray::{offset_ray_origin, Ray};
 use crate::utils::vector::{Dot, Vec3};
-use crate::utils::material;
 use crate::utils::material::Material;
+use crate::utils::simd::{Realx4, RayPacket, Vec3x4};
+use crate::utils::stats::count_intersection_tests;
+use crate::utils::real::{gamma, Real};
 
 pub trait Hittable {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    fn hit(&self, r: &Ray, t_min: Real, t_max: Real) -> Option<HitRecord>;
+
+    // Intersects the four rays of a packet, each lane with its own `t_max`. Objects without a
+    // SIMD path trace the rays one at a time.
+    fn hit_packet(&self, packet: &RayPacket, t_min: Real, t_max: Realx4) -> [Option<HitRecord>; 4] {
+        let t_max = t_max.to_array();
+        std::array::from_fn(|i| self.hit(&packet.ray(i), t_min, t_max[i]))
+    }
+
+    // Whether `hit_packet` is any faster than tracing the four rays one at a time. A single
+    // sphere's early exit on a miss beats testing four rays against it at once.
+    fn packets_pay_off(&self) -> bool {
+        false
+    }
+
+    // How many ids this object takes up in `HitRecord::object_id`
+    fn object_count(&self) -> usize {
+        1
+    }
 }
 #[derive(Clone)]
 pub struct HitRecord {
-    pub p: Vec3<f32>,
-    pub normal: Vec3<f32>,
+    pub p: Vec3<Real>,
+    // Bound on how far `p` may be from the true surface on each axis, from rounding
+    pub p_error: Vec3<Real>,
+    pub normal: Vec3<Real>,
     pub material: Option<Material>,
-    pub t: f32,
+    pub t: Real,
     pub front_face: bool,
+    // Index of the object that was hit, counting every object in nested lists and sphere
+    // batches separately
+    pub object_id: usize,
 }
 
 impl HitRecord {
-    fn set_face_normal(&mut self, r: &Ray, outward_normal: Vec3<f32>) {
+    fn set_face_normal(&mut self, r: &Ray, outward_normal: Vec3<Real>) {
         self.front_face = r.direction.dot(outward_normal) < 0.0;
         self.normal = if self.front_face { outward_normal } else { -outward_normal };
     }
+
+    // A ray leaving the surface in `direction`, starting just clear of it on that side
+    pub fn spawn_ray(&self, direction: Vec3<Real>) -> Ray {
+        Ray::new(offset_ray_origin(self.p, self.p_error, self.normal, direction), direction)
+    }
 }
 
 pub struct HittableList {
@@ -36,65 +63,243 @@ impl HittableList {
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: Real, t_max: Real) -> Option<HitRecord> {
         let mut temp_record: Option<HitRecord> = None;
         let mut closest_so_far = t_max;
+        let mut first_id = 0;
         for h in self.objects.iter() {
-            if let Some(rec) = h.hit(r, t_min, closest_so_far) {
+            if let Some(mut rec) = h.hit(r, t_min, closest_so_far) {
                 closest_so_far = rec.t;
+                rec.object_id += first_id;
                 temp_record = Some(rec);
             }
+            first_id += h.object_count();
         }
         temp_record
     }
+
+    fn hit_packet(&self, packet: &RayPacket, t_min: Real, t_max: Realx4) -> [Option<HitRecord>; 4] {
+        let mut records: [Option<HitRecord>; 4] = Default::default();
+        let mut closest_so_far = t_max;
+        let mut first_id = 0;
+        for h in self.objects.iter() {
+            let mut closest = closest_so_far.to_array();
+            for (i, rec) in h.hit_packet(packet, t_min, closest_so_far).into_iter().enumerate() {
+                if let Some(mut rec) = rec {
+                    closest[i] = rec.t;
+                    rec.object_id += first_id;
+                    records[i] = Some(rec);
+                }
+            }
+            closest_so_far = Realx4::from_array(closest);
+            first_id += h.object_count();
+        }
+        records
+    }
+
+    fn packets_pay_off(&self) -> bool {
+        self.objects.iter().any(|h| h.packets_pay_off())
+    }
+
+    fn object_count(&self) -> usize {
+        self.objects.iter().map(|h| h.object_count()).sum()
+    }
 }
 
 pub struct Sphere {
-    pub center: Vec3<f32>,
-    pub radius: f32,
+    pub center: Vec3<Real>,
+    pub radius: Real,
     pub material: Material,
 }
 
 impl Sphere {
-    pub fn new(center: Vec3<f32>, radius: f32, material: Material) -> Self {
+    pub fn new(center: Vec3<Real>, radius: Real, material: Material) -> Self {
         Sphere { center, radius, material }
     }
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: Real, t_max: Real) -> Option<HitRecord> {
+        count_intersection_tests(1);
         let oc = r.origin - self.center;
         let a = r.direction.length_squared();
         let half_b = oc.dot(r.direction);
-        let c = oc.length_squared() - (self.radius * self.radius);
+        let r2 = self.radius * self.radius;
+        let c = oc.length_squared() - r2;
 
-
-        let discriminant = half_b * half_b - a * c;
+        // a times the squared distance from the line to the edge of the sphere, which unlike
+        // half_b^2 - a*c doesn't cancel away when the sphere is small next to its distance
+        let l = oc - r.direction * (half_b / a);
+        let discriminant = a * (r2 - l.length_squared());
         if discriminant < 0.0 {
             return None;
         }
         let sqrtd = discriminant.sqrt();
 
-        let mut root = (-half_b - sqrtd) / a;
-        if (root <= t_min || t_max <= root) {
-            root = (-half_b + sqrtd) / a;
-            if (root <= t_min || t_max <= root) {
-                return None;
-            }
-        }
+        // The root nearest zero comes from c / q, without subtracting nearly equal numbers
+        let q = -(half_b + if half_b < 0.0 { -sqrtd } else { sqrtd });
+        let (t0, t1) = (c / q, q / a);
+        let (near, far) = if t1 < t0 { (t1, t0) } else { (t0, t1) };
+
+        // Roots no further from zero than their rounding error could be the surface a
+        // spawned ray starts on, so they must clear t_min by that much
+        let c_error = gamma(6) * (oc.length_squared() + r2) / q.abs();
+        let clears = |t: Real| t - (gamma(7) * t.abs() + c_error) > t_min && t < t_max;
+
+        let root = if clears(near) {
+            near
+        } else if clears(far) {
+            far
+        } else {
+            return None;
+        };
+
+        Some(self.record(r, root))
+    }
+}
 
-        let p = r.at(root);
+impl Sphere {
+    fn record(&self, r: &Ray, root: Real) -> HitRecord {
+        // Projecting the point back onto the sphere bounds its error by a few ulps of its
+        // distance from the centre, however far along the ray it was
+        let from_center = r.at(root) - self.center;
+        let from_center = from_center * (self.radius.abs() / from_center.length());
+        let p = self.center + from_center;
         let mut rec = HitRecord {
             p,
+            p_error: from_center.abs() * gamma(5) + p.abs() * gamma(1),
             material: Some(self.material.clone()),
             normal: Vec3::new(0.0, 0.0, 0.0),
             t: root,
             front_face: false,
+            object_id: 0,
         };
 
-        let outward_normal = (rec.p - self.center) / self.radius as f32;
+        let outward_normal = from_center / self.radius;
         rec.set_face_normal(r, outward_normal);
+        rec
+    }
+}
 
-        Some(rec)
+// Spheres stored as structure-of-arrays in groups of four, so a ray is tested against four
+// of them per SIMD instruction. Gives the same hits as a `HittableList` of the same spheres.
+pub struct SphereBatch {
+    spheres: Vec<Sphere>,
+    centers: Vec<Vec3x4>,
+    radii: Vec<Realx4>,
+}
+
+impl SphereBatch {
+    pub fn new(spheres: Vec<Sphere>) -> Self {
+        let mut centers = vec![];
+        let mut radii = vec![];
+        for group in spheres.chunks(Realx4::LANES) {
+            // Unused lanes are NaN, which never passes the root tests
+            let lane = |i: usize, f: &dyn Fn(&Sphere) -> Real| group.get(i).map_or(Real::NAN, f);
+            let lanes = |f: &dyn Fn(&Sphere) -> Real| Realx4::from_array([lane(0, f), lane(1, f), lane(2, f), lane(3, f)]);
+            centers.push(Vec3x4::new(lanes(&|s| s.center.x), lanes(&|s| s.center.y), lanes(&|s| s.center.z)));
+            radii.push(lanes(&|s| s.radius));
+        }
+        SphereBatch { spheres, centers, radii }
+    }
+
+    pub fn len(&self) -> usize {
+        self.spheres.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spheres.is_empty()
+    }
+}
+
+impl Hittable for SphereBatch {
+    fn hit(&self, r: &Ray, t_min: Real, t_max: Real) -> Option<HitRecord> {
+        let origin = Vec3x4::splat(r.origin);
+        let direction = Vec3x4::splat(r.direction);
+        let t_min4 = Realx4::splat(t_min);
+        count_intersection_tests(self.spheres.len() as u64);
+
+        let mut closest_so_far = t_max;
+        let mut hit_index = None;
+        for (group, (center, radius)) in self.centers.iter().zip(self.radii.iter()).enumerate() {
+            let t = intersect4(origin, direction, *center, *radius, t_min4, Realx4::splat(closest_so_far));
+            if t.lt(Realx4::splat(closest_so_far)).bitmask() == 0 {
+                continue;
+            }
+            for (lane, t) in t.to_array().into_iter().enumerate() {
+                if t < closest_so_far {
+                    closest_so_far = t;
+                    hit_index = Some(group * Realx4::LANES + lane);
+                }
+            }
+        }
+
+        hit_index.map(|i| {
+            let mut rec = self.spheres[i].record(r, closest_so_far);
+            rec.object_id = i;
+            rec
+        })
     }
+
+    // Each sphere against all four rays at once
+    fn hit_packet(&self, packet: &RayPacket, t_min: Real, t_max: Realx4) -> [Option<HitRecord>; 4] {
+        let t_min4 = Realx4::splat(t_min);
+        count_intersection_tests(4 * self.spheres.len() as u64);
+        let mut closest_so_far = t_max;
+        let mut hit_index = Realx4::splat(-1.0);
+        for (i, sphere) in self.spheres.iter().enumerate() {
+            let t = intersect4(packet.origin, packet.direction, Vec3x4::splat(sphere.center), Realx4::splat(sphere.radius), t_min4, closest_so_far);
+            let closer = t.lt(closest_so_far);
+            closest_so_far = closer.select(t, closest_so_far);
+            hit_index = closer.select(Realx4::splat(i as Real), hit_index);
+        }
+
+        let (t, index) = (closest_so_far.to_array(), hit_index.to_array());
+        std::array::from_fn(|lane| {
+            (index[lane] >= 0.0).then(|| {
+                let i = index[lane] as usize;
+                let mut rec = self.spheres[i].record(&packet.ray(lane), t[lane]);
+                rec.object_id = i;
+                rec
+            })
+        })
+    }
+
+    fn packets_pay_off(&self) -> bool {
+        true
+    }
+
+    fn object_count(&self) -> usize {
+        self.spheres.len()
+    }
+}
+
+// The nearest root in (t_min, t_max) of four ray-sphere pairs, following the same steps as
+// `Sphere::hit` so both give identical results. Lanes without one are infinite.
+fn intersect4(origin: Vec3x4, direction: Vec3x4, center: Vec3x4, radius: Realx4, t_min: Realx4, t_max: Realx4) -> Realx4 {
+    let oc = origin - center;
+    let a = direction.length_squared();
+    let half_b = oc.dot(direction);
+    let r2 = radius * radius;
+    let c = oc.length_squared() - r2;
+
+    // NaN where the discriminant is negative, which fails every comparison below
+    let l = oc - direction * (half_b / a);
+    let sqrtd = (a * (r2 - l.length_squared())).sqrt();
+
+    let zero = Realx4::splat(0.0);
+    let q = -(half_b + half_b.lt(zero).select(-sqrtd, sqrtd));
+    let (t0, t1) = (c / q, q / a);
+    let swap = t1.lt(t0);
+    let (near, far) = (swap.select(t1, t0), swap.select(t0, t1));
+
+    let c_error = Realx4::splat(gamma(6)) * (oc.length_squared() + r2) / abs(q);
+    let clears = |t: Realx4| (t - (Realx4::splat(gamma(7)) * abs(t) + c_error)).gt(t_min).and(t.lt(t_max));
+    let near_ok = clears(near);
+    let far_ok = clears(far);
+    near_ok.select(near, far_ok.select(far, Realx4::splat(Real::INFINITY)))
+}
+
+fn abs(x: Realx4) -> Realx4 {
+    x.max(-x)
 }
\ No newline at end of file
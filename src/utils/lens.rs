@@ -0,0 +1,81 @@
+use crate::utils::real::consts::PI;
+use std::path::Path;
+use crate::utils::distribution::Distribution2D;
+use crate::utils::error::{RenderError, Result};
+use crate::utils::vector::Vec3;
+use crate::utils::real::Real;
+
+// Shape of the opening in the lens, which is the shape out of focus highlights (bokeh) take
+#[derive(Clone)]
+pub enum Aperture {
+    Circle,
+    // Regular polygon formed by `blades` straight iris blades, rotated by `rotation` degrees
+    Polygon {
+        blades: u32,
+        rotation: Real,
+    },
+    Mask(ApertureMask),
+}
+
+impl Aperture {
+    // Maps a uniform sample to a point on the aperture within [-1, 1]^2, x to the right and
+    // y up when looking through the camera
+    pub fn sample(&self, u: (Real, Real)) -> (Real, Real) {
+        match self {
+            Aperture::Circle => {
+                let p = Vec3::in_unit_disk(u);
+                (p.x, p.y)
+            }
+            Aperture::Polygon { blades, rotation } => sample_polygon(*blades, rotation.to_radians(), u),
+            Aperture::Mask(mask) => mask.sample(u),
+        }
+    }
+}
+
+// Splits the polygon into one triangle per blade around the centre and samples a triangle
+// uniformly, reusing the rest of `u.0` for the point within it
+fn sample_polygon(blades: u32, rotation: Real, u: (Real, Real)) -> (Real, Real) {
+    let blades = blades.max(3);
+    let scaled = u.0 * blades as Real;
+    let k = (scaled as u32).min(blades - 1);
+    let remapped = scaled - k as Real;
+
+    let corner = |i: u32| {
+        let angle = rotation + PI / 2.0 + 2.0 * PI * i as Real / blades as Real;
+        (angle.cos(), angle.sin())
+    };
+    let (a, b) = (corner(k), corner(k + 1));
+
+    let s = remapped.sqrt();
+    let (wa, wb) = (s * (1.0 - u.1), s * u.1);
+    (a.0 * wa + b.0 * wb, a.1 * wa + b.1 * wb)
+}
+
+// Aperture taken from a grayscale image, where white is fully open and black blocks light.
+// Lens positions are importance sampled by transmission, so the mask shapes the bokeh without
+// making the image darker.
+#[derive(Clone)]
+pub struct ApertureMask {
+    distribution: Distribution2D,
+}
+
+impl ApertureMask {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let img = image::open(path)
+            .map_err(|e| RenderError::Scene(format!("aperture mask {}: {}", path.display(), e)))?
+            .to_luma32f();
+        let transmission: Vec<Real> = img.pixels().map(|p| p[0].max(0.0) as Real).collect();
+        Ok(ApertureMask::from_transmission(&transmission, img.width(), img.height()))
+    }
+
+    // Row-major transmission values, with the first row at the top of the aperture
+    pub fn from_transmission(transmission: &[Real], width: u32, height: u32) -> Self {
+        ApertureMask { distribution: Distribution2D::new(transmission, width as usize, height as usize) }
+    }
+
+    fn sample(&self, u: (Real, Real)) -> (Real, Real) {
+        let ((x, y), _) = self.distribution.sample_continuous(u);
+        (2.0 * x - 1.0, 1.0 - 2.0 * y)
+    }
+}
@@ -0,0 +1,131 @@
+use crate::utils::real::consts::PI;
+use crate::utils::vector::{Dot, Vec3};
+use crate::utils::real::Real;
+
+// Lights with no area. Scattered rays can never hit them, so they are only ever found by
+// shadow rays cast from the surfaces they illuminate.
+#[derive(Debug, Clone, Copy)]
+pub enum Light {
+    Point {
+        position: Vec3<Real>,
+        intensity: Vec3<Real>,
+    },
+    Spot {
+        position: Vec3<Real>,
+        direction: Vec3<Real>,
+        intensity: Vec3<Real>,
+        cos_inner: Real,
+        cos_outer: Real,
+    },
+    Directional {
+        // Direction the light travels in
+        direction: Vec3<Real>,
+        irradiance: Vec3<Real>,
+    },
+}
+
+// Incident light at a point: unit direction towards the light, radiance arriving along it
+// and how far a shadow ray must travel to reach the light
+pub struct LightSample {
+    pub direction: Vec3<Real>,
+    pub radiance: Vec3<Real>,
+    pub distance: Real,
+}
+
+impl Light {
+    // A light emitting `power` watts of `color` equally in all directions
+    pub fn point(position: Vec3<Real>, color: Vec3<Real>, power: Real) -> Self {
+        Light::Point { position, intensity: color * (power / (4.0 * PI)) }
+    }
+
+    // A cone of light aimed at `target`, at full strength within `inner_angle` degrees of its
+    // axis and fading out smoothly by `outer_angle`
+    pub fn spot(position: Vec3<Real>, target: Vec3<Real>, color: Vec3<Real>, power: Real, inner_angle: Real, outer_angle: Real) -> Self {
+        let cos_outer = outer_angle.to_radians().cos();
+        let cos_inner = inner_angle.min(outer_angle).to_radians().cos();
+        // Approximate the emitted power by a cone with the average of the two angles
+        let solid_angle = 2.0 * PI * (1.0 - 0.5 * (cos_inner + cos_outer));
+        Light::Spot {
+            position,
+            direction: (target - position).normalise(),
+            intensity: color * (power / solid_angle),
+            cos_inner,
+            cos_outer,
+        }
+    }
+
+    // Light from an infinitely distant source shining along `direction`
+    pub fn directional(direction: Vec3<Real>, color: Vec3<Real>, irradiance: Real) -> Self {
+        Light::Directional { direction: direction.normalise(), irradiance: color * irradiance }
+    }
+
+    pub fn sample(&self, p: Vec3<Real>) -> Option<LightSample> {
+        match self {
+            Light::Point { position, intensity } => {
+                let (direction, distance) = towards(p, *position)?;
+                Some(LightSample { direction, radiance: *intensity / (distance * distance), distance })
+            }
+            Light::Spot { position, direction: axis, intensity, cos_inner, cos_outer } => {
+                let (direction, distance) = towards(p, *position)?;
+                let falloff = smoothstep(*cos_outer, *cos_inner, (-direction).dot(*axis));
+                if falloff <= 0.0 {
+                    return None;
+                }
+                Some(LightSample { direction, radiance: *intensity * (falloff / (distance * distance)), distance })
+            }
+            Light::Directional { direction, irradiance } => {
+                Some(LightSample { direction: -*direction, radiance: *irradiance, distance: Real::INFINITY })
+            }
+        }
+    }
+}
+
+fn towards(p: Vec3<Real>, position: Vec3<Real>) -> Option<(Vec3<Real>, Real)> {
+    let offset = position - p;
+    let distance = offset.length();
+    if distance == 0.0 {
+        return None;
+    }
+    Some((offset / distance, distance))
+}
+
+fn smoothstep(edge0: Real, edge1: Real, x: Real) -> Real {
+    if edge0 == edge1 {
+        return if x >= edge1 { 1.0 } else { 0.0 };
+    }
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+pub struct LightList {
+    pub lights: Vec<Light>,
+}
+
+impl LightList {
+    pub fn new(list: Vec<Light>) -> Self {
+        LightList { lights: list }
+    }
+
+    // Key, fill and rim lights around a subject, all aimed at `target`. The key sits in front
+    // of `camera` to one side and above, the fill on the opposite side at a quarter of the
+    // power and the rim behind the subject.
+    pub fn three_point(target: Vec3<Real>, camera: Vec3<Real>, power: Real) -> Self {
+        let forward = (target - camera).normalise();
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        // Looking straight up or down has no horizontal side, so take it from +z instead
+        let reference = if forward.y.abs() > 0.999 { Vec3::new(0.0, 0.0, 1.0) } else { up };
+        let side = forward.cross(reference).normalise();
+        let distance = (target - camera).length();
+        let white = Vec3::new(1.0, 1.0, 1.0);
+
+        let key = target - forward * distance * 0.7 + side * distance * 0.7 + up * distance * 0.5;
+        let fill = target - forward * distance * 0.7 - side * distance * 0.8 + up * distance * 0.2;
+        let rim = target + forward * distance * 0.8 + up * distance * 0.6;
+
+        LightList::new(vec![
+            Light::spot(key, target, white, power, 20.0, 30.0),
+            Light::spot(fill, target, white, power * 0.25, 30.0, 45.0),
+            Light::spot(rim, target, white, power * 0.5, 15.0, 25.0),
+        ])
+    }
+}
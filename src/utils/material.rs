@@ -1,35 +1,90 @@
 use crate::utils::geometry::HitRecord;
 use crate::utils::ray::Ray;
+use crate::utils::sampler::Sampler;
 use crate::utils::vector::{Dot, Vec3};
-use rand::{Rng, thread_rng};
+use crate::utils::real::consts::PI;
+use crate::utils::real::Real;
 
 #[derive(Clone)]
 pub enum Material {
     Lambertian {
-        albedo: Vec3<f32>,
+        albedo: Vec3<Real>,
     },
     Metal {
-        albedo: Vec3<f32>,
-        fuzz: f32,
+        albedo: Vec3<Real>,
+        fuzz: Real,
     },
     Dielctric {
-        ir: f32,
+        ir: Real,
     },
 }
 
-pub fn scatter(material: &Material, r_in: &Ray, rec: &HitRecord) -> (Vec3<f32>, Ray, bool) {
+impl Material {
+    // Surface color, as written to the albedo pass
+    pub fn albedo(&self) -> Vec3<Real> {
+        match self {
+            Material::Lambertian { albedo } | Material::Metal { albedo, .. } => *albedo,
+            Material::Dielctric { .. } => Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    // Mirrors and glass only ever scatter in a single direction, so light sampling cannot reach them
+    pub fn is_specular(&self) -> bool {
+        match self {
+            Material::Lambertian { .. } => false,
+            Material::Metal { fuzz, .. } => *fuzz <= 0.0,
+            Material::Dielctric { .. } => true,
+        }
+    }
+
+    // Solid angle density with which `scatter` picks `direction`, or `None` for specular
+    // lobes that light sampling can never hit
+    pub fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3<Real>) -> Option<Real> {
+        if self.is_specular() {
+            return None;
+        }
+
+        let direction = direction.normalise();
+        let cosine = rec.normal.dot(direction);
+        if cosine <= 0.0 {
+            return Some(0.0);
+        }
+
+        match self {
+            Material::Lambertian { .. } => Some(cosine / PI),
+            Material::Metal { fuzz, .. } => {
+                let reflected = r_in.direction.normalise().reflect(rec.normal);
+                Some(fuzz_pdf(reflected, *fuzz, direction))
+            }
+            Material::Dielctric { .. } => None,
+        }
+    }
+
+    // BSDF times the cosine term, for directions chosen by something other than `scatter`
+    pub fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3<Real>) -> Vec3<Real> {
+        match self {
+            Material::Lambertian { albedo } | Material::Metal { albedo, .. } => {
+                // Both lobes are sampled exactly, so the BSDF times cosine is the albedo scaled by the density
+                *albedo * self.scattering_pdf(r_in, rec, direction).unwrap_or(0.0)
+            }
+            Material::Dielctric { .. } => Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+pub fn scatter(material: &Material, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> (Vec3<Real>, Ray, bool) {
     match material {
         Material::Lambertian { albedo } => {
-            let mut scatter_direction = rec.normal + Vec3::random_in_unit_sphere().normalise();
+            let mut scatter_direction = rec.normal + Vec3::on_unit_sphere(sampler.get_2d());
             if scatter_direction.near_zero() {
                 scatter_direction = rec.normal;
             }
-            let scattered = Ray::new(rec.p, scatter_direction);
+            let scattered = rec.spawn_ray(scatter_direction);
             (*albedo, scattered, true)
         }
         Material::Metal { albedo, fuzz } => {
-            let reflected = r_in.direction.reflect(rec.normal);
-            let scattered = Ray::new(rec.p, reflected + Vec3::random_in_unit_sphere() * *fuzz);
+            let reflected = r_in.direction.normalise().reflect(rec.normal);
+            let scattered = rec.spawn_ray(reflected + Vec3::in_unit_sphere(sampler.get_2d(), sampler.get_1d()) * *fuzz);
             let should_scatter = scattered.direction.dot(rec.normal) > 0.0;
             (*albedo, scattered, should_scatter)
         }
@@ -40,9 +95,8 @@ pub fn scatter(material: &Material, r_in: &Ray, rec: &HitRecord) -> (Vec3<f32>,
             let cos_theta = (-unit_direction).dot(rec.normal).min(1.0);
             let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
-            let mut rng = thread_rng();
             let cannot_refract = refraction_ratio * sin_theta > 1.0;
-            let will_reflect = rng.gen::<f32>() < schlick(cos_theta, refraction_ratio);
+            let will_reflect = sampler.get_1d() < schlick(cos_theta, refraction_ratio);
 
             let direction = if cannot_refract || will_reflect {
                 unit_direction.reflect(rec.normal)
@@ -50,14 +104,32 @@ pub fn scatter(material: &Material, r_in: &Ray, rec: &HitRecord) -> (Vec3<f32>,
                 unit_direction.refract(rec.normal, refraction_ratio)
             };
 
-            let scattered = Ray::new(rec.p, direction);
+            let scattered = rec.spawn_ray(direction);
 
             (Vec3::new(1.0, 1.0, 1.0), scattered, true)
         }
     }
 }
 
-pub fn schlick(cosine: f32, ir: f32) -> f32 {
+pub fn schlick(cosine: Real, ir: Real) -> Real {
     let r0 = ((1.0 - ir) / (1.0 + ir)).powi(2);
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
-}
\ No newline at end of file
+}
+// Density of the direction towards a point drawn uniformly from a ball of radius `fuzz`
+// centred on the tip of the unit vector `reflected`: the ball's volume along the ray,
+// integral of t^2 dt over the chord, divided by the whole volume
+fn fuzz_pdf(reflected: Vec3<Real>, fuzz: Real, direction: Vec3<Real>) -> Real {
+    let c = direction.dot(reflected);
+    let discriminant = c * c - 1.0 + fuzz * fuzz;
+    if discriminant < 0.0 {
+        return 0.0;
+    }
+
+    let t_far = c + discriminant.sqrt();
+    if t_far <= 0.0 {
+        return 0.0;
+    }
+    let t_near = (c - discriminant.sqrt()).max(0.0);
+
+    (t_far.powi(3) - t_near.powi(3)) / (4.0 * PI * fuzz.powi(3))
+}
@@ -0,0 +1,53 @@
+use std::time::Duration;
+use crate::utils::framebuffer::Framebuffer;
+
+// A block of the image that has just had another pass of samples taken. Each tile is sent
+// again after every pass, so later updates replace earlier ones. The pixels are resolved from
+// the samples of the thread that rendered them, so a wide filter's reach into neighbouring
+// tiles only shows up in the final image.
+pub struct TileUpdate<'a> {
+    pub x0: u32,
+    pub y0: u32,
+    pub pixels: &'a Framebuffer,
+    // Samples per pixel taken in the tile so far. When the render stops partway through a
+    // tile, the pixels it had not reached are a pass short of this.
+    pub samples: u32,
+}
+
+// Counted in samples rather than pixels, since the image is refined in passes
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub samples_done: u64,
+    pub samples_total: u64,
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    pub fn fraction(&self) -> f32 {
+        (self.samples_done as f64 / self.samples_total.max(1) as f64) as f32
+    }
+}
+
+// Told about a render as it comes together, so that a UI or server can show partial results.
+// Calls arrive from the render threads, possibly several at once, and should return quickly.
+pub trait RenderObserver: Sync {
+    fn tile_completed(&self, _tile: &TileUpdate) {}
+
+    // Called each time another `PROGRESS_STEP` of the samples is done, and at the end
+    fn progress(&self, _progress: Progress) {}
+}
+
+// Fraction of the samples between progress notifications
+pub const PROGRESS_STEP: f32 = 0.1;
+
+// Observes nothing
+impl RenderObserver for () {}
+
+// Prints a line at every progress milestone
+pub struct ConsoleProgress;
+
+impl RenderObserver for ConsoleProgress {
+    fn progress(&self, progress: Progress) {
+        println!("{:>3.0}% rendered (Current Time: {:?})", progress.fraction() * 100.0, progress.elapsed);
+    }
+}
@@ -0,0 +1,71 @@
+use std::sync::Arc;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use crate::utils::error::{RenderError, Result};
+
+// How the render is cut into jobs for the thread pool. Every strategy takes the same samples,
+// and a strategy gives the same image on any number of threads. With the default box filter of
+// radius 0.5 every sample stays in its own pixel, so all but `Samples` give the same image. Wider
+// filters splat samples into neighbouring blocks, whose overlaps are added up in the order of
+// the strategy's layout, so the strategies then only agree to rounding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    // One job per row, taking all of the row's samples. Fine grained, so the threads finish
+    // close together, but neighbouring rows' filter footprints overlap the most.
+    Rows,
+    // One job per square tile, taking all of the tile's samples. The best locality.
+    Tiles,
+    // Every tile takes one pass of samples before any takes the next, so the whole image
+    // sharpens together. A render that is cancelled or runs out of time is evenly sampled.
+    #[default]
+    SamplePasses,
+    // One job per thread, each rendering the whole frame with its own share of every pass's
+    // samples into its own buffer. The buffers are added up in a fixed order, so the image only
+    // depends on the seed and the thread count. For small images at high sample counts, where
+    // there are too few tiles to keep every thread busy.
+    Samples,
+}
+
+// The threads a render runs on. Cloning shares the same threads, so an application can give
+// every render, and anything else it runs on rayon, one pool sized to the machine.
+#[derive(Clone)]
+pub struct RenderPool {
+    pool: Arc<ThreadPool>,
+}
+
+impl RenderPool {
+    // Starts `num_threads` threads, each pinned to its own core if `pin_threads` is set and the
+    // platform allows it. Pinning stops the scheduler moving threads between cores, which helps
+    // on machines with several sockets or mixed core types.
+    pub fn new(num_threads: u32, pin_threads: bool) -> Result<Self> {
+        if num_threads == 0 {
+            return Err(RenderError::InvalidSettings("num_threads is 0".to_string()));
+        }
+        let cores = if pin_threads { core_affinity::get_core_ids().unwrap_or_default() } else { vec![] };
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads as usize)
+            .thread_name(|i| format!("render-{}", i))
+            .start_handler(move |i| {
+                if !cores.is_empty() {
+                    core_affinity::set_for_current(cores[i % cores.len()]);
+                }
+            })
+            .build()
+            .map_err(|e| RenderError::Worker(format!("could not start the thread pool: {}", e)))?;
+        Ok(RenderPool { pool: Arc::new(pool) })
+    }
+
+    // Renders on an application's own rayon pool
+    pub fn from_pool(pool: Arc<ThreadPool>) -> Self {
+        RenderPool { pool }
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    // Runs `f` on the pool, so that rayon's parallel iterators and `join` inside it, such as
+    // the BVH build in `Bvh::new`, use these threads
+    pub fn install<R: Send>(&self, f: impl FnOnce() -> R + Send) -> R {
+        self.pool.install(f)
+    }
+}
@@ -1,19 +1,41 @@
-use crate::utils::vector::Vec3;
+use crate::utils::vector::{Dot, Vec3};
+use crate::utils::real::Real;
 #[derive(Debug, Clone, Copy)]
 pub struct Ray {
-    pub origin: Vec3<f32>,
-    pub direction: Vec3<f32>,
+    pub origin: Vec3<Real>,
+    pub direction: Vec3<Real>,
 }
 
 impl Ray {
-    pub fn new(origin: Vec3<f32>, direction: Vec3<f32>) -> Ray {
+    pub fn new(origin: Vec3<Real>, direction: Vec3<Real>) -> Ray {
         Ray {
-            origin: origin,
-            direction: direction
+            origin,
+            direction
         }
     }
 
-    pub fn at(&self, t: f32) -> Vec3<f32> {
+    pub fn at(&self, t: Real) -> Vec3<Real> {
         self.origin + self.direction * t
     }
 }
+
+// Moves a computed hit point `p`, within `p_error` of the true surface on each axis, along the
+// normal `n` to the side `w` leaves by, far enough that a ray from there can't hit the same
+// surface again. Replaces a fixed minimum distance, which misses small objects and lets rays
+// back into large ones (PBRT's `OffsetRayOrigin`).
+pub fn offset_ray_origin(p: Vec3<Real>, p_error: Vec3<Real>, n: Vec3<Real>, w: Vec3<Real>) -> Vec3<Real> {
+    let d = n.abs().dot(p_error);
+    let offset = if w.dot(n) < 0.0 { -n * d } else { n * d };
+    let po = p + offset;
+    // Rounding the sum could undo part of the offset, so round away from `p`
+    let away = |po: Real, offset: Real| {
+        if offset > 0.0 {
+            po.next_up()
+        } else if offset < 0.0 {
+            po.next_down()
+        } else {
+            po
+        }
+    };
+    Vec3::new(away(po.x, offset.x), away(po.y, offset.y), away(po.z, offset.z))
+}
@@ -0,0 +1,31 @@
+// The float type geometry, sampling and shading are computed in. Single precision by default;
+// the `f64` feature switches the whole pipeline to double precision, which keeps hit points
+// on large or distant objects accurate. Images and AOV buffers are stored as f32 either way.
+#[cfg(not(feature = "f64"))]
+pub type Real = f32;
+#[cfg(feature = "f64")]
+pub type Real = f64;
+
+#[cfg(not(feature = "f64"))]
+pub use std::f32::consts;
+#[cfg(feature = "f64")]
+pub use std::f64::consts;
+
+// For storing a value in an f32 image or buffer. A no-op in the default build.
+#[allow(clippy::unnecessary_cast)]
+pub fn to_f32(x: Real) -> f32 {
+    x as f32
+}
+
+// For accumulating in double precision. A no-op in the `f64` build.
+#[allow(clippy::unnecessary_cast)]
+pub fn to_f64(x: Real) -> f64 {
+    x as f64
+}
+
+// Bound on the relative rounding error of `n` operations in a row, (1 ± ε)^n - 1 with ε half
+// an ulp of one (Higham; PBRT's `gamma`)
+pub fn gamma(n: u32) -> Real {
+    let e = n as Real * Real::EPSILON * 0.5;
+    e / (1.0 - e)
+}
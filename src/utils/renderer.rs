@@ -1,88 +1,347 @@
-use std::sync::{Arc, Mutex};
-use std::sync::mpsc;
-use std::time::Instant;
-use image::{ImageBuffer, Rgb, RgbImage};
-use rand::{Rng, thread_rng};
-use crate::utils::camera::{Camera};
-use crate::utils::color::Color;
-use crate::utils::geometry::{Hittable, HittableList};
-use crate::utils::material::scatter;
+use std::ops::Range;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use rayon::prelude::*;
+use crate::utils::aov::{AovBuffers, AovOutput, FirstHit, Pass};
+use crate::utils::cancel::CancellationToken;
+use crate::utils::denoise::{denoise, DenoiseSettings};
+use crate::utils::error::{RenderError, Result};
+use crate::utils::film::{Film, Filter};
+use crate::utils::framebuffer::Framebuffer;
+use crate::utils::geometry::{HitRecord, Hittable};
+use crate::utils::material::{scatter, Material};
+use crate::utils::observer::{Progress, RenderObserver, TileUpdate, PROGRESS_STEP};
+use crate::utils::parallel::{RenderPool, Strategy};
 use crate::utils::ray::Ray;
-use crate::utils::vector::Vec3;
-
-pub fn render(camera: Camera,
-              world: HittableList,
-              image_width: u32,
-              image_height: u32,
-              samples_per_pixel: u32,
-              max_depth: i32,
-              num_threads: u32,
-              output_file: &str
-    ) -> () {
-    let pixel_delta_horizontal = camera.horizontal / (image_width as f32);
-    let pixel_delta_vertical = camera.vertical / (image_height as f32);
-    let pixel100_location = camera.upper_left_corner + pixel_delta_horizontal + pixel_delta_vertical * 0.5;
-
-    let timer = Instant::now();
-    //let (tx, rx) = mpsc::channel();
-    let mut threads = vec![];
-
-    let arc_world = Arc::new(world);
-    let arc_img = Arc::new(Mutex::new(RgbImage::new(image_width, image_height)));
-
-    for i in 0..num_threads {
-        let inner_world = arc_world.clone();
-        let inner_img = arc_img.clone();
-
-        threads.push(std::thread::spawn(move || {
-            let start = i * image_width / num_threads;
-            let end = if i == num_threads - 1 {
-                image_width
-            } else {
-                (i as u32 + 1) * image_width / num_threads
-            };
-
-            let mut sub_img = ImageBuffer::new(end - start, image_height);
-
-            let mut rng = thread_rng();
-            for x in start..end {
-                for y in 0..image_height {
-                    let mut pixel_color = Vec3::new(0.0, 0.0, 0.0);
-                    //Multisampling
-                    for _ in 0..samples_per_pixel {
-                        let ru: f32 = rng.gen();
-                        let rv: f32 = rng.gen();
-
-                        let u = ((x as f32) + ru) / ((image_width-1) as f32);
-                        let v = ((y as f32) + rv) / ((image_height-1) as f32);
-
-                        let ray = camera.get_ray(u, v);
-                        pixel_color = ray_color_vec3_float(&ray, &inner_world, max_depth) + pixel_color;
-                    }
-                    //Average colors
-                    let final_color = Color::from_vec3_float(pixel_color, samples_per_pixel);
-                    sub_img.put_pixel(x - start, y, Rgb(final_color.to_rgb()));
-                }
+use crate::utils::sampler::{Sampler, SamplerKind, SamplerState};
+use crate::utils::simd::{Realx4, RayPacket};
+use crate::utils::scene::Scene;
+use crate::utils::stats::{count_absorbed, count_escaped, count_ray, count_scatter, count_shadow_ray, start_thread, take_thread_stats, RenderStats};
+use crate::utils::vector::{Dot, Vec3};
+use crate::utils::wavefront::{Integrator, Wavefront, WAVE_SIZE};
+use crate::utils::real::Real;
 
-            }
+// Side of the square blocks the image is cut into, except by `Strategy::Rows`. Each one is
+// reported to the observer as it is rendered.
+const TILE_SIZE: u32 = 32;
+
+// Samples per pixel a block takes at a time. The render checks whether to stop between
+// pixels, so with `Strategy::SamplePasses` a render that stops early is evenly sampled to
+// within this many.
+const SAMPLES_PER_PASS: u32 = 8;
+
+// Camera rays the megakernel intersects together, as a `RayPacket`
+const PACKET_SIZE: usize = 4;
+
+#[derive(Clone)]
+pub struct RenderSettings {
+    pub image_width: u32,
+    pub image_height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: i32,
+    // Threads in the pool started for the render
+    pub num_threads: u32,
+    // Pin each of those threads to its own core
+    pub pin_threads: bool,
+    // Render on this pool instead of starting one, so that an application embedding the
+    // renderer doesn't oversubscribe the machine. `num_threads` and `pin_threads` are ignored.
+    pub pool: Option<RenderPool>,
+    // How the image is cut into jobs for the pool
+    pub strategy: Strategy,
+    // How each job follows its paths: one at a time, or in waves
+    pub integrator: Integrator,
+    pub output_file: String,
+    // Auxiliary buffers to write alongside the beauty image
+    pub passes: Vec<Pass>,
+    pub aov_output: AovOutput,
+    // Filter the image using the albedo and normal buffers. The unfiltered image is kept
+    // next to the output as `<output>.noisy.png`
+    pub denoise: Option<DenoiseSettings>,
+    // Source of the random numbers for pixel jitter, depth of field, lights and materials
+    pub sampler: SamplerKind,
+    // Renders with the same seed and settings produce the same image
+    pub seed: u64,
+    // How samples are weighted into the pixels around them
+    pub filter: Filter,
+    // Also write the render statistics to this file as JSON
+    pub stats_file: Option<String>,
+    // Cancelling this stops the render, keeping the samples taken so far
+    pub cancel: Option<CancellationToken>,
+    // Stop taking samples once this much time has passed. `samples_per_pixel` still caps the
+    // samples, so set it high to spend the whole budget.
+    pub time_limit: Option<Duration>,
+}
+
+impl Default for RenderSettings {
+    // The binary's settings: 900x600 at 200 samples per pixel on every CPU
+    fn default() -> Self {
+        RenderSettings {
+            image_width: 900,
+            image_height: 600,
+            samples_per_pixel: 200,
+            max_depth: 5,
+            num_threads: num_cpus::get() as u32,
+            pin_threads: false,
+            pool: None,
+            strategy: Strategy::default(),
+            integrator: Integrator::default(),
+            output_file: "output.png".to_string(),
+            passes: vec![],
+            aov_output: AovOutput::MultiLayerExr,
+            denoise: None,
+            sampler: SamplerKind::Sobol,
+            seed: 0,
+            filter: Filter::default(),
+            stats_file: None,
+            cancel: None,
+            time_limit: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderStatus {
+    // Every pixel has all of its samples
+    Complete,
+    // The settings' cancellation token was cancelled first
+    Cancelled,
+    // The time limit ran out first
+    TimedOut,
+}
+
+impl RenderSettings {
+    // Catches settings that would otherwise divide by zero or quietly render nothing
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |message: &str| Err(RenderError::InvalidSettings(message.to_string()));
+        if self.image_width == 0 || self.image_height == 0 {
+            return invalid(&format!("the image is {}x{} pixels", self.image_width, self.image_height));
+        }
+        if self.image_width.checked_mul(self.image_height).is_none() {
+            return invalid(&format!("the image is {}x{} pixels, too many to count", self.image_width, self.image_height));
+        }
+        if self.samples_per_pixel == 0 {
+            return invalid("samples_per_pixel is 0");
+        }
+        if self.pool.is_none() && self.num_threads == 0 {
+            return invalid("num_threads is 0");
+        }
+        Ok(())
+    }
+}
 
-            let mut img_data = inner_img.lock().unwrap();
-            for x in start..end {
-                for y in 0..image_height {
-                    img_data.put_pixel(x, y, *sub_img.get_pixel((x - start) as u32, y));
+// Everything a render produces, held in memory until `save` writes it out
+pub struct RenderOutput {
+    // Linear radiance with the camera's exposure applied. Denoised if the settings asked for it.
+    pub image: Framebuffer,
+    // The image before denoising, when the denoiser ran
+    pub noisy: Option<Framebuffer>,
+    // Resolved auxiliary passes that were asked for
+    pub aovs: AovBuffers,
+    // Counters merged from every render thread
+    pub stats: RenderStats,
+    // Samples taken in each pixel, row-major. Less than `samples_per_pixel` where the render
+    // stopped early, and the image is averaged over however many there were.
+    pub sample_counts: Vec<u32>,
+    pub status: RenderStatus,
+}
+
+impl RenderOutput {
+    // One of the auxiliary passes, row-major with the pass's channels interleaved
+    pub fn aov(&self, pass: Pass) -> Option<&[f32]> {
+        self.aovs.channel_data(pass)
+    }
+
+    pub fn sample_count(&self, x: u32, y: u32) -> u32 {
+        self.sample_counts[(y * self.image.width() + x) as usize]
+    }
+
+    // Writes the image to `settings.output_file`, along with the noisy image, the passes and
+    // the statistics where the settings ask for them. Returns the files written. If the image
+    // can't be written there, it is saved to the temporary directory as a linear OpenEXR file
+    // instead, so that a long render isn't lost to a typo in the path or to the PNG encoder
+    // failing, and the error says where it went.
+    pub fn save(&self, settings: &RenderSettings) -> Result<Vec<PathBuf>> {
+        let output_file = settings.output_file.as_str();
+        let mut written = vec![];
+
+        if let Err(error) = self.image.save_png(output_file) {
+            return Err(match error {
+                RenderError::Image { path, source, .. } => {
+                    let fallback = fallback_path(&path);
+                    let fallback = self.image.save_exr(&fallback).ok().map(|_| fallback);
+                    RenderError::Image { path, source, fallback }
                 }
-            }
-        }));
+                error => error,
+            });
+        }
+        written.push(PathBuf::from(output_file));
+
+        if let Some(noisy) = &self.noisy {
+            let noisy_file = Path::new(output_file).with_extension("noisy.png");
+            noisy.save_png(&noisy_file)?;
+            written.push(noisy_file);
+        }
+
+        if !self.aovs.is_empty() {
+            let beauty = self.noisy.as_ref().unwrap_or(&self.image);
+            written.extend(self.aovs.save(settings.aov_output, output_file, beauty.pixels())?);
+        }
+
+        if let Some(stats_file) = &settings.stats_file {
+            std::fs::write(stats_file, self.stats.to_json()).map_err(|e| RenderError::io(stats_file, e))?;
+            written.push(PathBuf::from(stats_file));
+        }
+        Ok(written)
     }
+}
+
+// Same file name in the temporary directory, with the OpenEXR extension
+fn fallback_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map_or("output.exr".into(), |name| name.to_os_string());
+    std::env::temp_dir().join(name).with_extension("exr")
+}
+
+// Renders `scene` into memory, denoising the result if the settings ask for it. Nothing is
+// written to disk. Pass an `Arc<Scene>` to render the same scene more than once.
+pub fn render(scene: impl Into<Arc<Scene>>, settings: &RenderSettings) -> Result<RenderOutput> {
+    render_with_observer(scene, settings, &())
+}
 
-    for _ in 0..num_threads {
+// Same as `render`, telling `observer` about each finished tile and the progress made
+pub fn render_with_observer(scene: impl Into<Arc<Scene>>, settings: &RenderSettings, observer: &dyn RenderObserver) -> Result<RenderOutput> {
+    settings.validate()?;
+    let traced = trace_image(scene.into(), settings, observer)?;
+    let (image, mut aovs) = (traced.image, traced.aovs);
 
+    let (image, noisy) = match &settings.denoise {
+        Some(denoise_settings) => {
+            let denoised = denoise(
+                image.pixels(),
+                aovs.channel_data(Pass::Albedo).unwrap(),
+                aovs.channel_data(Pass::Normal).unwrap(),
+                settings.image_width,
+                settings.image_height,
+                denoise_settings
+            );
+            (Framebuffer::from_pixels(settings.image_width, settings.image_height, denoised), Some(image))
+        }
+        None => (image, None),
+    };
+
+    // Drop the buffers that were only recorded for the denoiser
+    aovs.retain(&settings.passes);
+    let status = if traced.sample_counts.iter().all(|&n| n == settings.samples_per_pixel) {
+        RenderStatus::Complete
+    } else if settings.cancel.as_ref().is_some_and(CancellationToken::is_cancelled) {
+        RenderStatus::Cancelled
+    } else {
+        RenderStatus::TimedOut
+    };
+    Ok(RenderOutput { image, noisy, aovs, stats: traced.stats, sample_counts: traced.sample_counts, status })
+}
+
+struct TracedImage {
+    // With the camera's exposure applied
+    image: Framebuffer,
+    // Including those the denoiser needs
+    aovs: AovBuffers,
+    stats: RenderStats,
+    sample_counts: Vec<u32>,
+}
+
+// Traces the samples on the thread pool until they are all taken, the render is cancelled or
+// the time limit runs out
+fn trace_image(scene: Arc<Scene>, settings: &RenderSettings, observer: &dyn RenderObserver) -> Result<TracedImage> {
+    let image_width = settings.image_width;
+    let image_height = settings.image_height;
+    let samples_per_pixel = settings.samples_per_pixel;
+
+    // The denoiser is guided by albedo and normals whether or not they were asked for
+    let mut passes = settings.passes.clone();
+    if settings.denoise.is_some() {
+        for pass in [Pass::Albedo, Pass::Normal] {
+            if !passes.contains(&pass) {
+                passes.push(pass);
+            }
+        }
     }
 
-    for thread in threads {
-        thread.join().unwrap();
+    let pool = match &settings.pool {
+        Some(pool) => pool.clone(),
+        None => RenderPool::new(settings.num_threads, settings.pin_threads)?,
+    };
+    let render_start = Instant::now();
+    let samples_total = (image_width * image_height) as u64 * samples_per_pixel as u64;
+    let tracer = Tracer {
+        scene: &scene,
+        settings,
+        observer,
+        exposure: scene.camera.exposure(),
+        render_start,
+        deadline: settings.time_limit.map(|limit| render_start + limit),
+        failed: AtomicBool::new(false),
+        error: Mutex::new(None),
+        stats: Mutex::new(RenderStats::default()),
+        samples_done: AtomicU64::new(0),
+        samples_total,
+        progress_step: ((samples_total as f64 * PROGRESS_STEP as f64).ceil() as u64).max(1),
+    };
+
+    let mut blocks = Block::cover(image_width, image_height, settings.strategy, pool.num_threads(), settings.filter, &passes);
+    let sample_passes = (0..samples_per_pixel).step_by(SAMPLES_PER_PASS as usize)
+        .map(|start| start..start.saturating_add(SAMPLES_PER_PASS).min(samples_per_pixel));
+    pool.install(|| match settings.strategy {
+        Strategy::Rows | Strategy::Tiles => blocks.par_iter_mut().for_each(|block| {
+            for pass in sample_passes.clone() {
+                if !tracer.render_pass(block, pass) {
+                    break;
+                }
+            }
+        }),
+        Strategy::SamplePasses => {
+            for pass in sample_passes {
+                blocks.par_iter_mut().for_each(|block| {
+                    tracer.render_pass(block, pass.clone());
+                });
+                if tracer.should_stop() {
+                    break;
+                }
+            }
+        }
+        Strategy::Samples => {
+            // Stream i takes the i-th run of SAMPLES_PER_PASS samples in each pass
+            let pass_size = SAMPLES_PER_PASS.saturating_mul(blocks.len() as u32);
+            for pass_start in (0..samples_per_pixel).step_by(pass_size as usize) {
+                blocks.par_iter_mut().enumerate().for_each(|(i, block)| {
+                    let start = pass_start.saturating_add(i as u32 * SAMPLES_PER_PASS).min(samples_per_pixel);
+                    let end = start.saturating_add(SAMPLES_PER_PASS).min(samples_per_pixel);
+                    if start < end {
+                        tracer.render_pass(block, start..end);
+                    }
+                });
+                // The streams only make sense together, so the observer sees the whole frame
+                let (film, _, _) = merge_blocks(&blocks, image_width, image_height, settings.filter, &[]);
+                let samples = pass_start.saturating_add(pass_size).min(samples_per_pixel);
+                tracer.guard(|| {
+                    tracer.report(&film, 0, 0, image_width, image_height, samples);
+                    Ok(true)
+                });
+                if tracer.should_stop() {
+                    break;
+                }
+            }
+        }
+    });
+
+    if let Some(error) = lock(&tracer.error)?.take() {
+        return Err(error);
     }
 
+    let (film, mut aovs, sample_counts) = merge_blocks(&blocks, image_width, image_height, settings.filter, &passes);
+
     /*let mut rng = thread_rng();
     for x in 0..image_width {
         if (image_width - x - 1) % 50 == 0 {
@@ -93,11 +352,11 @@ pub fn render(camera: Camera,
             let mut pixel_color = Vec3::new(0.0, 0.0, 0.0);
             //Multisampling
             for _ in 0..samples_per_pixel {
-                let ru: f32 = rng.gen();
-                let rv: f32 = rng.gen();
+                let ru: Real = rng.gen();
+                let rv: Real = rng.gen();
 
-                let u = ((x as f32) + ru) / ((image_width-1) as f32);
-                let v = ((y as f32) + rv) / ((image_height-1) as f32);
+                let u = ((x as Real) + ru) / ((image_width-1) as Real);
+                let v = ((y as Real) + rv) / ((image_height-1) as Real);
 
                 let ray = self.get_ray(u, v);
                 pixel_color = ray_color_vec3_float(&ray, &world, max_depth) + pixel_color;
@@ -108,31 +367,363 @@ pub fn render(camera: Camera,
         }
     }*/
 
-    let final_img = arc_img.lock().unwrap();
-    final_img.save_with_format(output_file, image::ImageFormat::Png).unwrap();
-    println!("Finished Rendering!");
-}
-
-//Returns the ray's color but in the form of an f32 vec so it can be summed and then passed to ray_color for an anti-aliased clamped average
-pub fn ray_color_vec3_float(ray: &Ray, world: &HittableList, depth: i32) -> Vec3<f32> {
-    world.hit(ray, 0.001, f32::INFINITY).and_then(|rec| {
-        if rec.material.is_some() {
-            return rec.material.clone().map(|mat| {
-                if depth >= 0 {
-                    let (attenuation, scattered, should_scatter) = scatter(&mat, ray, &rec);
-                    if should_scatter {
-                        let pixel = ray_color_vec3_float(&scattered, world, depth - 1);
-                        return Vec3::new(attenuation.x * pixel.x, attenuation.y * pixel.y, attenuation.z * pixel.z);
+    aovs.resolve();
+    let linear = film.resolve().into_iter().map(|c| (c * tracer.exposure).to_f32()).collect();
+    let stats = lock(&tracer.stats)?.clone();
+
+    Ok(TracedImage { image: Framebuffer::from_pixels(image_width, image_height, linear), aovs, stats, sample_counts })
+}
+
+// Adds the blocks' samples into one image. Always in the same order, so where filter
+// footprints overlap or sample streams cover the same pixels, the sums don't depend on which
+// thread finished first.
+fn merge_blocks(blocks: &[Block], image_width: u32, image_height: u32, filter: Filter, passes: &[Pass]) -> (Film, AovBuffers, Vec<u32>) {
+    let mut film = Film::new(image_width, image_height, filter);
+    let mut aovs = AovBuffers::new(image_width, image_height, passes);
+    let mut sample_counts = vec![0u32; (image_width * image_height) as usize];
+    for block in blocks {
+        film.merge(&block.film);
+        if !passes.is_empty() {
+            aovs.add_block(&block.aovs, block.x0, block.y0);
+        }
+        for y in 0..block.height {
+            for x in 0..block.width {
+                sample_counts[((block.y0 + y) * image_width + block.x0 + x) as usize] += block.sample_counts[(y * block.width + x) as usize];
+            }
+        }
+    }
+    (film, aovs, sample_counts)
+}
+
+// A row or tile of the image, or the whole frame for one stream of samples, and everything
+// rendered into it so far
+struct Block {
+    x0: u32,
+    y0: u32,
+    width: u32,
+    height: u32,
+    // Also covers the filter's reach into the neighbouring blocks
+    film: Film,
+    aovs: AovBuffers,
+    sample_counts: Vec<u32>,
+}
+
+impl Block {
+    fn new(x0: u32, y0: u32, width: u32, height: u32, filter: Filter, passes: &[Pass]) -> Self {
+        Block {
+            x0,
+            y0,
+            width,
+            height,
+            film: Film::tile_for_block(x0, y0, width, height, filter),
+            aovs: AovBuffers::new(width, height, passes),
+            sample_counts: vec![0; (width * height) as usize],
+        }
+    }
+
+    // The blocks a strategy splits the image into, in row-major order, or one whole frame
+    // per thread for `Strategy::Samples`
+    fn cover(image_width: u32, image_height: u32, strategy: Strategy, num_threads: usize, filter: Filter, passes: &[Pass]) -> Vec<Block> {
+        let (block_width, block_height) = match strategy {
+            Strategy::Rows => (image_width, 1),
+            Strategy::Tiles | Strategy::SamplePasses => (TILE_SIZE, TILE_SIZE),
+            Strategy::Samples => {
+                return (0..num_threads.max(1)).map(|_| Block::new(0, 0, image_width, image_height, filter, passes)).collect();
+            }
+        };
+
+        let mut blocks = vec![];
+        for y0 in (0..image_height).step_by(block_height as usize) {
+            for x0 in (0..image_width).step_by(block_width as usize) {
+                let width = block_width.min(image_width - x0);
+                let height = block_height.min(image_height - y0);
+                blocks.push(Block::new(x0, y0, width, height, filter, passes));
+            }
+        }
+        blocks
+    }
+}
+
+// What every job of a render shares
+struct Tracer<'a> {
+    scene: &'a Scene,
+    settings: &'a RenderSettings,
+    observer: &'a dyn RenderObserver,
+    exposure: Real,
+    render_start: Instant,
+    deadline: Option<Instant>,
+    // Set when a job panics, so that the others stop instead of carrying on for nothing
+    failed: AtomicBool,
+    error: Mutex<Option<RenderError>>,
+    stats: Mutex<RenderStats>,
+    samples_done: AtomicU64,
+    samples_total: u64,
+    progress_step: u64,
+}
+
+impl Tracer<'_> {
+    fn should_stop(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+            || self.settings.cancel.as_ref().is_some_and(CancellationToken::is_cancelled)
+            || self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    // Takes samples `pass` of every pixel in the block and, unless it is one stream of
+    // `Strategy::Samples`, shows the observer the block. Returns false if the render stopped
+    // first. A panic is caught and kept as the render's error rather than taking down the pool.
+    fn render_pass(&self, block: &mut Block, pass: Range<u32>) -> bool {
+        self.guard(|| {
+            start_thread(self.settings.max_depth);
+            let completed = self.trace_block(block, pass.clone());
+            lock(&self.stats)?.merge(&take_thread_stats());
+            if self.settings.strategy != Strategy::Samples {
+                self.report(&block.film, block.x0, block.y0, block.width, block.height, pass.end);
+            }
+            Ok(completed)
+        })
+    }
+
+    // Runs `f`, keeping its error, or the message of a panic in it, as the render's error
+    fn guard(&self, f: impl FnOnce() -> Result<bool>) -> bool {
+        let result = catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| Err(RenderError::worker(payload.as_ref())));
+        result.unwrap_or_else(|error| {
+            self.failed.store(true, Ordering::Relaxed);
+            if let Ok(mut first) = self.error.lock() {
+                first.get_or_insert(error);
+            }
+            false
+        })
+    }
+
+    fn trace_block(&self, block: &mut Block, pass: Range<u32>) -> bool {
+        let mut sampler = self.settings.sampler.create(self.settings.samples_per_pixel, self.settings.seed);
+        let (samples, stopped) = match self.settings.integrator {
+            Integrator::Megakernel => self.trace_paths(block, pass, sampler.as_mut()),
+            Integrator::Wavefront => self.trace_waves(block, pass, sampler.as_mut()),
+        };
+
+        let done = self.samples_done.fetch_add(samples, Ordering::Relaxed) + samples;
+        if done / self.progress_step > (done - samples) / self.progress_step || (samples > 0 && done == self.samples_total) {
+            self.observer.progress(Progress { samples_done: done, samples_total: self.samples_total, elapsed: self.render_start.elapsed() });
+        }
+        !stopped
+    }
+
+    // Follows each path to the end before starting the next. Returns the samples taken and
+    // whether the render stopped first.
+    fn trace_paths(&self, block: &mut Block, pass: Range<u32>, sampler: &mut dyn Sampler) -> (u64, bool) {
+        let scene = self.scene;
+        let image_width = self.settings.image_width;
+        let image_height = self.settings.image_height;
+        let max_depth = self.settings.max_depth;
+        let mut samples = 0;
+        let mut camera_samples = Vec::with_capacity(PACKET_SIZE);
+        let packets = scene.world.packets_pay_off();
+
+        for x in block.x0..block.x0 + block.width {
+            for y in block.y0..block.y0 + block.height {
+                // Checked between pixels, so a pixel always gets its whole pass
+                if self.should_stop() {
+                    return (samples, true);
+                }
+
+                //Multisampling, four samples at a time so their camera rays are intersected as a packet
+                for first in pass.clone().step_by(PACKET_SIZE) {
+                    camera_samples.clear();
+                    for s in first..(first + PACKET_SIZE as u32).min(pass.end) {
+                        sampler.start_pixel_sample(x, y, s);
+                        let (ru, rv) = sampler.get_2d();
+
+                        let u = ((x as Real) + ru) / ((image_width-1).max(1) as Real);
+                        let v = ((y as Real) + rv) / ((image_height-1).max(1) as Real);
+
+                        // Outside the area covered by the camera, e.g. the corners of a circular fisheye
+                        let Some(ray) = scene.camera.get_ray(u, v, sampler) else {
+                            block.aovs.add_sample(x - block.x0, y - block.y0, None);
+                            block.film.add_sample(x as Real + ru, y as Real + rv, Vec3::new(0.0, 0.0, 0.0));
+                            continue;
+                        };
+                        count_ray(max_depth);
+                        camera_samples.push((x as Real + ru, y as Real + rv, ray, sampler.state()));
+                    }
+
+                    for ((film_x, film_y, ray, state), rec) in camera_samples.iter().zip(first_hits(scene, &camera_samples, packets)) {
+                        // Carry on drawing numbers where this sample's camera ray left off
+                        sampler.resume(*state);
+                        if !block.aovs.is_empty() {
+                            let first_hit = rec.as_ref().map(|rec| FirstHit::new(ray, rec));
+                            block.aovs.add_sample(x - block.x0, y - block.y0, first_hit.as_ref());
+                        }
+                        block.film.add_sample(*film_x, *film_y, radiance(ray, rec, scene, max_depth, None, sampler));
                     }
                 }
-                Vec3::new(0.0, 0.0, 0.0)
-            })
-        } else {
-            None
-        }
-    }).unwrap_or_else(|| {
-        let unit_direction = ray.direction.normalise();
-        let t = 0.5 * (unit_direction.y + 1.0);
-        Vec3::new(1.0, 1.0, 1.0) * (1.0 - t) + Vec3::new(0.5, 0.7, 1.0) * t
+                block.sample_counts[((y - block.y0) * block.width + x - block.x0) as usize] += pass.end - pass.start;
+                samples += (pass.end - pass.start) as u64;
+            }
+        }
+        (samples, false)
+    }
+
+    // Takes the pixels in the same order as `trace_paths`, a wave of them at a time
+    fn trace_waves(&self, block: &mut Block, pass: Range<u32>, sampler: &mut dyn Sampler) -> (u64, bool) {
+        let pixels: Vec<(u32, u32)> = (block.x0..block.x0 + block.width)
+            .flat_map(|x| (block.y0..block.y0 + block.height).map(move |y| (x, y)))
+            .collect();
+        let settings = self.settings;
+        let mut wavefront = Wavefront::new(self.scene, settings.image_width, settings.image_height, settings.max_depth, !block.aovs.is_empty());
+        let mut samples = 0;
+
+        for wave in pixels.chunks((WAVE_SIZE / (pass.end - pass.start).max(1) as usize).max(1)) {
+            // Checked between waves, so a pixel always gets its whole pass
+            if self.should_stop() {
+                return (samples, true);
+            }
+
+            for sample in wavefront.trace(wave, pass.clone(), sampler) {
+                if !block.aovs.is_empty() {
+                    block.aovs.add_sample(sample.x - block.x0, sample.y - block.y0, sample.first_hit.as_ref());
+                }
+                block.film.add_sample(sample.film_x, sample.film_y, sample.radiance);
+            }
+            for &(x, y) in wave {
+                block.sample_counts[((y - block.y0) * block.width + x - block.x0) as usize] += pass.end - pass.start;
+                samples += (pass.end - pass.start) as u64;
+            }
+        }
+        (samples, false)
+    }
+
+    // Shows the observer pixels x0..x0 + width, y0..y0 + height of `film`
+    fn report(&self, film: &Film, x0: u32, y0: u32, width: u32, height: u32, samples: u32) {
+        let tile = Framebuffer::from_pixels(width, height, (y0..y0 + height)
+            .flat_map(|y| (x0..x0 + width).map(move |x| (x, y)))
+            .map(|(x, y)| (film.resolve_pixel(x as i32, y as i32) * self.exposure).to_f32())
+            .collect());
+        self.observer.tile_completed(&TileUpdate { x0, y0, pixels: &tile, samples });
+    }
+}
+
+// A lock is only poisoned by a job panicking while it held it
+fn lock<T>(mutex: &Mutex<T>) -> Result<MutexGuard<'_, T>> {
+    mutex.lock().map_err(|_| RenderError::Worker("a render job panicked while holding a lock".to_string()))
+}
+
+//Returns the ray's color but in the form of a Real vec so it can be summed and then passed to ray_color for an anti-aliased clamped average
+pub fn ray_color_vec3_float(ray: &Ray, scene: &Scene, depth: i32, sampler: &mut dyn Sampler) -> Vec3<Real> {
+    trace(ray, scene, depth, None, sampler)
+}
+
+// `scattering_pdf` is the density with which the previous bounce chose this ray, or `None`
+// for camera rays and specular bounces, which light sampling could not have produced
+fn trace(ray: &Ray, scene: &Scene, depth: i32, scattering_pdf: Option<Real>, sampler: &mut dyn Sampler) -> Vec3<Real> {
+    radiance(ray, scene.world.hit(ray, 0.0, Real::INFINITY), scene, depth, scattering_pdf, sampler)
+}
+
+// The light along `ray`, given what it hits first
+fn radiance(ray: &Ray, rec: Option<HitRecord>, scene: &Scene, depth: i32, scattering_pdf: Option<Real>, sampler: &mut dyn Sampler) -> Vec3<Real> {
+    rec.and_then(|rec| {
+        shade(ray, &rec, scene, depth, sampler)
+    }).unwrap_or_else(|| escaped(ray, scene, scattering_pdf))
+}
+
+// What each camera ray hits first. If the world is faster that way, a full packet is
+// intersected four rays at a time, which finds the same hits as tracing them one by one.
+fn first_hits(scene: &Scene, camera_samples: &[(Real, Real, Ray, SamplerState)], packets: bool) -> [Option<HitRecord>; PACKET_SIZE] {
+    if let ([a, b, c, d], true) = (camera_samples, packets) {
+        let packet = RayPacket::new([a.2, b.2, c.2, d.2]);
+        return scene.world.hit_packet(&packet, 0.0, Realx4::splat(Real::INFINITY));
+    }
+    std::array::from_fn(|i| camera_samples.get(i).and_then(|(_, _, ray, _)| scene.world.hit(ray, 0.0, Real::INFINITY)))
+}
+
+// Same as `ray_color_vec3_float`, also reporting what the ray hit first for the AOV passes
+pub fn ray_color_with_first_hit(ray: &Ray, scene: &Scene, depth: i32, sampler: &mut dyn Sampler) -> (Vec3<Real>, Option<FirstHit>) {
+    let rec = scene.world.hit(ray, 0.0, Real::INFINITY);
+    let first_hit = rec.as_ref().map(|rec| FirstHit::new(ray, rec));
+    (radiance(ray, rec, scene, depth, None, sampler), first_hit)
+}
+
+fn shade(ray: &Ray, rec: &HitRecord, scene: &Scene, depth: i32, sampler: &mut dyn Sampler) -> Option<Vec3<Real>> {
+    rec.material.as_ref().map(|mat| {
+        if depth >= 0 {
+            let direct = sample_lights(scene, mat, ray, rec, sampler);
+            let (attenuation, scattered, should_scatter) = scatter(mat, ray, rec, sampler);
+            if should_scatter {
+                count_scatter(mat);
+                let pdf = mat.scattering_pdf(ray, rec, scattered.direction);
+                count_ray(depth - 1);
+                let pixel = trace(&scattered, scene, depth - 1, pdf, sampler);
+                return direct + attenuation * pixel;
+            }
+            count_absorbed();
+            return direct;
+        }
+        Vec3::new(0.0, 0.0, 0.0)
     })
-}
\ No newline at end of file
+}
+
+pub(crate) fn escaped(ray: &Ray, scene: &Scene, scattering_pdf: Option<Real>) -> Vec3<Real> {
+    count_escaped();
+    let radiance = scene.environment.radiance(ray.direction);
+    match scattering_pdf {
+        Some(pdf) if scene.environment.is_sampled() => {
+            radiance * power_heuristic(pdf, scene.environment.pdf(ray.direction))
+        }
+        _ => radiance,
+    }
+}
+
+// Next event estimation: shadow rays towards every delta light, plus one towards an
+// importance sampled environment weighted against the chance of `scatter` reaching it
+fn sample_lights(scene: &Scene, material: &Material, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Vec3<Real> {
+    let mut direct = Vec3::new(0.0, 0.0, 0.0);
+    if material.is_specular() {
+        return direct;
+    }
+
+    for light in scene.lights.lights.iter() {
+        let Some(sample) = light.sample(rec.p) else {
+            continue;
+        };
+        if sample.direction.dot(rec.normal) <= 0.0 {
+            continue;
+        }
+
+        let shadow_ray = rec.spawn_ray(sample.direction);
+        count_shadow_ray();
+        if scene.world.hit(&shadow_ray, 0.0, sample.distance).is_none() {
+            direct = direct + material.eval(r_in, rec, sample.direction) * sample.radiance;
+        }
+    }
+
+    if scene.environment.is_sampled() {
+        direct = direct + sample_environment(scene, material, r_in, rec, sampler);
+    }
+    direct
+}
+
+fn sample_environment(scene: &Scene, material: &Material, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Vec3<Real> {
+    let none = Vec3::new(0.0, 0.0, 0.0);
+    let Some((direction, radiance, light_pdf)) = scene.environment.sample(sampler.get_2d()) else {
+        return none;
+    };
+    if direction.dot(rec.normal) <= 0.0 {
+        return none;
+    }
+
+    let shadow_ray = rec.spawn_ray(direction);
+    count_shadow_ray();
+    if scene.world.hit(&shadow_ray, 0.0, Real::INFINITY).is_some() {
+        return none;
+    }
+
+    let f = material.eval(r_in, rec, direction);
+    let weight = power_heuristic(light_pdf, material.scattering_pdf(r_in, rec, direction).unwrap_or(0.0));
+    f * radiance * (weight / light_pdf)
+}
+
+pub(crate) fn power_heuristic(f_pdf: Real, g_pdf: Real) -> Real {
+    let f = f_pdf * f_pdf;
+    let g = g_pdf * g_pdf;
+    if f + g == 0.0 { 0.0 } else { f / (f + g) }
+}
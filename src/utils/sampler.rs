@@ -0,0 +1,542 @@
+use std::sync::OnceLock;
+use crate::utils::real::Real;
+
+// Hands out the random numbers used by one camera sample. Every call to `get_1d` or
+// `get_2d` consumes the next dimension, so a path asks for its numbers in the same order
+// (pixel jitter, lens, then per bounce light sampling and scattering) for every sample.
+pub trait Sampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32);
+    fn get_1d(&mut self) -> Real;
+    fn get_2d(&mut self) -> (Real, Real);
+    // Where the current sample has got to, so that one sampler can interleave many paths
+    fn state(&self) -> SamplerState;
+    // Carries on from `state` as if nothing had been drawn since
+    fn resume(&mut self, state: SamplerState);
+}
+
+// Everything that changes as a sampler hands out one sample's numbers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SamplerState {
+    pub pixel: (u32, u32),
+    pub sample_index: u32,
+    pub dimension: u32,
+    // The independent sampler's position in its stream, which the stratified and Halton
+    // samplers use for jitter and high dimensions
+    pub stream: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplerKind {
+    // Uncorrelated random numbers, as the renderer has always used
+    Independent,
+    // Jittered strata, shuffled independently in every dimension
+    Stratified,
+    // Halton sequence with per-pixel Owen scrambling of its digits
+    Halton,
+    // Sobol (0,2)-sequence padded over dimension pairs, with hash-based Owen scrambling
+    Sobol,
+    // One Sobol sequence shared by every pixel, shifted by a blue-noise mask so that the
+    // remaining error is spread at high frequencies across the image
+    BlueNoise,
+}
+
+impl SamplerKind {
+    pub fn create(self, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler + Send> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(samples_per_pixel, seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+            SamplerKind::BlueNoise => Box::new(BlueNoiseSampler::new(seed)),
+        }
+    }
+}
+
+// Largest Real below one, so that samples stay in [0, 1)
+const ONE_MINUS_EPSILON: Real = 1.0 - Real::EPSILON / 2.0;
+
+pub struct IndependentSampler {
+    seed: u64,
+    state: u64,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        IndependentSampler { seed, state: 0 }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.state = hash(&[x as u64, y as u64, sample_index as u64, self.seed]);
+    }
+
+    fn get_1d(&mut self) -> Real {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        to_unit_float(mix(self.state) as u32)
+    }
+
+    fn get_2d(&mut self) -> (Real, Real) {
+        (self.get_1d(), self.get_1d())
+    }
+
+    fn state(&self) -> SamplerState {
+        SamplerState { stream: self.state, ..SamplerState::default() }
+    }
+
+    fn resume(&mut self, state: SamplerState) {
+        self.state = state.stream;
+    }
+}
+
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    x_strata: u32,
+    y_strata: u32,
+    seed: u64,
+    pixel: (u32, u32),
+    sample_index: u32,
+    dimension: u32,
+    jitter: IndependentSampler,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        let x_strata = (samples_per_pixel as Real).sqrt() as u32;
+        StratifiedSampler {
+            samples_per_pixel,
+            x_strata,
+            y_strata: samples_per_pixel / x_strata,
+            seed,
+            pixel: (0, 0),
+            sample_index: 0,
+            dimension: 0,
+            jitter: IndependentSampler::new(seed ^ 0x5851f42d4c957f2d),
+        }
+    }
+
+    fn stratum(&mut self, count: u32) -> u32 {
+        let key = hash(&[self.pixel.0 as u64, self.pixel.1 as u64, self.dimension as u64, self.seed]);
+        self.dimension += 1;
+        permutation_element(self.sample_index % count, count, key as u32)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.pixel = (x, y);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+        self.jitter.start_pixel_sample(x, y, sample_index);
+    }
+
+    fn get_1d(&mut self) -> Real {
+        let stratum = self.stratum(self.samples_per_pixel);
+        ((stratum as Real + self.jitter.get_1d()) / self.samples_per_pixel as Real).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (Real, Real) {
+        let stratum = self.stratum(self.x_strata * self.y_strata);
+        let (sx, sy) = (stratum % self.x_strata, stratum / self.x_strata);
+        let (dx, dy) = self.jitter.get_2d();
+        (
+            ((sx as Real + dx) / self.x_strata as Real).min(ONE_MINUS_EPSILON),
+            ((sy as Real + dy) / self.y_strata as Real).min(ONE_MINUS_EPSILON),
+        )
+    }
+
+    fn state(&self) -> SamplerState {
+        SamplerState { pixel: self.pixel, sample_index: self.sample_index, dimension: self.dimension, stream: self.jitter.state }
+    }
+
+    fn resume(&mut self, state: SamplerState) {
+        (self.pixel, self.sample_index, self.dimension, self.jitter.state) = (state.pixel, state.sample_index, state.dimension, state.stream);
+    }
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+];
+
+pub struct HaltonSampler {
+    // Number of digits the sample indices take up in each prime base
+    digits: [u32; PRIMES.len()],
+    seed: u64,
+    pixel: (u32, u32),
+    sample_index: u32,
+    dimension: u32,
+    fallback: IndependentSampler,
+}
+
+impl HaltonSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        let digits = PRIMES.map(|base| {
+            let (mut count, mut n) = (0, 1u64);
+            while n < samples_per_pixel as u64 {
+                n *= base as u64;
+                count += 1;
+            }
+            count
+        });
+        HaltonSampler { digits, seed, pixel: (0, 0), sample_index: 0, dimension: 0, fallback: IndependentSampler::new(seed) }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.pixel = (x, y);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+        self.fallback.start_pixel_sample(x, y, sample_index);
+    }
+
+    fn get_1d(&mut self) -> Real {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        // Halton points in high prime bases are poorly distributed, so switch to random numbers
+        if dimension as usize >= PRIMES.len() {
+            return self.fallback.get_1d();
+        }
+
+        let key = hash(&[self.pixel.0 as u64, self.pixel.1 as u64, dimension as u64, self.seed]);
+        let d = dimension as usize;
+        owen_scrambled_radical_inverse(PRIMES[d], self.sample_index as u64, self.digits[d], key as u32)
+    }
+
+    fn get_2d(&mut self) -> (Real, Real) {
+        (self.get_1d(), self.get_1d())
+    }
+
+    fn state(&self) -> SamplerState {
+        SamplerState { pixel: self.pixel, sample_index: self.sample_index, dimension: self.dimension, stream: self.fallback.state }
+    }
+
+    fn resume(&mut self, state: SamplerState) {
+        (self.pixel, self.sample_index, self.dimension, self.fallback.state) = (state.pixel, state.sample_index, state.dimension, state.stream);
+    }
+}
+
+pub struct SobolSampler {
+    seed: u64,
+    pixel: (u32, u32),
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        SobolSampler { seed, pixel: (0, 0), sample_index: 0, dimension: 0 }
+    }
+
+    fn next_seed(&mut self) -> u64 {
+        let key = hash(&[self.pixel.0 as u64, self.pixel.1 as u64, self.dimension as u64, self.seed]);
+        self.dimension += 1;
+        key
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.pixel = (x, y);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> Real {
+        let key = self.next_seed();
+        // Shuffle the order points are visited in so that dimensions stay uncorrelated
+        let index = nested_uniform_scramble(self.sample_index, key as u32);
+        to_unit_float(nested_uniform_scramble(index.reverse_bits(), (key >> 32) as u32))
+    }
+
+    fn get_2d(&mut self) -> (Real, Real) {
+        let key = self.next_seed();
+        let index = nested_uniform_scramble(self.sample_index, key as u32);
+        let scramble = mix(key);
+        (
+            to_unit_float(nested_uniform_scramble(index.reverse_bits(), scramble as u32)),
+            to_unit_float(nested_uniform_scramble(sobol_second_dimension(index), (scramble >> 32) as u32)),
+        )
+    }
+
+    fn state(&self) -> SamplerState {
+        SamplerState { pixel: self.pixel, sample_index: self.sample_index, dimension: self.dimension, stream: 0 }
+    }
+
+    fn resume(&mut self, state: SamplerState) {
+        (self.pixel, self.sample_index, self.dimension) = (state.pixel, state.sample_index, state.dimension);
+    }
+}
+
+pub struct BlueNoiseSampler {
+    seed: u64,
+    pixel: (u32, u32),
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl BlueNoiseSampler {
+    pub fn new(seed: u64) -> Self {
+        BlueNoiseSampler { seed, pixel: (0, 0), sample_index: 0, dimension: 0 }
+    }
+
+    // Each dimension reads the mask at a different toroidal offset so that the shifts of
+    // different dimensions are uncorrelated
+    fn shift(&self, dimension: u32) -> Real {
+        let mask = blue_noise_mask();
+        let offset = hash(&[dimension as u64, self.seed]);
+        let x = (self.pixel.0 as u64 + (offset & 0xffff)) as usize % BLUE_NOISE_SIZE;
+        let y = (self.pixel.1 as u64 + ((offset >> 16) & 0xffff)) as usize % BLUE_NOISE_SIZE;
+        mask[y * BLUE_NOISE_SIZE + x]
+    }
+
+    fn toroidal(value: Real, shift: Real) -> Real {
+        let v = value + shift;
+        (v - v.floor()).min(ONE_MINUS_EPSILON)
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.pixel = (x, y);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> Real {
+        let key = hash(&[self.dimension as u64, self.seed]) as u32;
+        let value = to_unit_float(nested_uniform_scramble(self.sample_index.reverse_bits(), key));
+        let shifted = BlueNoiseSampler::toroidal(value, self.shift(self.dimension));
+        self.dimension += 1;
+        shifted
+    }
+
+    fn get_2d(&mut self) -> (Real, Real) {
+        let key = hash(&[self.dimension as u64, self.seed]);
+        let (u, v) = (
+            to_unit_float(nested_uniform_scramble(self.sample_index.reverse_bits(), key as u32)),
+            to_unit_float(nested_uniform_scramble(sobol_second_dimension(self.sample_index), (key >> 32) as u32)),
+        );
+        let sample = (
+            BlueNoiseSampler::toroidal(u, self.shift(self.dimension)),
+            BlueNoiseSampler::toroidal(v, self.shift(self.dimension + 1)),
+        );
+        self.dimension += 2;
+        sample
+    }
+
+    fn state(&self) -> SamplerState {
+        SamplerState { pixel: self.pixel, sample_index: self.sample_index, dimension: self.dimension, stream: 0 }
+    }
+
+    fn resume(&mut self, state: SamplerState) {
+        (self.pixel, self.sample_index, self.dimension) = (state.pixel, state.sample_index, state.dimension);
+    }
+}
+
+fn to_unit_float(bits: u32) -> Real {
+    ((bits >> 8) as Real * (1.0 / (1u32 << 24) as Real)).min(ONE_MINUS_EPSILON)
+}
+
+// The murmur3 64-bit finaliser
+fn mix(mut v: u64) -> u64 {
+    v ^= v >> 33;
+    v = v.wrapping_mul(0xff51afd7ed558ccd);
+    v ^= v >> 33;
+    v = v.wrapping_mul(0xc4ceb9fe1a85ec53);
+    v ^ (v >> 33)
+}
+
+fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x2545f4914f6cdd1d, |h, v| mix(h ^ mix(v.wrapping_add(0x9e3779b97f4a7c15))))
+}
+
+// Reverses the digits of `index` in `base` about the radix point, randomly permuting each
+// digit by a hash of the digits before it. Only the `digits` digits the sample indices use
+// are permuted; the infinitely many trailing zeros would be scrambled into a uniformly random
+// position within the last interval, so that is what gets added instead.
+fn owen_scrambled_radical_inverse(base: u32, mut index: u64, digits: u32, seed: u32) -> Real {
+    let inverse_base = 1.0 / base as f64;
+    let mut reversed = 0u64;
+    let mut inverse_base_n = 1.0f64;
+    let mut digit_count = 0;
+    while digit_count < digits || index > 0 {
+        let next = index / base as u64;
+        let digit = (index - next * base as u64) as u32;
+        let digit_seed = mix(seed as u64 ^ (reversed << 8) ^ digit_count as u64) as u32;
+        reversed = reversed * base as u64 + permutation_element(digit, base, digit_seed) as u64;
+        inverse_base_n *= inverse_base;
+        index = next;
+        digit_count += 1;
+    }
+
+    let tail = to_unit_float(mix((seed as u64) << 32 ^ reversed) as u32) as f64;
+    (((reversed as f64 + tail) * inverse_base_n) as Real).min(ONE_MINUS_EPSILON)
+}
+
+// Second dimension of the Sobol sequence, whose generator matrix is Pascal's triangle mod 2.
+// The first dimension is simply the bit reversed index.
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let mut v = 1u32 << 31;
+    let mut result = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    result
+}
+
+// Burley, "Practical Hash-based Owen Scrambling" (JCGT 2020)
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+// Element `i` of a random permutation of 0..n chosen by `seed`, without storing it
+// (Kensler, "Correlated Multi-Jittered Sampling")
+fn permutation_element(mut i: u32, n: u32, seed: u32) -> u32 {
+    let mut w = n.wrapping_sub(1);
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            break;
+        }
+    }
+    (i.wrapping_add(seed)) % n
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+// Thresholds in [0, 1) for a 64x64 tile, built once with Ulichney's void-and-cluster method
+fn blue_noise_mask() -> &'static [Real] {
+    static MASK: OnceLock<Vec<Real>> = OnceLock::new();
+    MASK.get_or_init(void_and_cluster)
+}
+
+fn void_and_cluster() -> Vec<Real> {
+    const N: usize = BLUE_NOISE_SIZE;
+    const RADIUS: i32 = 6;
+    let sigma: Real = 1.5;
+
+    let mut kernel: Vec<Real> = vec![0.0; ((2 * RADIUS + 1) * (2 * RADIUS + 1)) as usize];
+    for dy in -RADIUS..=RADIUS {
+        for dx in -RADIUS..=RADIUS {
+            kernel[((dy + RADIUS) * (2 * RADIUS + 1) + dx + RADIUS) as usize] = (-((dx * dx + dy * dy) as Real) / (2.0 * sigma * sigma)).exp();
+        }
+    }
+
+    // Energy is the Gaussian-filtered binary pattern, with the image wrapping around
+    let splat = |energy: &mut [Real], index: usize, sign: Real| {
+        let (x, y) = ((index % N) as i32, (index / N) as i32);
+        for dy in -RADIUS..=RADIUS {
+            for dx in -RADIUS..=RADIUS {
+                let px = (x + dx).rem_euclid(N as i32) as usize;
+                let py = (y + dy).rem_euclid(N as i32) as usize;
+                energy[py * N + px] += sign * kernel[((dy + RADIUS) * (2 * RADIUS + 1) + dx + RADIUS) as usize];
+            }
+        }
+    };
+    let tightest_cluster = |pattern: &[bool], energy: &[Real], value: bool| {
+        (0..N * N).filter(|&i| pattern[i] == value).max_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+    let largest_void = |pattern: &[bool], energy: &[Real], value: bool| {
+        (0..N * N).filter(|&i| pattern[i] == value).min_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+
+    // Initial pattern: a tenth of the pixels picked by a fixed hash, then relaxed until the
+    // tightest cluster is also the largest void
+    let mut pattern = vec![false; N * N];
+    let mut energy: Vec<Real> = vec![0.0; N * N];
+    let initial = N * N / 10;
+    let mut placed = 0;
+    let mut counter = 0u64;
+    while placed < initial {
+        let i = (mix(counter) % (N * N) as u64) as usize;
+        counter += 1;
+        if !pattern[i] {
+            pattern[i] = true;
+            splat(&mut energy, i, 1.0);
+            placed += 1;
+        }
+    }
+    for _ in 0..N * N {
+        let cluster = tightest_cluster(&pattern, &energy, true);
+        pattern[cluster] = false;
+        splat(&mut energy, cluster, -1.0);
+        let void = largest_void(&pattern, &energy, false);
+        pattern[void] = true;
+        splat(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0usize; N * N];
+
+    // Phase 1: rank the initial points by repeatedly removing the tightest cluster
+    let mut working = pattern.clone();
+    let mut working_energy = energy.clone();
+    for r in (0..initial).rev() {
+        let cluster = tightest_cluster(&working, &working_energy, true);
+        working[cluster] = false;
+        splat(&mut working_energy, cluster, -1.0);
+        rank[cluster] = r;
+    }
+
+    // Phase 2: fill the largest voids up to half the pixels
+    for r in initial..N * N / 2 {
+        let void = largest_void(&pattern, &energy, false);
+        pattern[void] = true;
+        splat(&mut energy, void, 1.0);
+        rank[void] = r;
+    }
+
+    // Phase 3: the remaining zeros are now the minority, so rank their tightest clusters
+    let mut energy: Vec<Real> = vec![0.0; N * N];
+    for (i, _) in pattern.iter().enumerate().filter(|(_, &set)| !set) {
+        splat(&mut energy, i, 1.0);
+    }
+    for r in N * N / 2..N * N {
+        let cluster = tightest_cluster(&pattern, &energy, false);
+        pattern[cluster] = true;
+        splat(&mut energy, cluster, -1.0);
+        rank[cluster] = r;
+    }
+
+    rank.iter().map(|&r| (r as Real + 0.5) / (N * N) as Real).collect()
+}
@@ -1,48 +1,110 @@
-use rand::{Rng, thread_rng};
-use crate::utils::camera::Camera;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng, thread_rng};
+use crate::utils::camera::{Camera, CameraBuilder};
+use crate::utils::environment::Environment;
+use crate::utils::error::{RenderError, Result};
+use crate::utils::bvh::Bvh;
 use crate::utils::geometry::{HittableList, Sphere};
+use crate::utils::light::{Light, LightList};
 use crate::utils::material::Material;
 use crate::utils::vector::Vec3;
+use crate::utils::real::Real;
 
-pub fn random_scene(aspect_ratio: f32, fov: f32) -> (HittableList, Camera) {
+pub struct Scene {
+    pub world: HittableList,
+    pub lights: LightList,
+    pub camera: Camera,
+    pub environment: Environment,
+}
+
+impl Scene {
+    pub fn new(world: HittableList, camera: Camera) -> Self {
+        Scene { world, lights: LightList::new(vec![]), camera, environment: Environment::default() }
+    }
+
+    pub fn with_light(mut self, light: Light) -> Self {
+        self.lights.lights.push(light);
+        self
+    }
+
+    pub fn with_lights(mut self, lights: LightList) -> Self {
+        self.lights.lights.extend(lights.lights);
+        self
+    }
+
+    // Swaps in another camera model, e.g. an equirectangular panorama from the same position
+    pub fn with_camera(mut self, camera: Camera) -> Self {
+        self.camera = camera;
+        self
+    }
+
+    // Focuses a perspective camera on whatever is seen through image position (u, v). Other
+    // cameras have no focus, and a point that sees only sky gives nothing to focus on.
+    pub fn with_autofocus(mut self, u: Real, v: Real) -> Result<Self> {
+        let Camera::Perspective(camera) = &mut self.camera else {
+            return Err(RenderError::Scene("autofocus needs a perspective camera".to_string()));
+        };
+        if camera.focus_on(&self.world, u, v).is_none() {
+            return Err(RenderError::Scene(format!("autofocus found nothing at ({}, {})", u, v)));
+        }
+        Ok(self)
+    }
+
+    pub fn with_environment(mut self, environment: Environment) -> Self {
+        self.environment = environment;
+        self
+    }
+}
+
+pub fn random_scene(aspect_ratio: Real, fov: Real) -> Scene {
+    random_scene_from(&mut thread_rng(), aspect_ratio, fov)
+}
+
+// The same layout of spheres every time for a given seed
+pub fn seeded_random_scene(seed: u64, aspect_ratio: Real, fov: Real) -> Scene {
+    random_scene_from(&mut StdRng::seed_from_u64(seed), aspect_ratio, fov)
+}
+
+fn random_scene_from<R: Rng + ?Sized>(rng: &mut R, aspect_ratio: Real, fov: Real) -> Scene {
     let cam_origin = Vec3::new(13.0, 2.0, 3.0);
-    let cam_direction = Vec3::new(0.0, 0.0, 0.0);
+    let cam_look_at = Vec3::new(0.0, 0.0, 0.0);
     let cam_up = Vec3::new(0.0, 1.0, 0.0);
     let distance_to_focus = 10.0;
     let aperture = 0.1;
 
-    let mut rng = thread_rng();
-    let mut world = HittableList::new(vec![]);
+    // Several hundred spheres, in a BVH with a few intersected four at a time in each leaf
+    let mut spheres = vec![];
 
     let material_ground = Material::Lambertian { albedo: Vec3::new(0.5, 0.5, 0.5) };
-    let ground_sphere = Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, material_ground));
+    let ground_sphere = Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, material_ground);
 
-    world.objects.push(ground_sphere);
+    spheres.push(ground_sphere);
 
+    // Drawn as f32 whatever `Real` is, so that a seed gives the same scene in both precisions
     for i in -11..11 {
         for j in -11..11 {
-            let choose_mat = rng.gen::<f32>();
-            let center = Vec3::new(i as f32 + 0.9 * rng.gen::<f32>(), 0.2, j as f32 + 0.9 * rng.gen::<f32>());
+            let choose_mat = rng.gen::<f32>() as Real;
+            let center = Vec3::new(i as Real + 0.9 * rng.gen::<f32>() as Real, 0.2, j as Real + 0.9 * rng.gen::<f32>() as Real);
 
             if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
                 let material: Material;
 
                 if choose_mat < 0.8 {
                     // Diffuse
-                    let albedo = Vec3::random(0.0..1.0) * Vec3::random(0.0..1.0);
+                    let albedo = Vec3::random_with(rng, 0.0..1.0) * Vec3::random_with(rng, 0.0..1.0);
                     material = Material::Lambertian { albedo };
                 } else if choose_mat < 0.95 {
                     // Metal
-                    let albedo = Vec3::random(0.5..1.0);
-                    let fuzz = rng.gen_range(0.0..0.5);
+                    let albedo = Vec3::random_with(rng, 0.5..1.0);
+                    let fuzz = rng.gen_range(0.0f32..0.5) as Real;
                     material = Material::Metal { albedo, fuzz };
                 } else {
                     // Glass
                     material = Material::Dielctric { ir: 1.5 };
                 }
 
-                let sphere = Box::new(Sphere::new(center, 0.2, material));
-                world.objects.push(sphere);
+                let sphere = Sphere::new(center, 0.2, material);
+                spheres.push(sphere);
             }
         }
     }
@@ -51,17 +113,19 @@ pub fn random_scene(aspect_ratio: f32, fov: f32) -> (HittableList, Camera) {
     let material2 = Material::Lambertian { albedo: Vec3::new(0.4, 0.2, 0.1) };
     let material3 = Material::Metal { albedo: Vec3::new(0.7, 0.6, 0.5), fuzz: 0.0 };
 
-    let sphere1 = Box::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, material1));
-    let sphere2 = Box::new(Sphere::new(Vec3::new(-4.0, 1.0, 0.0), 1.0, material2));
-    let sphere3 = Box::new(Sphere::new(Vec3::new(4.0, 1.0, 0.0), 1.0, material3));
+    let sphere1 = Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, material1);
+    let sphere2 = Sphere::new(Vec3::new(-4.0, 1.0, 0.0), 1.0, material2);
+    let sphere3 = Sphere::new(Vec3::new(4.0, 1.0, 0.0), 1.0, material3);
+
+    spheres.push(sphere1);
+    spheres.push(sphere2);
+    spheres.push(sphere3);
 
-    world.objects.push(sphere1);
-    world.objects.push(sphere2);
-    world.objects.push(sphere3);
+    let world = HittableList::new(vec![Box::new(Bvh::new(spheres))]);
 
     let camera = Camera::new(
         cam_origin,
-        cam_direction,
+        cam_look_at,
         cam_up,
         fov,
         aspect_ratio,
@@ -69,15 +133,12 @@ pub fn random_scene(aspect_ratio: f32, fov: f32) -> (HittableList, Camera) {
         distance_to_focus
     );
 
-    (world, camera)
+    Scene::new(world, camera)
 }
 
-pub fn default_scene(aspect: f32, fov: f32) -> (HittableList, Camera) {
+pub fn default_scene(aspect: Real, fov: Real) -> Scene {
     let cam_origin = Vec3::new(3.0, 3.0, 2.0);
-    let cam_direction = Vec3::new(0.0, 0.0, -1.0);
-    let cam_up = Vec3::new(0.0, 1.0, 0.0);
-    let distance_to_focus = (cam_origin - cam_direction).length();
-    let aperture = 2.0;
+    let cam_look_at = Vec3::new(0.0, 0.0, -1.0);
 
     let material_ground = Material::Lambertian { albedo: Vec3::new(0.8, 0.8, 0.0) };
     let material_center = Material::Lambertian { albedo: Vec3::new(0.7, 0.3, 0.3) };
@@ -98,15 +159,13 @@ pub fn default_scene(aspect: f32, fov: f32) -> (HittableList, Camera) {
 
     ]);
 
-    let camera = Camera::new(
-        cam_origin,
-        cam_direction,
-        cam_up,
-        fov,
-        aspect,
-        aperture,
-        distance_to_focus
-    );
+    // A full frame camera at f/2.8, focused on the centre sphere
+    let camera = CameraBuilder::new(cam_origin)
+        .with_look_at(cam_look_at)
+        .with_aspect_ratio(aspect)
+        .with_vfov(fov)
+        .with_f_number(2.8)
+        .build();
 
-    (world, camera)
+    Scene::new(world, camera)
 }
\ No newline at end of file
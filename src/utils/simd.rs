@@ -0,0 +1,268 @@
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use crate::utils::ray::Ray;
+use crate::utils::real::Real;
+use crate::utils::vector::Vec3;
+
+// Four `Real` lanes operated on at once. On x86_64 in single precision this is an SSE
+// register, which every x86_64 CPU has; elsewhere, or with the `scalar` or `f64` feature, it
+// is a plain array the compiler may or may not vectorise. Comparisons return masks with every
+// bit of a lane set where they hold.
+#[derive(Debug, Clone, Copy)]
+pub struct Realx4(imp::Lanes);
+
+impl Realx4 {
+    pub const LANES: usize = 4;
+
+    pub fn splat(value: Real) -> Self {
+        Realx4(imp::splat(value))
+    }
+
+    pub fn from_array(values: [Real; 4]) -> Self {
+        Realx4(imp::from_array(values))
+    }
+
+    pub fn to_array(self) -> [Real; 4] {
+        imp::to_array(self.0)
+    }
+
+    pub fn sqrt(self) -> Self {
+        Realx4(imp::sqrt(self.0))
+    }
+
+    pub fn min(self, other: Self) -> Self {
+        Realx4(imp::min(self.0, other.0))
+    }
+
+    pub fn max(self, other: Self) -> Self {
+        Realx4(imp::max(self.0, other.0))
+    }
+
+    pub fn lt(self, other: Self) -> Self {
+        Realx4(imp::lt(self.0, other.0))
+    }
+
+    pub fn gt(self, other: Self) -> Self {
+        Realx4(imp::gt(self.0, other.0))
+    }
+
+    pub fn ge(self, other: Self) -> Self {
+        Realx4(imp::ge(self.0, other.0))
+    }
+
+    pub fn and(self, other: Self) -> Self {
+        Realx4(imp::and(self.0, other.0))
+    }
+
+    pub fn or(self, other: Self) -> Self {
+        Realx4(imp::or(self.0, other.0))
+    }
+
+    // Lanes of `if_true` where `self` is a set mask, lanes of `if_false` elsewhere
+    pub fn select(self, if_true: Self, if_false: Self) -> Self {
+        Realx4(imp::select(self.0, if_true.0, if_false.0))
+    }
+
+    // One bit per lane of a mask, lane 0 in the lowest bit
+    pub fn bitmask(self) -> u32 {
+        imp::bitmask(self.0)
+    }
+}
+
+impl Add for Realx4 {
+    type Output = Realx4;
+    fn add(self, rhs: Realx4) -> Realx4 {
+        Realx4(imp::add(self.0, rhs.0))
+    }
+}
+
+impl Sub for Realx4 {
+    type Output = Realx4;
+    fn sub(self, rhs: Realx4) -> Realx4 {
+        Realx4(imp::sub(self.0, rhs.0))
+    }
+}
+
+impl Mul for Realx4 {
+    type Output = Realx4;
+    fn mul(self, rhs: Realx4) -> Realx4 {
+        Realx4(imp::mul(self.0, rhs.0))
+    }
+}
+
+impl Div for Realx4 {
+    type Output = Realx4;
+    fn div(self, rhs: Realx4) -> Realx4 {
+        Realx4(imp::div(self.0, rhs.0))
+    }
+}
+
+impl Neg for Realx4 {
+    type Output = Realx4;
+    fn neg(self) -> Realx4 {
+        Realx4::splat(0.0) - self
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", not(any(feature = "scalar", feature = "f64"))))]
+mod imp {
+    use std::arch::x86_64::*;
+
+    pub type Lanes = __m128;
+
+    // SAFETY: SSE and SSE2 are part of the x86_64 baseline, so these intrinsics are always
+    // available
+    pub fn splat(value: f32) -> Lanes { unsafe { _mm_set1_ps(value) } }
+    pub fn from_array(v: [f32; 4]) -> Lanes { unsafe { _mm_setr_ps(v[0], v[1], v[2], v[3]) } }
+    pub fn to_array(a: Lanes) -> [f32; 4] {
+        let mut out = [0.0; 4];
+        // SAFETY: `out` has room for the four floats written
+        unsafe { _mm_storeu_ps(out.as_mut_ptr(), a) };
+        out
+    }
+    pub fn add(a: Lanes, b: Lanes) -> Lanes { unsafe { _mm_add_ps(a, b) } }
+    pub fn sub(a: Lanes, b: Lanes) -> Lanes { unsafe { _mm_sub_ps(a, b) } }
+    pub fn mul(a: Lanes, b: Lanes) -> Lanes { unsafe { _mm_mul_ps(a, b) } }
+    pub fn div(a: Lanes, b: Lanes) -> Lanes { unsafe { _mm_div_ps(a, b) } }
+    pub fn sqrt(a: Lanes) -> Lanes { unsafe { _mm_sqrt_ps(a) } }
+    pub fn min(a: Lanes, b: Lanes) -> Lanes { unsafe { _mm_min_ps(a, b) } }
+    pub fn max(a: Lanes, b: Lanes) -> Lanes { unsafe { _mm_max_ps(a, b) } }
+    pub fn lt(a: Lanes, b: Lanes) -> Lanes { unsafe { _mm_cmplt_ps(a, b) } }
+    pub fn gt(a: Lanes, b: Lanes) -> Lanes { unsafe { _mm_cmpgt_ps(a, b) } }
+    pub fn ge(a: Lanes, b: Lanes) -> Lanes { unsafe { _mm_cmpge_ps(a, b) } }
+    pub fn and(a: Lanes, b: Lanes) -> Lanes { unsafe { _mm_and_ps(a, b) } }
+    pub fn or(a: Lanes, b: Lanes) -> Lanes { unsafe { _mm_or_ps(a, b) } }
+    pub fn select(mask: Lanes, a: Lanes, b: Lanes) -> Lanes { unsafe { _mm_or_ps(_mm_and_ps(mask, a), _mm_andnot_ps(mask, b)) } }
+    pub fn bitmask(a: Lanes) -> u32 { unsafe { _mm_movemask_ps(a) as u32 } }
+}
+
+#[cfg(not(all(target_arch = "x86_64", not(any(feature = "scalar", feature = "f64")))))]
+mod imp {
+    use crate::utils::real::Real;
+
+    pub type Lanes = [Real; 4];
+
+    fn map(a: Lanes, f: impl Fn(Real) -> Real) -> Lanes { [f(a[0]), f(a[1]), f(a[2]), f(a[3])] }
+    fn zip(a: Lanes, b: Lanes, f: impl Fn(Real, Real) -> Real) -> Lanes { [f(a[0], b[0]), f(a[1], b[1]), f(a[2], b[2]), f(a[3], b[3])] }
+    fn mask(set: bool) -> Real { if set { Real::from_bits(!0) } else { 0.0 } }
+    fn bits(a: Lanes, b: Lanes, f: impl Fn(Raw, Raw) -> Raw) -> Lanes { zip(a, b, |x, y| Real::from_bits(f(x.to_bits(), y.to_bits()))) }
+
+    // The bits of a `Real`
+    #[cfg(not(feature = "f64"))]
+    type Raw = u32;
+    #[cfg(feature = "f64")]
+    type Raw = u64;
+
+    pub fn splat(value: Real) -> Lanes { [value; 4] }
+    pub fn from_array(v: [Real; 4]) -> Lanes { v }
+    pub fn to_array(a: Lanes) -> [Real; 4] { a }
+    pub fn add(a: Lanes, b: Lanes) -> Lanes { zip(a, b, |x, y| x + y) }
+    pub fn sub(a: Lanes, b: Lanes) -> Lanes { zip(a, b, |x, y| x - y) }
+    pub fn mul(a: Lanes, b: Lanes) -> Lanes { zip(a, b, |x, y| x * y) }
+    pub fn div(a: Lanes, b: Lanes) -> Lanes { zip(a, b, |x, y| x / y) }
+    pub fn sqrt(a: Lanes) -> Lanes { map(a, Real::sqrt) }
+    // Same NaN handling as SSE: the second operand wins when either is NaN
+    pub fn min(a: Lanes, b: Lanes) -> Lanes { zip(a, b, |x, y| if x < y { x } else { y }) }
+    pub fn max(a: Lanes, b: Lanes) -> Lanes { zip(a, b, |x, y| if x > y { x } else { y }) }
+    pub fn lt(a: Lanes, b: Lanes) -> Lanes { zip(a, b, |x, y| mask(x < y)) }
+    pub fn gt(a: Lanes, b: Lanes) -> Lanes { zip(a, b, |x, y| mask(x > y)) }
+    pub fn ge(a: Lanes, b: Lanes) -> Lanes { zip(a, b, |x, y| mask(x >= y)) }
+    pub fn and(a: Lanes, b: Lanes) -> Lanes { bits(a, b, |x, y| x & y) }
+    pub fn or(a: Lanes, b: Lanes) -> Lanes { bits(a, b, |x, y| x | y) }
+    pub fn select(mask: Lanes, a: Lanes, b: Lanes) -> Lanes {
+        let picked = bits(mask, a, |m, x| m & x);
+        zip(picked, bits(mask, b, |m, y| !m & y), |x, y| Real::from_bits(x.to_bits() | y.to_bits()))
+    }
+    // A set mask has its sign bit set
+    pub fn bitmask(a: Lanes) -> u32 {
+        a.iter().enumerate().map(|(i, x)| (x.is_sign_negative() as u32) << i).sum()
+    }
+}
+
+// Four vectors stored component-wise, so that each operation works on all four at once
+#[derive(Debug, Clone, Copy)]
+pub struct Vec3x4 {
+    pub x: Realx4,
+    pub y: Realx4,
+    pub z: Realx4,
+}
+
+impl Vec3x4 {
+    pub fn new(x: Realx4, y: Realx4, z: Realx4) -> Self {
+        Vec3x4 { x, y, z }
+    }
+
+    pub fn splat(v: Vec3<Real>) -> Self {
+        Vec3x4::new(Realx4::splat(v.x), Realx4::splat(v.y), Realx4::splat(v.z))
+    }
+
+    pub fn from_vecs(v: [Vec3<Real>; 4]) -> Self {
+        Vec3x4::new(
+            Realx4::from_array([v[0].x, v[1].x, v[2].x, v[3].x]),
+            Realx4::from_array([v[0].y, v[1].y, v[2].y, v[3].y]),
+            Realx4::from_array([v[0].z, v[1].z, v[2].z, v[3].z]),
+        )
+    }
+
+    pub fn lane(&self, i: usize) -> Vec3<Real> {
+        Vec3::new(self.x.to_array()[i], self.y.to_array()[i], self.z.to_array()[i])
+    }
+
+    pub fn dot(self, other: Vec3x4) -> Realx4 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(self, other: Vec3x4) -> Vec3x4 {
+        Vec3x4::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    pub fn length_squared(self) -> Realx4 {
+        self.dot(self)
+    }
+}
+
+impl Add for Vec3x4 {
+    type Output = Vec3x4;
+    fn add(self, rhs: Vec3x4) -> Vec3x4 {
+        Vec3x4::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl Sub for Vec3x4 {
+    type Output = Vec3x4;
+    fn sub(self, rhs: Vec3x4) -> Vec3x4 {
+        Vec3x4::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl Mul<Realx4> for Vec3x4 {
+    type Output = Vec3x4;
+    fn mul(self, rhs: Realx4) -> Vec3x4 {
+        Vec3x4::new(self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+// Four rays traced together. Coherent rays, such as camera rays through neighbouring pixels,
+// tend to hit the same objects, so testing them against one object at a time keeps all lanes
+// busy.
+#[derive(Debug, Clone, Copy)]
+pub struct RayPacket {
+    pub origin: Vec3x4,
+    pub direction: Vec3x4,
+}
+
+impl RayPacket {
+    pub fn new(rays: [Ray; 4]) -> Self {
+        RayPacket {
+            origin: Vec3x4::from_vecs(rays.map(|r| r.origin)),
+            direction: Vec3x4::from_vecs(rays.map(|r| r.direction)),
+        }
+    }
+
+    pub fn ray(&self, i: usize) -> Ray {
+        Ray::new(self.origin.lane(i), self.direction.lane(i))
+    }
+}
@@ -0,0 +1,277 @@
+use crate::utils::real::consts::PI;
+use crate::utils::environment::{luminance, EnvironmentMap};
+use crate::utils::vector::{Dot, Vec3};
+use crate::utils::real::Real;
+
+// Luminance of the sun's disk outside the atmosphere, in the same kcd/m^2 the sky model uses
+const EXTRATERRESTRIAL_SUN_LUMINANCE: Real = 1.9e6;
+// Maps the model's kcd/m^2 to radiance values comparable with the gradient sky
+pub(crate) const DEFAULT_INTENSITY: Real = 0.025;
+// Resolution of the table used to importance sample the sky dome
+const TABLE_WIDTH: u32 = 64;
+const TABLE_HEIGHT: u32 = 32;
+
+// Preetham, Shirley and Smits, "A Practical Analytic Model for Daylight" (1999).
+// Directions are in world space with +y as the zenith.
+#[derive(Clone)]
+pub struct Sky {
+    sun: Sun,
+    turbidity: Real,
+    ground_albedo: Vec3<Real>,
+    intensity: Real,
+    zenith: Vec3<Real>,
+    perez_y: [Real; 5],
+    perez_x: [Real; 5],
+    perez_yy: [Real; 5],
+    ground: Vec3<Real>,
+    sun_probability: Real,
+    table: EnvironmentMap,
+}
+
+// Directional light with a finite angular size, such as the sun seen from the ground
+#[derive(Debug, Clone, Copy)]
+pub struct Sun {
+    pub direction: Vec3<Real>,
+    pub angular_radius: Real,
+    pub radiance: Vec3<Real>,
+}
+
+impl Sun {
+    pub fn new(direction: Vec3<Real>, angular_radius_degrees: Real, radiance: Vec3<Real>) -> Self {
+        Sun {
+            direction: direction.normalise(),
+            angular_radius: angular_radius_degrees.to_radians(),
+            radiance,
+        }
+    }
+
+    fn cos_max(&self) -> Real {
+        self.angular_radius.cos()
+    }
+
+    // 1 - cos_max computed without cancellation, since the sun is only half a degree across
+    fn one_minus_cos_max(&self) -> Real {
+        2.0 * (self.angular_radius / 2.0).sin().powi(2)
+    }
+
+    pub fn solid_angle(&self) -> Real {
+        2.0 * PI * self.one_minus_cos_max()
+    }
+
+    pub fn radiance(&self, direction: Vec3<Real>) -> Vec3<Real> {
+        if direction.normalise().dot(self.direction) >= self.cos_max() {
+            self.radiance
+        } else {
+            Vec3::new(0.0, 0.0, 0.0)
+        }
+    }
+
+    // Uniformly samples a direction inside the sun's cone
+    pub fn sample(&self, u: (Real, Real)) -> (Vec3<Real>, Real) {
+        let cos_theta = 1.0 - u.0 * self.one_minus_cos_max();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.1;
+
+        let (t, b) = self.direction.orthonormal_basis();
+        let direction = t * (sin_theta * phi.cos()) + b * (sin_theta * phi.sin()) + self.direction * cos_theta;
+        (direction, 1.0 / self.solid_angle())
+    }
+
+    pub fn pdf(&self, direction: Vec3<Real>) -> Real {
+        if direction.normalise().dot(self.direction) >= self.cos_max() {
+            1.0 / self.solid_angle()
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Sky {
+    pub fn new(sun_direction: Vec3<Real>, turbidity: Real, ground_albedo: Vec3<Real>) -> Self {
+        Sky::build(sun_direction.normalise(), turbidity, ground_albedo, DEFAULT_INTENSITY, 0.2675)
+    }
+
+    // Sky for a place on Earth at a given moment, see `sun_direction_at`
+    pub fn at_location(latitude: Real, longitude: Real, julian_day: Real, solar_time: Real, turbidity: Real, ground_albedo: Vec3<Real>) -> Self {
+        Sky::new(sun_direction_at(latitude, longitude, julian_day, solar_time), turbidity, ground_albedo)
+    }
+
+    pub fn with_intensity(self, intensity: Real) -> Self {
+        Sky::build(self.sun.direction, self.turbidity, self.ground_albedo, intensity, self.sun.angular_radius.to_degrees())
+    }
+
+    pub fn with_sun_size(self, angular_radius_degrees: Real) -> Self {
+        Sky::build(self.sun.direction, self.turbidity, self.ground_albedo, self.intensity, angular_radius_degrees)
+    }
+
+    pub fn sun(&self) -> Sun {
+        self.sun
+    }
+
+    fn build(sun_direction: Vec3<Real>, turbidity: Real, ground_albedo: Vec3<Real>, intensity: Real, sun_radius: Real) -> Self {
+        let t = turbidity;
+        // The model is only defined for a sun above the horizon
+        let theta_s = sun_direction.y.clamp(0.0, 1.0).acos();
+
+        let perez_y = [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703];
+        let perez_x = [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452];
+        let perez_yy = [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+
+        let th = theta_s;
+        let (th2, th3) = (th * th, th * th * th);
+        let zenith_x = t * t * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th)
+            + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394)
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886);
+        let zenith_y = t * t * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th)
+            + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516)
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688);
+
+        let sun_radiance = if sun_direction.y > 0.0 {
+            sun_transmittance(theta_s, t) * (EXTRATERRESTRIAL_SUN_LUMINANCE * intensity)
+        } else {
+            Vec3::new(0.0, 0.0, 0.0)
+        };
+
+        let mut sky = Sky {
+            sun: Sun::new(sun_direction, sun_radius, sun_radiance),
+            turbidity,
+            ground_albedo,
+            intensity,
+            zenith: Vec3::new(zenith_x, zenith_y, zenith_luminance),
+            perez_y,
+            perez_x,
+            perez_yy,
+            ground: Vec3::new(0.0, 0.0, 0.0),
+            sun_probability: 0.0,
+            table: EnvironmentMap::from_pixels(1, 1, vec![Vec3::new(1.0, 1.0, 1.0)]),
+        };
+
+        // Tabulate the upper hemisphere, both to light the ground and to build the sampling distribution
+        let mut pixels = Vec::with_capacity((TABLE_WIDTH * TABLE_HEIGHT) as usize);
+        let mut sky_irradiance = Vec3::new(0.0, 0.0, 0.0);
+        for row in 0..TABLE_HEIGHT {
+            let theta = PI * (row as Real + 0.5) / TABLE_HEIGHT as Real;
+            for column in 0..TABLE_WIDTH {
+                let phi = 2.0 * PI * (column as Real + 0.5) / TABLE_WIDTH as Real;
+                let direction = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+                let radiance = sky.sky_radiance(direction);
+                if direction.y > 0.0 {
+                    let solid_angle = (2.0 * PI / TABLE_WIDTH as Real) * (PI / TABLE_HEIGHT as Real) * theta.sin();
+                    sky_irradiance = sky_irradiance + radiance * (direction.y * solid_angle);
+                }
+                pixels.push(radiance);
+            }
+        }
+
+        // A diffuse ground lit by the sky dome and the sun
+        let sun_irradiance = sky.sun.radiance * (sky.sun.solid_angle() * sun_direction.y.max(0.0));
+        sky.ground = ground_albedo * (sky_irradiance + sun_irradiance) / PI;
+        for p in pixels.iter_mut().skip((TABLE_WIDTH * TABLE_HEIGHT / 2) as usize) {
+            *p = sky.ground;
+        }
+
+        // Split light samples between the sun and the dome in proportion to their power
+        let sun_power = luminance(sky.sun.radiance) * sky.sun.solid_angle();
+        let dome_power = pixels.iter().map(|p| luminance(*p)).sum::<Real>() * 4.0 * PI / pixels.len() as Real;
+        sky.sun_probability = if sun_power + dome_power > 0.0 { sun_power / (sun_power + dome_power) } else { 0.0 };
+        sky.table = EnvironmentMap::from_pixels(TABLE_WIDTH, TABLE_HEIGHT, pixels);
+
+        sky
+    }
+
+    fn perez(coefficients: &[Real; 5], cos_theta: Real, gamma: Real) -> Real {
+        let [a, b, c, d, e] = *coefficients;
+        (1.0 + a * (b / cos_theta.max(1e-3)).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+    }
+
+    fn sky_radiance(&self, direction: Vec3<Real>) -> Vec3<Real> {
+        let d = direction.normalise();
+        if d.y < 0.0 {
+            return self.ground;
+        }
+
+        let theta_s = self.sun.direction.y.clamp(0.0, 1.0).acos();
+        let gamma = d.dot(self.sun.direction).clamp(-1.0, 1.0).acos();
+        let luminance = self.zenith.z * Sky::perez(&self.perez_y, d.y, gamma) / Sky::perez(&self.perez_y, 1.0, theta_s);
+        let x = self.zenith.x * Sky::perez(&self.perez_x, d.y, gamma) / Sky::perez(&self.perez_x, 1.0, theta_s);
+        let y = self.zenith.y * Sky::perez(&self.perez_yy, d.y, gamma) / Sky::perez(&self.perez_yy, 1.0, theta_s);
+
+        xyy_to_rgb(x, y, luminance) * self.intensity
+    }
+
+    pub fn radiance(&self, direction: Vec3<Real>) -> Vec3<Real> {
+        self.sky_radiance(direction) + self.sun.radiance(direction)
+    }
+
+    pub fn sample(&self, u: (Real, Real)) -> Option<(Vec3<Real>, Vec3<Real>, Real)> {
+        let direction = if u.0 < self.sun_probability {
+            self.sun.sample((u.0 / self.sun_probability, u.1)).0
+        } else {
+            let remapped = (u.0 - self.sun_probability) / (1.0 - self.sun_probability);
+            self.table.sample((remapped.min(0.999_999), u.1))?.0
+        };
+
+        let pdf = self.pdf(direction);
+        if pdf == 0.0 {
+            return None;
+        }
+        Some((direction, self.radiance(direction), pdf))
+    }
+
+    pub fn pdf(&self, direction: Vec3<Real>) -> Real {
+        self.sun_probability * self.sun.pdf(direction) + (1.0 - self.sun_probability) * self.table.pdf(direction)
+    }
+}
+
+// Sun position from Preetham et al. appendix A.6. Angles are in degrees, `julian_day` is the
+// day of the year (1-365) and `solar_time` is in decimal hours. The returned direction uses
+// +y as up and +z as south, so the sun rises towards -x.
+pub fn sun_direction_at(latitude: Real, longitude: Real, julian_day: Real, solar_time: Real) -> Vec3<Real> {
+    let latitude = latitude.to_radians();
+    let j = julian_day;
+
+    // Correct the local time for the equation of time, relative to the time zone's meridian
+    let standard_meridian = (longitude / 15.0).round() * 15.0;
+    let t = solar_time
+        + 0.170 * (4.0 * PI * (j - 80.0) / 373.0).sin()
+        - 0.129 * (2.0 * PI * (j - 8.0) / 355.0).sin()
+        + (standard_meridian - longitude) / 15.0;
+    let declination = 0.4093 * (2.0 * PI * (j - 81.0) / 368.0).sin();
+    let hour_angle = PI * t / 12.0;
+
+    let elevation = (latitude.sin() * declination.sin() - latitude.cos() * declination.cos() * hour_angle.cos()).asin();
+    let azimuth = (-declination.cos() * hour_angle.sin())
+        .atan2(latitude.cos() * declination.sin() - latitude.sin() * declination.cos() * hour_angle.cos());
+
+    let theta = PI / 2.0 - elevation;
+    Vec3::new(theta.sin() * azimuth.sin(), theta.cos(), theta.sin() * azimuth.cos())
+}
+
+// Attenuation of direct sunlight by Rayleigh and aerosol scattering, evaluated at
+// representative red, green and blue wavelengths
+fn sun_transmittance(theta_s: Real, turbidity: Real) -> Vec3<Real> {
+    let optical_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let transmittance = |lambda_um: Real| {
+        let rayleigh = 0.008735 * lambda_um.powf(-4.08);
+        let aerosol = beta * lambda_um.powf(-1.3);
+        (-optical_mass * (rayleigh + aerosol)).exp()
+    };
+    Vec3::new(transmittance(0.68), transmittance(0.55), transmittance(0.44))
+}
+
+fn xyy_to_rgb(x: Real, y: Real, luminance: Real) -> Vec3<Real> {
+    if y <= 0.0 {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    let cx = x * luminance / y;
+    let cz = (1.0 - x - y) * luminance / y;
+    Vec3::new(
+        (3.2406 * cx - 1.5372 * luminance - 0.4986 * cz).max(0.0),
+        (-0.9689 * cx + 1.8758 * luminance + 0.0415 * cz).max(0.0),
+        (0.0557 * cx - 0.2040 * luminance + 1.0570 * cz).max(0.0),
+    )
+}
@@ -0,0 +1,154 @@
+use std::cell::{Cell, RefCell};
+use crate::utils::material::Material;
+
+// Counters describing where a render spent its time. Each render job counts into its thread's
+// thread-local copy, so counting costs no synchronisation, and the copies are merged as the
+// jobs finish.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    // Rays traced at each bounce, camera rays being bounce 0
+    pub rays_by_depth: Vec<u64>,
+    // Visibility tests towards lights and the environment
+    pub shadow_rays: u64,
+    // Ray-primitive intersection tests, including those done for shadow rays
+    pub intersection_tests: u64,
+    // Boxes tested while traversing a `Bvh`
+    pub bvh_nodes_visited: u64,
+    pub lambertian_scatters: u64,
+    pub metal_scatters: u64,
+    pub dielectric_scatters: u64,
+    // Rays that left the scene and picked up the environment's radiance
+    pub escaped: u64,
+    // Paths ended by a material declining to scatter
+    pub absorbed: u64,
+}
+
+impl RenderStats {
+    pub fn primary_rays(&self) -> u64 {
+        self.rays_by_depth.first().copied().unwrap_or(0)
+    }
+
+    pub fn secondary_rays(&self) -> u64 {
+        self.rays_by_depth.iter().skip(1).sum()
+    }
+
+    pub fn total_rays(&self) -> u64 {
+        self.primary_rays() + self.secondary_rays() + self.shadow_rays
+    }
+
+    pub fn merge(&mut self, other: &RenderStats) {
+        if self.rays_by_depth.len() < other.rays_by_depth.len() {
+            self.rays_by_depth.resize(other.rays_by_depth.len(), 0);
+        }
+        for (total, count) in self.rays_by_depth.iter_mut().zip(other.rays_by_depth.iter()) {
+            *total += count;
+        }
+        self.shadow_rays += other.shadow_rays;
+        self.intersection_tests += other.intersection_tests;
+        self.bvh_nodes_visited += other.bvh_nodes_visited;
+        self.lambertian_scatters += other.lambertian_scatters;
+        self.metal_scatters += other.metal_scatters;
+        self.dielectric_scatters += other.dielectric_scatters;
+        self.escaped += other.escaped;
+        self.absorbed += other.absorbed;
+    }
+
+    fn rows(&self) -> Vec<(String, u64)> {
+        let mut rows: Vec<(String, u64)> = self.rays_by_depth.iter().enumerate()
+            .map(|(depth, &count)| (format!("rays at depth {}", depth), count))
+            .collect();
+        rows.extend([
+            ("shadow rays".to_string(), self.shadow_rays),
+            ("intersection tests".to_string(), self.intersection_tests),
+            ("BVH nodes visited".to_string(), self.bvh_nodes_visited),
+            ("lambertian scatters".to_string(), self.lambertian_scatters),
+            ("metal scatters".to_string(), self.metal_scatters),
+            ("dielectric scatters".to_string(), self.dielectric_scatters),
+            ("escaped to background".to_string(), self.escaped),
+            ("absorbed".to_string(), self.absorbed),
+        ]);
+        rows
+    }
+
+    pub fn print_table(&self) {
+        println!("Render statistics:");
+        for (name, count) in self.rows() {
+            println!("  {:<24}{:>16}", name, count);
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let depths: Vec<String> = self.rays_by_depth.iter().map(|c| c.to_string()).collect();
+        format!(
+            "{{\n  \"rays_by_depth\": [{}],\n  \"shadow_rays\": {},\n  \"intersection_tests\": {},\n  \"bvh_nodes_visited\": {},\n  \"scatters\": {{\"lambertian\": {}, \"metal\": {}, \"dielectric\": {}}},\n  \"escaped\": {},\n  \"absorbed\": {}\n}}\n",
+            depths.join(", "), self.shadow_rays, self.intersection_tests, self.bvh_nodes_visited,
+            self.lambertian_scatters, self.metal_scatters, self.dielectric_scatters, self.escaped, self.absorbed
+        )
+    }
+}
+
+thread_local! {
+    static STATS: RefCell<RenderStats> = RefCell::new(RenderStats::default());
+    // Bumped for every primitive and box tested, so kept apart from the rest in plain cells
+    static INTERSECTION_TESTS: Cell<u64> = const { Cell::new(0) };
+    static BVH_NODES_VISITED: Cell<u64> = const { Cell::new(0) };
+    // Converts the remaining depth that paths are traced with into a bounce number
+    static MAX_DEPTH: Cell<i32> = const { Cell::new(0) };
+}
+
+// Starts this thread's counters from zero for a render with the given maximum depth
+pub fn start_thread(max_depth: i32) {
+    MAX_DEPTH.set(max_depth);
+    take_thread_stats();
+}
+
+// Returns what this thread has counted so far and starts again from zero
+pub fn take_thread_stats() -> RenderStats {
+    let mut stats = STATS.take();
+    stats.intersection_tests += INTERSECTION_TESTS.take();
+    stats.bvh_nodes_visited += BVH_NODES_VISITED.take();
+    stats
+}
+
+fn record(f: impl FnOnce(&mut RenderStats)) {
+    STATS.with_borrow_mut(f);
+}
+
+// A camera or bounce ray traced with `depth` bounces left
+pub fn count_ray(depth: i32) {
+    let bounce = (MAX_DEPTH.get() - depth).max(0) as usize;
+    record(|stats| {
+        if stats.rays_by_depth.len() <= bounce {
+            stats.rays_by_depth.resize(bounce + 1, 0);
+        }
+        stats.rays_by_depth[bounce] += 1;
+    });
+}
+
+pub fn count_shadow_ray() {
+    record(|stats| stats.shadow_rays += 1);
+}
+
+pub fn count_intersection_tests(tests: u64) {
+    INTERSECTION_TESTS.set(INTERSECTION_TESTS.get() + tests);
+}
+
+pub fn count_bvh_node() {
+    BVH_NODES_VISITED.set(BVH_NODES_VISITED.get() + 1);
+}
+
+pub fn count_scatter(material: &Material) {
+    record(|stats| match material {
+        Material::Lambertian { .. } => stats.lambertian_scatters += 1,
+        Material::Metal { .. } => stats.metal_scatters += 1,
+        Material::Dielctric { .. } => stats.dielectric_scatters += 1,
+    });
+}
+
+pub fn count_escaped() {
+    record(|stats| stats.escaped += 1);
+}
+
+pub fn count_absorbed() {
+    record(|stats| stats.absorbed += 1);
+}
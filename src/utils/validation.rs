@@ -0,0 +1,365 @@
+use std::f64::consts::PI;
+use crate::utils::camera::Camera;
+use crate::utils::environment::Environment;
+use crate::utils::geometry::{HitRecord, Hittable, HittableList, Sphere};
+use crate::utils::material::{scatter, Material};
+use crate::utils::ray::Ray;
+use crate::utils::sampler::Sampler;
+use crate::utils::scene::Scene;
+use crate::utils::vector::{Dot, Vec3};
+use crate::utils::real::{to_f64, Real};
+
+// Statistical checks of the materials, run over every entry of `catalogue` by
+// tests/materials.rs. Directions are binned over the whole sphere in equal area cells of
+// cos(theta) and phi around the shading normal.
+const THETA_BINS: usize = 20;
+const PHI_BINS: usize = 40;
+// Pieces per side a cell is split into before integrating a density over it adaptively
+const SUBDIVISIONS: usize = 2;
+const INTEGRATION_TOLERANCE: f64 = 1e-7;
+const MAX_DEPTH: u32 = 8;
+// Cells expected to receive fewer samples than this are pooled, as the chi-square
+// approximation needs a handful of samples per cell
+const MIN_EXPECTED: f64 = 5.0;
+
+// Materials every check runs over: the examples of each variant in `variants`
+pub fn catalogue() -> Vec<Material> {
+    variants().iter().flat_map(examples).collect()
+}
+
+// One material of every variant, in declaration order. tests/materials.rs fails until a new
+// variant is listed here.
+fn variants() -> [Material; 3] {
+    [
+        Material::Lambertian { albedo: Vec3::new(1.0, 1.0, 1.0) },
+        Material::Metal { albedo: Vec3::new(1.0, 1.0, 1.0), fuzz: 0.0 },
+        Material::Dielctric { ir: 1.5 },
+    ]
+}
+
+// A variant at settings that exercise its lobes. Adding a variant fails to compile here until
+// it has examples.
+pub fn examples(material: &Material) -> Vec<Material> {
+    match material {
+        Material::Lambertian { .. } => vec![Material::Lambertian { albedo: Vec3::new(0.8, 0.5, 0.2) }],
+        Material::Metal { .. } => [0.0, 0.3, 1.0].map(|fuzz| Material::Metal { albedo: Vec3::new(0.9, 0.8, 0.7), fuzz }).to_vec(),
+        Material::Dielctric { .. } => [1.5, 2.4].map(|ir| Material::Dielctric { ir }).to_vec(),
+    }
+}
+
+pub fn variant_name(material: &Material) -> &'static str {
+    match material {
+        Material::Lambertian { .. } => "lambertian",
+        Material::Metal { .. } => "metal",
+        Material::Dielctric { .. } => "dielectric",
+    }
+}
+
+// The same material with an albedo of one, for the white furnace
+pub fn white(material: &Material) -> Material {
+    let one = Vec3::new(1.0, 1.0, 1.0);
+    match material {
+        Material::Lambertian { .. } => Material::Lambertian { albedo: one },
+        Material::Metal { fuzz, .. } => Material::Metal { albedo: one, fuzz: *fuzz },
+        Material::Dielctric { ir } => Material::Dielctric { ir: *ir },
+    }
+}
+
+// Whether the material is allowed to lose energy even with an albedo of one. Fuzzy metal
+// absorbs the rays its fuzz sends below the surface.
+pub fn absorbs_at_white(material: &Material) -> bool {
+    match material {
+        Material::Lambertian { .. } | Material::Dielctric { .. } => false,
+        Material::Metal { fuzz, .. } => *fuzz > 0.0,
+    }
+}
+
+// A hit at the origin with the shading normal +y, by a ray arriving at `cos_theta` to the
+// normal from the outside or, for `inside`, from within the object
+pub fn hit_at(material: &Material, cos_theta: Real, inside: bool) -> (Ray, HitRecord) {
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let towards_source = Vec3::new(sin_theta, cos_theta, 0.0);
+    let p = Vec3::new(0.0, 0.0, 0.0);
+    let r_in = Ray::new(p + towards_source, -towards_source);
+    let rec = HitRecord {
+        p,
+        p_error: Vec3::new(0.0, 0.0, 0.0),
+        normal: Vec3::new(0.0, 1.0, 0.0),
+        material: Some(material.clone()),
+        t: 1.0,
+        front_face: !inside,
+        object_id: 0,
+    };
+    (r_in, rec)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ChiSquare {
+    pub statistic: f64,
+    pub degrees_of_freedom: usize,
+    // Chance of a statistic at least this large if `scatter` really follows `scattering_pdf`
+    pub p_value: f64,
+}
+
+// Goodness of fit of the directions `scatter` draws to the density `scattering_pdf` claims.
+// Rays the material declines to scatter are counted in a cell of their own, expected to hold
+// whatever the density leaves unaccounted for. `None` for specular materials, which have no
+// density.
+pub fn chi_square(material: &Material, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler, samples: usize) -> Option<ChiSquare> {
+    if material.is_specular() {
+        return None;
+    }
+
+    let mut observed = vec![0.0; THETA_BINS * PHI_BINS + 1];
+    let absorbed = observed.len() - 1;
+    for _ in 0..samples {
+        let (_, scattered, should_scatter) = scatter(material, r_in, rec, sampler);
+        let cell = if should_scatter { cell_of(rec.normal, scattered.direction) } else { absorbed };
+        observed[cell] += 1.0;
+    }
+
+    let mut expected: Vec<f64> = integrate_cells(rec.normal, |direction| {
+        to_f64(material.scattering_pdf(r_in, rec, direction).unwrap_or(0.0))
+    }).into_iter().map(|p| p * samples as f64).collect();
+    expected.push((samples as f64 - expected.iter().sum::<f64>()).max(0.0));
+
+    Some(chi_square_statistic(&observed, &expected))
+}
+
+// Mean attenuation of the rays `scatter` draws, absorbed rays counting as zero. The fraction
+// of energy reflected or transmitted for light arriving along `r_in`, never above one.
+pub fn directional_albedo(material: &Material, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler, samples: usize) -> Vec3<Real> {
+    let mut sum = [0.0f64; 3];
+    for _ in 0..samples {
+        let (attenuation, _, should_scatter) = scatter(material, r_in, rec, sampler);
+        if should_scatter {
+            sum[0] += to_f64(attenuation.x);
+            sum[1] += to_f64(attenuation.y);
+            sum[2] += to_f64(attenuation.z);
+        }
+    }
+    let n = samples as f64;
+    Vec3::new((sum[0] / n) as Real, (sum[1] / n) as Real, (sum[2] / n) as Real)
+}
+
+// The same albedo from integrating `eval` over the sphere, for the non-specular materials
+// that light sampling evaluates
+pub fn integrated_albedo(material: &Material, r_in: &Ray, rec: &HitRecord) -> Option<Vec3<Real>> {
+    if material.is_specular() {
+        return None;
+    }
+    let channel = |c: fn(Vec3<Real>) -> Real| {
+        integrate_cells(rec.normal, |direction| to_f64(c(material.eval(r_in, rec, direction)))).iter().sum::<f64>() as Real
+    };
+    Some(Vec3::new(channel(|v| v.x), channel(|v| v.y), channel(|v| v.z)))
+}
+
+// Largest difference between the attenuation `scatter` returns and `eval / scattering_pdf` for
+// the same direction. Light sampling and MIS rely on the two agreeing.
+pub fn sampling_weight_error(material: &Material, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler, samples: usize) -> Option<Real> {
+    if material.is_specular() {
+        return None;
+    }
+    let mut worst: Real = 0.0;
+    for _ in 0..samples {
+        let (attenuation, scattered, should_scatter) = scatter(material, r_in, rec, sampler);
+        let pdf = material.scattering_pdf(r_in, rec, scattered.direction).unwrap_or(0.0);
+        if !should_scatter || pdf <= 0.0 {
+            continue;
+        }
+        let weight = material.eval(r_in, rec, scattered.direction) * (1.0 / pdf);
+        let error = attenuation - weight;
+        worst = worst.max(error.x.abs()).max(error.y.abs()).max(error.z.abs());
+    }
+    Some(worst)
+}
+
+// A unit sphere of `material` filling the middle of the frame, lit only by `environment`
+pub fn furnace_scene(material: Material, environment: Environment, aspect_ratio: Real) -> Scene {
+    let world = HittableList::new(vec![
+        Box::new(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, material)) as Box<dyn Hittable + Sync + Send>,
+    ]);
+    let camera = Camera::new(
+        Vec3::new(0.0, 0.0, 4.0),
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        40.0,
+        aspect_ratio,
+        0.0,
+        4.0,
+    );
+    Scene::new(world, camera).with_environment(environment)
+}
+
+// Orthonormal tangents around `n` (Duff et al.'s branchless construction)
+fn tangents(n: Vec3<Real>) -> (Vec3<Real>, Vec3<Real>) {
+    let sign = Real::copysign(1.0, n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    (
+        Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+        Vec3::new(b, sign + n.y * n.y * a, -n.y),
+    )
+}
+
+fn cell_of(normal: Vec3<Real>, direction: Vec3<Real>) -> usize {
+    let (s, t) = tangents(normal);
+    let d = direction.normalise();
+    let z = to_f64(d.dot(normal).clamp(-1.0, 1.0));
+    let phi = to_f64(d.dot(t)).atan2(to_f64(d.dot(s))).rem_euclid(2.0 * PI);
+    let theta_bin = (((1.0 - z) / 2.0 * THETA_BINS as f64) as usize).min(THETA_BINS - 1);
+    let phi_bin = ((phi / (2.0 * PI) * PHI_BINS as f64) as usize).min(PHI_BINS - 1);
+    theta_bin * PHI_BINS + phi_bin
+}
+
+// Integral of `density` over each cell, in the same order as `cell_of`. Cells are equal
+// area, so integrating over (cos(theta), phi) needs no Jacobian. Each cell is split before
+// integrating adaptively, so that a narrow lobe cannot fall between the first few points.
+fn integrate_cells(normal: Vec3<Real>, density: impl Fn(Vec3<Real>) -> f64) -> Vec<f64> {
+    let (s, t) = tangents(normal);
+    let density_at = |z: f64, phi: f64| {
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        density(s * (r * phi.cos()) as Real + t * (r * phi.sin()) as Real + normal * z as Real)
+    };
+    let (dz, dphi) = (2.0 / THETA_BINS as f64, 2.0 * PI / PHI_BINS as f64);
+    let (sub_dz, sub_dphi) = (dz / SUBDIVISIONS as f64, dphi / SUBDIVISIONS as f64);
+
+    let mut integrals = Vec::with_capacity(THETA_BINS * PHI_BINS);
+    for theta_bin in 0..THETA_BINS {
+        for phi_bin in 0..PHI_BINS {
+            let mut sum = 0.0;
+            for i in 0..SUBDIVISIONS {
+                for j in 0..SUBDIVISIONS {
+                    let z1 = 1.0 - theta_bin as f64 * dz - i as f64 * sub_dz;
+                    let phi0 = phi_bin as f64 * dphi + j as f64 * sub_dphi;
+                    sum += adaptive_simpson(&|z| {
+                        adaptive_simpson(&|phi| density_at(z, phi), phi0, phi0 + sub_dphi, INTEGRATION_TOLERANCE, MAX_DEPTH)
+                    }, z1 - sub_dz, z1, INTEGRATION_TOLERANCE, MAX_DEPTH);
+                }
+            }
+            integrals.push(sum);
+        }
+    }
+    integrals
+}
+
+fn adaptive_simpson(f: &dyn Fn(f64) -> f64, a: f64, b: f64, tolerance: f64, depth: u32) -> f64 {
+    let (fa, fm, fb) = (f(a), f(0.5 * (a + b)), f(b));
+    let whole = (b - a) / 6.0 * (fa + 4.0 * fm + fb);
+    simpson_step(f, a, b, fa, fm, fb, whole, tolerance, depth)
+}
+
+// Halves the interval until the two halves agree with their parent (Lyness' criterion)
+#[allow(clippy::too_many_arguments)]
+fn simpson_step(f: &dyn Fn(f64) -> f64, a: f64, b: f64, fa: f64, fm: f64, fb: f64, whole: f64, tolerance: f64, depth: u32) -> f64 {
+    let m = 0.5 * (a + b);
+    let (flm, frm) = (f(0.5 * (a + m)), f(0.5 * (m + b)));
+    let left = (m - a) / 6.0 * (fa + 4.0 * flm + fm);
+    let right = (b - m) / 6.0 * (fm + 4.0 * frm + fb);
+    let delta = left + right - whole;
+    if depth == 0 || delta.abs() <= 15.0 * tolerance {
+        return left + right + delta / 15.0;
+    }
+    simpson_step(f, a, m, fa, flm, fm, left, tolerance / 2.0, depth - 1)
+        + simpson_step(f, m, b, fm, frm, fb, right, tolerance / 2.0, depth - 1)
+}
+
+// Pearson's statistic over cells with at least `MIN_EXPECTED` samples expected, the rest pooled into
+// one cell
+pub fn chi_square_statistic(observed: &[f64], expected: &[f64]) -> ChiSquare {
+    let mut statistic = 0.0;
+    let mut cells = 0;
+    let (mut pooled_observed, mut pooled_expected) = (0.0, 0.0);
+    for (&o, &e) in observed.iter().zip(expected) {
+        if e < MIN_EXPECTED {
+            pooled_observed += o;
+            pooled_expected += e;
+        } else {
+            statistic += (o - e) * (o - e) / e;
+            cells += 1;
+        }
+    }
+
+    if pooled_expected < MIN_EXPECTED * 1e-3 && pooled_observed > 0.0 {
+        // Samples where the density says there should be none at all
+        return ChiSquare { statistic: f64::INFINITY, degrees_of_freedom: cells, p_value: 0.0 };
+    }
+    if pooled_expected > 0.0 {
+        statistic += (pooled_observed - pooled_expected).powi(2) / pooled_expected;
+        cells += 1;
+    }
+
+    let degrees_of_freedom = cells.saturating_sub(1).max(1);
+    let p_value = regularized_gamma_q(degrees_of_freedom as f64 / 2.0, statistic / 2.0);
+    ChiSquare { statistic, degrees_of_freedom, p_value }
+}
+
+// Upper regularised incomplete gamma function Q(a, x), the chi-square survival function
+// when a = dof / 2 and x = statistic / 2 (Numerical Recipes, section 6.2)
+fn regularized_gamma_q(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let log_prefix = a * x.ln() - x - ln_gamma(a);
+    if x < a + 1.0 {
+        // Series for P(a, x)
+        let (mut term, mut sum, mut n) = (1.0 / a, 1.0 / a, a);
+        for _ in 0..1000 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-15 {
+                break;
+            }
+        }
+        (1.0 - sum * log_prefix.exp()).max(0.0)
+    } else {
+        // Continued fraction for Q(a, x), by Lentz's method
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..1000 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny { d = tiny; }
+            c = b + an / c;
+            if c.abs() < tiny { c = tiny; }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < 1e-15 {
+                break;
+            }
+        }
+        (log_prefix.exp() * h).clamp(0.0, 1.0)
+    }
+}
+
+// Lanczos approximation, accurate to around 1e-15 for positive arguments
+fn ln_gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        return (PI / (PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let mut sum = COEFFICIENTS[0];
+    for (i, c) in COEFFICIENTS.iter().enumerate().skip(1) {
+        sum += c / (x + i as f64);
+    }
+    let t = x + G + 0.5;
+    0.5 * (2.0 * PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
@@ -1,6 +1,7 @@
 use std::ops::{Neg, Add, Mul, Div, Sub, Rem, Range};
 use num::Float;
 use rand::Rng;
+use crate::utils::real::{to_f32, Real};
 
 pub trait Dot<RHS = Self> {
     type Output;
@@ -15,22 +16,40 @@ pub struct Vec3<T> {
     pub z: T,
 }
 
-impl Vec3<f32> {
-    pub fn new(x: f32, y: f32, z: f32) -> Self {
+impl<T> Vec3<T> {
+    pub fn new(x: T, y: T, z: T) -> Self {
         Vec3 { x, y, z }
     }
+}
 
-    pub fn random(r: Range<f32>) -> Vec3<f32> {
-        let mut rng = rand::thread_rng();
+// Images and AOV buffers are stored as f32, whatever precision the renderer computes in
+impl Vec3<f32> {
+    pub fn to_real(self) -> Vec3<Real> {
+        Vec3::new(self.x as Real, self.y as Real, self.z as Real)
+    }
+}
 
-        Vec3 { 
-            x: rng.gen_range(r.clone()),
-            y: rng.gen_range(r.clone()), 
-            z: rng.gen_range(r.clone())
-        }
+impl Vec3<Real> {
+    pub fn to_f32(self) -> Vec3<f32> {
+        Vec3::new(to_f32(self.x), to_f32(self.y), to_f32(self.z))
     }
 
-    pub fn random_in_unit_sphere() -> Vec3<f32> {
+    pub fn random(r: Range<Real>) -> Vec3<Real> {
+        Vec3::random_with(&mut rand::thread_rng(), r)
+    }
+
+    // Same as `random`, drawing from the given generator so the result can be reproduced. Drawn
+    // as f32, so a seed gives the same vectors whatever `Real` is.
+    pub fn random_with<R: Rng + ?Sized>(rng: &mut R, r: Range<Real>) -> Vec3<Real> {
+        let r = to_f32(r.start)..to_f32(r.end);
+        Vec3::new(
+            rng.gen_range(r.clone()) as Real,
+            rng.gen_range(r.clone()) as Real,
+            rng.gen_range(r) as Real,
+        )
+    }
+
+    pub fn random_in_unit_sphere() -> Vec3<Real> {
         loop {
             let v = Vec3::random(-1.0..1.0);
             if v.length() < 1.0 {
@@ -39,7 +58,7 @@ impl Vec3<f32> {
         }
     }
 
-    pub fn random_in_unit_disk() -> Vec3<f32> {
+    pub fn random_in_unit_disk() -> Vec3<Real> {
         let mut rng = rand::thread_rng();
 
         loop {
@@ -54,23 +73,67 @@ impl Vec3<f32> {
         }
     }
 
+    // Maps a point in [0, 1)^2 onto the unit disk in the z = 0 plane, keeping strata
+    // adjacent (Shirley and Chiu's concentric mapping)
+    pub fn in_unit_disk(u: (Real, Real)) -> Vec3<Real> {
+        let (a, b) = (2.0 * u.0 - 1.0, 2.0 * u.1 - 1.0);
+        if a == 0.0 && b == 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let (r, theta) = if a.abs() > b.abs() {
+            (a, crate::utils::real::consts::FRAC_PI_4 * (b / a))
+        } else {
+            (b, crate::utils::real::consts::FRAC_PI_2 - crate::utils::real::consts::FRAC_PI_4 * (a / b))
+        };
+        Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+    }
+
+    // Maps a point in [0, 1)^2 uniformly onto the surface of the unit sphere
+    pub fn on_unit_sphere(u: (Real, Real)) -> Vec3<Real> {
+        let z = 1.0 - 2.0 * u.0;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * crate::utils::real::consts::PI * u.1;
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    // Maps a point in [0, 1)^3 uniformly into the unit ball
+    pub fn in_unit_sphere(u: (Real, Real), w: Real) -> Vec3<Real> {
+        Vec3::on_unit_sphere(u) * w.cbrt()
+    }
+
     pub fn near_zero(&self) -> bool {
         let s = 1e-8;
         self.x.abs() < s && self.y.abs() < s && self.z.abs() < s
     }
 
-    pub fn reflect(&self, n: Vec3<f32>) -> Vec3<f32> {
+    pub fn reflect(&self, n: Vec3<Real>) -> Vec3<Real> {
         *self - n * 2.0 * self.dot(n)
     }
 
-    pub fn refract(&self, n: Vec3<f32>, etai_over_etat: f32) -> Vec3<f32> {
+    pub fn refract(&self, n: Vec3<Real>, etai_over_etat: Real) -> Vec3<Real> {
         let cos_theta = (-*self).dot(n).min(1.0);
         let r_out_perp = (*self + n * cos_theta) * etai_over_etat;
         let r_out_parallel = n * -(1.0 - r_out_perp.length_squared()).abs().sqrt();
         r_out_perp + r_out_parallel
     }
 
-    pub fn cross(&self, other: Vec3<f32>) -> Vec3<f32> {
+    // Two unit vectors completing an orthonormal basis with this unit vector
+    // (Duff et al., "Building an Orthonormal Basis, Revisited")
+    pub fn orthonormal_basis(&self) -> (Vec3<Real>, Vec3<Real>) {
+        let sign = Real::copysign(1.0, self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        (
+            Vec3::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            Vec3::new(b, sign + self.y * self.y * a, -self.y),
+        )
+    }
+
+    pub fn abs(&self) -> Vec3<Real> {
+        Vec3::new(self.x.abs(), self.y.abs(), self.z.abs())
+    }
+
+    pub fn cross(&self, other: Vec3<Real>) -> Vec3<Real> {
         Vec3 {
             x: self.y * other.z - self.z * other.y,
             y: self.z * other.x - self.x * other.z,
@@ -148,18 +211,26 @@ impl<T: Mul<S>, S: Copy> Mul<S> for Vec3<T> {
     }
 }
 
-impl Mul<Vec3<f32>> for Vec3<f32> {
-    type Output = Vec3<f32>;
-
-    fn mul(self, rhs: Vec3<f32>) -> Vec3<f32> {
-        Vec3 {
-            x: self.x * rhs.x,
-            y: self.y * rhs.y,
-            z: self.z * rhs.z,
+// Component-wise, for colors
+macro_rules! impl_mul_vec3 {
+    ($t:ty) => {
+        impl Mul<Vec3<$t>> for Vec3<$t> {
+            type Output = Vec3<$t>;
+
+            fn mul(self, rhs: Vec3<$t>) -> Vec3<$t> {
+                Vec3 {
+                    x: self.x * rhs.x,
+                    y: self.y * rhs.y,
+                    z: self.z * rhs.z,
+                }
+            }
         }
-    }
+    };
 }
 
+impl_mul_vec3!(f32);
+impl_mul_vec3!(f64);
+
 impl<T: Div<S>, S: Copy> Div<S> for Vec3<T> {
     type Output = Vec3<<T as Div<S>>::Output>;
 
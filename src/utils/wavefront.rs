@@ -0,0 +1,239 @@
+use std::ops::Range;
+use crate::utils::aov::FirstHit;
+use crate::utils::geometry::{HitRecord, Hittable};
+use crate::utils::material::{scatter, Material};
+use crate::utils::ray::Ray;
+use crate::utils::renderer::{escaped, power_heuristic};
+use crate::utils::sampler::{Sampler, SamplerState};
+use crate::utils::scene::Scene;
+use crate::utils::stats::{count_absorbed, count_ray, count_scatter, count_shadow_ray};
+use crate::utils::vector::{Dot, Vec3};
+use crate::utils::real::Real;
+
+// Camera samples started at once. Enough for long runs of rays with the same material or
+// direction, few enough that the queues stay small.
+pub const WAVE_SIZE: usize = 1 << 14;
+
+// How a render follows its paths
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Integrator {
+    // Each path is traced to the end, recursively, before the next one starts
+    #[default]
+    Megakernel,
+    // A wave of paths goes through each stage together: camera rays, intersection, shading
+    // grouped by material, then shadow rays. Takes the same random numbers as the megakernel,
+    // so the image only differs in the last bits.
+    Wavefront,
+}
+
+// A camera sample, once its path has finished
+pub struct CameraSample {
+    pub x: u32,
+    pub y: u32,
+    // Where in the image the sample was taken, for the reconstruction filter
+    pub film_x: Real,
+    pub film_y: Real,
+    pub radiance: Vec3<Real>,
+    // What the camera ray hit, if first hits are being recorded
+    pub first_hit: Option<FirstHit>,
+}
+
+// A path waiting for its next ray to be intersected
+struct PathState {
+    // Index of the camera sample it adds its light to
+    sample: usize,
+    ray: Ray,
+    // Product of the attenuations of the bounces so far
+    throughput: Vec3<Real>,
+    depth: i32,
+    // As in `trace`: the density with which the last bounce chose `ray`, or `None`
+    scattering_pdf: Option<Real>,
+    sampler: SamplerState,
+}
+
+// A path whose ray hit a surface, waiting to be shaded
+struct Hit {
+    path: PathState,
+    rec: HitRecord,
+    material: Material,
+}
+
+// Light that reaches a shaded path unless something is in the way of `ray` before `t_max`
+struct ShadowRay {
+    sample: usize,
+    ray: Ray,
+    t_max: Real,
+    radiance: Vec3<Real>,
+}
+
+// Traces waves of camera samples stage by stage. The queues are kept between waves so that
+// they are only allocated once per block.
+pub struct Wavefront<'a> {
+    scene: &'a Scene,
+    image_width: u32,
+    image_height: u32,
+    max_depth: i32,
+    record_first_hits: bool,
+    samples: Vec<CameraSample>,
+    paths: Vec<PathState>,
+    hits: Vec<Hit>,
+    shadow_rays: Vec<ShadowRay>,
+}
+
+impl<'a> Wavefront<'a> {
+    pub fn new(scene: &'a Scene, image_width: u32, image_height: u32, max_depth: i32, record_first_hits: bool) -> Self {
+        Wavefront {
+            scene,
+            image_width,
+            image_height,
+            max_depth,
+            record_first_hits,
+            samples: vec![],
+            paths: vec![],
+            hits: vec![],
+            shadow_rays: vec![],
+        }
+    }
+
+    // Traces samples `samples` of every pixel in `pixels`. They come back in the order the
+    // megakernel takes them, pixel by pixel, so the film adds them up in the same order.
+    pub fn trace(&mut self, pixels: &[(u32, u32)], samples: Range<u32>, sampler: &mut dyn Sampler) -> &[CameraSample] {
+        self.samples.clear();
+        self.generate(pixels, samples, sampler);
+        while !self.paths.is_empty() {
+            self.intersect();
+            self.shade(sampler);
+            self.trace_shadow_rays();
+        }
+        &self.samples
+    }
+
+    fn generate(&mut self, pixels: &[(u32, u32)], samples: Range<u32>, sampler: &mut dyn Sampler) {
+        for &(x, y) in pixels {
+            for s in samples.clone() {
+                sampler.start_pixel_sample(x, y, s);
+                let (ru, rv) = sampler.get_2d();
+
+                let u = ((x as Real) + ru) / ((self.image_width-1).max(1) as Real);
+                let v = ((y as Real) + rv) / ((self.image_height-1).max(1) as Real);
+
+                let sample = self.samples.len();
+                self.samples.push(CameraSample { x, y, film_x: x as Real + ru, film_y: y as Real + rv, radiance: Vec3::new(0.0, 0.0, 0.0), first_hit: None });
+                // Outside the area covered by the camera the sample stays black
+                if let Some(ray) = self.scene.camera.get_ray(u, v, sampler) {
+                    count_ray(self.max_depth);
+                    self.paths.push(PathState {
+                        sample,
+                        ray,
+                        throughput: Vec3::new(1.0, 1.0, 1.0),
+                        depth: self.max_depth,
+                        scattering_pdf: None,
+                        sampler: sampler.state(),
+                    });
+                }
+            }
+        }
+    }
+
+    // Rays heading the same way are intersected one after another, so they tend to visit the
+    // same BVH nodes while those are still in cache
+    fn intersect(&mut self) {
+        let scene = self.scene;
+        self.paths.sort_by_key(|path| octant(path.ray.direction));
+        for path in self.paths.drain(..) {
+            let Some(mut rec) = scene.world.hit(&path.ray, 0.0, Real::INFINITY) else {
+                let sample = &mut self.samples[path.sample];
+                sample.radiance = sample.radiance + path.throughput * escaped(&path.ray, scene, path.scattering_pdf);
+                continue;
+            };
+
+            // Camera rays are the only ones that haven't bounced yet
+            if self.record_first_hits && path.depth == self.max_depth {
+                self.samples[path.sample].first_hit = Some(FirstHit::new(&path.ray, &rec));
+            }
+            match rec.material.take() {
+                // Past the last bounce a path ends at the next surface it hits
+                Some(_) if path.depth < 0 => {}
+                Some(material) => self.hits.push(Hit { path, rec, material }),
+                None => {
+                    let sample = &mut self.samples[path.sample];
+                    sample.radiance = sample.radiance + path.throughput * escaped(&path.ray, scene, path.scattering_pdf);
+                }
+            }
+        }
+    }
+
+    // Hits on the same kind of material are shaded one after another, running the same code.
+    // The random numbers are drawn in the same order as in `shade` and `sample_lights`.
+    fn shade(&mut self, sampler: &mut dyn Sampler) {
+        let scene = self.scene;
+        self.hits.sort_by_key(|hit| material_kind(&hit.material));
+        for Hit { path, rec, material } in self.hits.drain(..) {
+            sampler.resume(path.sampler);
+            let ray = &path.ray;
+
+            if !material.is_specular() {
+                for light in scene.lights.lights.iter() {
+                    let Some(sample) = light.sample(rec.p) else {
+                        continue;
+                    };
+                    if sample.direction.dot(rec.normal) > 0.0 {
+                        let radiance = path.throughput * (material.eval(ray, &rec, sample.direction) * sample.radiance);
+                        self.shadow_rays.push(ShadowRay { sample: path.sample, ray: rec.spawn_ray(sample.direction), t_max: sample.distance, radiance });
+                    }
+                }
+
+                if scene.environment.is_sampled() {
+                    if let Some((direction, radiance, light_pdf)) = scene.environment.sample(sampler.get_2d()) {
+                        if direction.dot(rec.normal) > 0.0 {
+                            let f = material.eval(ray, &rec, direction);
+                            let weight = power_heuristic(light_pdf, material.scattering_pdf(ray, &rec, direction).unwrap_or(0.0));
+                            let radiance = path.throughput * (f * radiance * (weight / light_pdf));
+                            self.shadow_rays.push(ShadowRay { sample: path.sample, ray: rec.spawn_ray(direction), t_max: Real::INFINITY, radiance });
+                        }
+                    }
+                }
+            }
+
+            let (attenuation, scattered, should_scatter) = scatter(&material, ray, &rec, sampler);
+            if !should_scatter {
+                count_absorbed();
+                continue;
+            }
+            count_scatter(&material);
+            let scattering_pdf = material.scattering_pdf(ray, &rec, scattered.direction);
+            count_ray(path.depth - 1);
+            self.paths.push(PathState {
+                ray: scattered,
+                throughput: path.throughput * attenuation,
+                depth: path.depth - 1,
+                scattering_pdf,
+                sampler: sampler.state(),
+                ..path
+            });
+        }
+    }
+
+    fn trace_shadow_rays(&mut self) {
+        for shadow in self.shadow_rays.drain(..) {
+            count_shadow_ray();
+            if self.scene.world.hit(&shadow.ray, 0.0, shadow.t_max).is_none() {
+                let sample = &mut self.samples[shadow.sample];
+                sample.radiance = sample.radiance + shadow.radiance;
+            }
+        }
+    }
+}
+
+fn material_kind(material: &Material) -> u8 {
+    match material {
+        Material::Lambertian { .. } => 0,
+        Material::Metal { .. } => 1,
+        Material::Dielctric { .. } => 2,
+    }
+}
+
+// Which of the eight octants a direction points into
+fn octant(direction: Vec3<Real>) -> u8 {
+    (direction.x < 0.0) as u8 | ((direction.y < 0.0) as u8) << 1 | ((direction.z < 0.0) as u8) << 2
+}
@@ -0,0 +1,83 @@
+// The BVH finds the same closest hit as testing every sphere, however it was built, and so does
+// each lane of a packet of rays
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use parallel_raytracing_rs::utils::simd::{Realx4, RayPacket};
+use parallel_raytracing_rs::{Bvh, Hittable, HittableList, Material, Ray, Real, RenderPool, Sphere, SphereBatch, Vec3};
+
+// Drawn as f32 whatever `Real` is, so both builds test the same spheres and rays
+fn uniform(rng: &mut StdRng, range: std::ops::Range<f32>) -> Real {
+    rng.gen_range(range) as Real
+}
+
+fn spheres(rng: &mut StdRng, count: usize) -> Vec<Sphere> {
+    (0..count).map(|_| {
+        let center = Vec3::new(uniform(rng, -20.0..20.0), uniform(rng, -20.0..20.0), uniform(rng, -20.0..20.0));
+        let material = Material::Lambertian { albedo: Vec3::new(0.5, 0.5, 0.5) };
+        // Some hollow, as the glass spheres in the scenes are
+        let radius = uniform(rng, 0.1..1.5) * if rng.gen::<f32>() < 0.1 { -1.0 } else { 1.0 };
+        Sphere::new(center, radius, material)
+    }).collect()
+}
+
+fn random_ray(rng: &mut StdRng) -> Ray {
+    let origin = Vec3::new(uniform(rng, -25.0..25.0), uniform(rng, -25.0..25.0), uniform(rng, -25.0..25.0));
+    let direction = Vec3::new(uniform(rng, -1.0..1.0), uniform(rng, -1.0..1.0), uniform(rng, -1.0..1.0));
+    Ray::new(origin, direction)
+}
+
+fn assert_matches_brute_force(build: impl Fn(Vec<Sphere>) -> Bvh) {
+    let mut rng = StdRng::seed_from_u64(7);
+    for count in [0, 1, 5, 100, 1000] {
+        let bvh = build(spheres(&mut StdRng::seed_from_u64(count as u64), count));
+        let batch = SphereBatch::new(spheres(&mut StdRng::seed_from_u64(count as u64), count));
+        assert_eq!(bvh.object_count(), count);
+
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let ray = random_ray(&mut rng);
+            let expected = batch.hit(&ray, 0.0, Real::INFINITY);
+            let got = bvh.hit(&ray, 0.0, Real::INFINITY);
+            assert_eq!(got.as_ref().map(|rec| (rec.object_id, rec.t)), expected.as_ref().map(|rec| (rec.object_id, rec.t)), "{} spheres", count);
+            hits += expected.is_some() as u32;
+        }
+        if count >= 100 {
+            assert!(hits > 100, "only {} of the rays hit anything", hits);
+        }
+    }
+}
+
+#[test]
+fn matches_brute_force() {
+    assert_matches_brute_force(Bvh::new);
+}
+
+#[test]
+fn parallel_build_matches_brute_force() {
+    let pool = RenderPool::new(3, false).unwrap();
+    assert_matches_brute_force(|spheres| pool.install(|| Bvh::new(spheres)));
+}
+
+#[test]
+fn packets_match_single_rays() {
+    let mut rng = StdRng::seed_from_u64(11);
+    for count in [0, 1, 5, 100, 1000] {
+        let bvh = Bvh::new(spheres(&mut StdRng::seed_from_u64(count as u64), count));
+        let batch = SphereBatch::new(spheres(&mut StdRng::seed_from_u64(count as u64), count));
+        let list = HittableList::new(spheres(&mut StdRng::seed_from_u64(count as u64), count).into_iter().map(|sphere| Box::new(sphere) as _).collect());
+        for _ in 0..500 {
+            let rays: [Ray; 4] = std::array::from_fn(|_| random_ray(&mut rng));
+            // A different limit in each lane, one of them cutting the nearest hits off
+            let t_max = [Real::INFINITY, 30.0, 5.0, Real::INFINITY];
+            let packet = RayPacket::new(rays);
+            let objects: [&dyn Hittable; 3] = [&bvh, &batch, &list];
+            for object in objects {
+                let got = object.hit_packet(&packet, 0.0, Realx4::from_array(t_max));
+                for lane in 0..4 {
+                    let expected = object.hit(&rays[lane], 0.0, t_max[lane]);
+                    assert_eq!(got[lane].as_ref().map(|rec| (rec.object_id, rec.t)), expected.as_ref().map(|rec| (rec.object_id, rec.t)), "{} spheres, lane {}", count, lane);
+                }
+            }
+        }
+    }
+}
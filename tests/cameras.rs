@@ -0,0 +1,125 @@
+// Where the panoramic and fisheye cameras look for a given image position, how far apart the
+// two eyes of an omni-directional stereo pair are, and the field of view and exposure that
+// `CameraBuilder` derives from photographic settings.
+use parallel_raytracing_rs::utils::vector::Dot;
+use parallel_raytracing_rs::{CameraBuilder, EquirectangularCamera, FisheyeCamera, OmniStereoCamera, Real, SamplerKind, StereoEye, Vec3};
+
+fn origin() -> Vec3<Real> {
+    Vec3::new(1.0, 2.0, 3.0)
+}
+
+// Looking down -z with +y up, so right is +x
+fn look_at() -> Vec3<Real> {
+    Vec3::new(1.0, 2.0, -2.0)
+}
+
+fn up() -> Vec3<Real> {
+    Vec3::new(0.0, 1.0, 0.0)
+}
+
+fn assert_direction(direction: Vec3<Real>, expected: Vec3<Real>, what: &str) {
+    let direction = direction.normalise();
+    assert!((direction - expected).length() < 1e-4, "{}: {:?} instead of {:?}", what, direction, expected);
+}
+
+#[test]
+fn equirectangular_centre_looks_forward() {
+    let camera = EquirectangularCamera::new(origin(), look_at(), up());
+    let ray = camera.get_ray(0.5, 0.5);
+    assert!((ray.origin - origin()).length() < 1e-5);
+    assert_direction(ray.direction, Vec3::new(0.0, 0.0, -1.0), "centre");
+    assert_direction(camera.get_ray(0.75, 0.5).direction, Vec3::new(1.0, 0.0, 0.0), "three quarters across");
+    assert_direction(camera.get_ray(0.0, 0.5).direction, Vec3::new(0.0, 0.0, 1.0), "left edge");
+    assert_direction(camera.get_ray(0.3, 0.0).direction, Vec3::new(0.0, 1.0, 0.0), "top");
+}
+
+#[test]
+fn fisheye_covers_only_its_image_circle() {
+    // A 180 degree circle inscribed in the height of a 2:1 image
+    let camera = FisheyeCamera::new(origin(), look_at(), up(), 180.0, 2.0);
+    assert_direction(camera.get_ray(0.5, 0.5).unwrap().direction, Vec3::new(0.0, 0.0, -1.0), "centre");
+    // The rim of the circle looks 90 degrees away from the view direction
+    assert_direction(camera.get_ray(0.75, 0.5).unwrap().direction, Vec3::new(1.0, 0.0, 0.0), "right of the rim");
+    assert_direction(camera.get_ray(0.5, 0.0).unwrap().direction, Vec3::new(0.0, 1.0, 0.0), "top of the rim");
+
+    for (u, v) in [(0.0, 0.0), (1.0, 1.0), (0.77, 0.5), (0.1, 0.5), (0.7, 0.1)] {
+        assert!(camera.get_ray(u, v).is_none(), "({}, {}) is outside the circle", u, v);
+    }
+    assert!(camera.get_ray(0.73, 0.5).is_some());
+}
+
+#[test]
+fn stereo_eyes_are_an_interpupillary_distance_apart() {
+    let ipd = 0.064;
+    let left = OmniStereoCamera::new(origin(), look_at(), up(), ipd, StereoEye::Left);
+    let right = OmniStereoCamera::new(origin(), look_at(), up(), ipd, StereoEye::Right);
+    let top_bottom = OmniStereoCamera::new(origin(), look_at(), up(), ipd, StereoEye::TopBottom);
+
+    for (u, v) in [(0.5, 0.5), (0.1, 0.3), (0.8, 0.7), (0.37, 0.05)] {
+        let (l, r) = (left.get_ray(u, v), right.get_ray(u, v));
+        let baseline = r.origin - l.origin;
+        assert!((baseline.length() - ipd).abs() < 1e-5, "({}, {}): eyes {} apart", u, v, baseline.length());
+        // Both eyes look the same way, from either side of the head
+        assert_direction(l.direction, r.direction.normalise(), "same direction");
+        assert!(baseline.dot(l.direction.normalise()).abs() < 1e-5);
+        assert!(((l.origin + r.origin) / 2.0 - origin()).length() < 1e-5);
+
+        // The top half of a top-bottom image is the left eye and the bottom half the right eye
+        let top = top_bottom.get_ray(u, v / 2.0);
+        let bottom = top_bottom.get_ray(u, 0.5 + v / 2.0);
+        assert!((top.origin - l.origin).length() < 1e-5 && (bottom.origin - r.origin).length() < 1e-5);
+    }
+
+    // Looking forward, the right eye sits to the right
+    let baseline = right.get_ray(0.5, 0.5).origin - left.get_ray(0.5, 0.5).origin;
+    assert_direction(baseline, Vec3::new(1.0, 0.0, 0.0), "baseline");
+}
+
+#[test]
+fn focal_length_gives_the_field_of_view() {
+    // A 50mm lens on a 36x24mm sensor sees 2 * atan(12 / 50) = 26.99 degrees vertically
+    let expected = 2.0 * (12.0 as Real / 50.0).atan();
+    let camera = CameraBuilder::new(origin())
+        .with_look_at(look_at())
+        .with_aspect_ratio(1.5)
+        .with_sensor_width(36.0)
+        .with_focal_length(50.0)
+        .build();
+
+    // No aperture, so the lens is a pinhole and the sampler does not move the ray
+    let mut sampler = SamplerKind::Independent.create(1, 0);
+    let centre = camera.get_ray(0.5, 0.5, sampler.as_mut()).unwrap().direction.normalise();
+    let top = camera.get_ray(0.5, 0.0, sampler.as_mut()).unwrap().direction.normalise();
+    let left = camera.get_ray(0.0, 0.5, sampler.as_mut()).unwrap().direction.normalise();
+    assert_direction(centre, Vec3::new(0.0, 0.0, -1.0), "centre");
+    let vfov = 2.0 * centre.dot(top).acos();
+    assert!((vfov - expected).abs() < 1e-4, "vertical field of view {} instead of {}", vfov.to_degrees(), expected.to_degrees());
+    // And 2 * atan(18 / 50) horizontally
+    let hfov = 2.0 * centre.dot(left).acos();
+    assert!((hfov - 2.0 * (18.0 as Real / 50.0).atan()).abs() < 1e-4, "horizontal field of view {}", hfov.to_degrees());
+}
+
+#[test]
+fn exposure_scales_with_shutter_time_and_iso() {
+    let camera = CameraBuilder::new(origin()).with_f_number(8.0).with_shutter_speed(1.0 / 250.0).with_iso(100.0);
+    let exposure = camera.exposure();
+    assert!(exposure > 0.0);
+
+    let longer = camera.with_shutter_speed(1.0 / 125.0).exposure();
+    let faster_film = camera.with_iso(200.0).exposure();
+    assert!((longer / exposure - 2.0).abs() < 1e-4, "doubling the shutter time scales exposure by {}", longer / exposure);
+    assert!((faster_film / exposure - 2.0).abs() < 1e-4, "doubling the ISO scales exposure by {}", faster_film / exposure);
+    // One stop smaller aperture halves it again
+    let stopped_down = camera.with_f_number(8.0 * (2.0 as Real).sqrt()).exposure();
+    assert!((stopped_down / exposure - 0.5).abs() < 1e-4);
+
+    // The built camera applies the same exposure
+    assert!((camera.build().exposure() - exposure).abs() < 1e-6 * exposure);
+}
+
+#[test]
+fn no_shutter_or_iso_leaves_the_image_unexposed() {
+    let camera = CameraBuilder::new(origin()).with_f_number(2.8).with_focal_length(35.0);
+    assert_eq!(camera.exposure(), 1.0);
+    assert_eq!(camera.build().exposure(), 1.0);
+}
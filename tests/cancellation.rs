@@ -0,0 +1,92 @@
+// Stopping a render early, by cancelling it or with a time limit: the render returns what it
+// has, each pixel averaged over the samples it actually got, and says how many that was.
+use std::time::{Duration, Instant};
+use parallel_raytracing_rs::{render_with_observer, CancellationToken, RenderObserver, RenderSettings, RenderStatus, TileUpdate};
+use common::{default_scene, render_default, settings};
+
+mod common;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 40;
+
+// Cancels the render as soon as the first tile has had a pass of samples
+struct CancelAfterFirstTile {
+    token: CancellationToken,
+}
+
+impl RenderObserver for CancelAfterFirstTile {
+    fn tile_completed(&self, _tile: &TileUpdate) {
+        self.token.cancel();
+    }
+}
+
+#[test]
+fn uncancelled_render_is_complete() {
+    let output = render_default(&settings(WIDTH, HEIGHT, 4)).unwrap();
+    assert_eq!(output.status, RenderStatus::Complete);
+    assert!(output.sample_counts.iter().all(|&n| n == 4));
+}
+
+#[test]
+fn cancelled_before_starting() {
+    let token = CancellationToken::new();
+    token.cancel();
+    let output = render_default(&RenderSettings { cancel: Some(token), ..settings(WIDTH, HEIGHT, 16) }).unwrap();
+
+    assert_eq!(output.status, RenderStatus::Cancelled);
+    assert!(output.sample_counts.iter().all(|&n| n == 0));
+    assert!(output.image.pixels().iter().all(|c| (c.x, c.y, c.z) == (0.0, 0.0, 0.0)));
+}
+
+#[test]
+fn cancelled_render_is_normalised() {
+    let token = CancellationToken::new();
+    let cancellable = RenderSettings { cancel: Some(token.clone()), ..settings(WIDTH, HEIGHT, 64) };
+    let output = render_with_observer(default_scene(&cancellable), &cancellable, &CancelAfterFirstTile { token }).unwrap();
+    assert_eq!(output.status, RenderStatus::Cancelled);
+
+    // Only the first tile got its first pass of samples
+    let partial = output.sample_counts.iter().copied().filter(|&n| n > 0).collect::<Vec<_>>();
+    assert_eq!(partial.len(), 32 * 32);
+    let samples = partial[0];
+    assert!(samples > 0 && samples < 64);
+    assert!(partial.iter().all(|&n| n == samples));
+
+    let reference = render_default(&settings(WIDTH, HEIGHT, samples)).unwrap();
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let (got, expected) = (output.image.pixel(x, y), reference.image.pixel(x, y));
+            if output.sample_count(x, y) == 0 {
+                assert_eq!((got.x, got.y, got.z), (0.0, 0.0, 0.0));
+            } else {
+                assert_eq!((got.x, got.y, got.z), (expected.x, expected.y, expected.z), "pixel ({}, {})", x, y);
+            }
+        }
+    }
+}
+
+#[test]
+fn time_limit_stops_the_render() {
+    let limit = Duration::from_millis(500);
+    let start = Instant::now();
+    let output = render_default(&RenderSettings { time_limit: Some(limit), ..settings(WIDTH, HEIGHT, u32::MAX) }).unwrap();
+    let elapsed = start.elapsed();
+
+    assert_eq!(output.status, RenderStatus::TimedOut);
+    // Stopping waits for at most the pixel being rendered
+    assert!(elapsed < limit + Duration::from_secs(1), "took {:?}", elapsed);
+    // A pass of the small image takes far less than the budget, so every pixel was reached
+    // and none is more than a pass ahead of another
+    let fewest = *output.sample_counts.iter().min().unwrap();
+    let most = *output.sample_counts.iter().max().unwrap();
+    assert!(fewest > 0);
+    assert!(most - fewest <= 8);
+    assert!(output.image.pixels().iter().all(|c| c.x.is_finite() && c.y.is_finite() && c.z.is_finite()));
+}
+
+#[test]
+fn time_limit_with_room_to_spare_completes() {
+    let output = render_default(&RenderSettings { time_limit: Some(Duration::from_secs(600)), ..settings(WIDTH, HEIGHT, 4) }).unwrap();
+    assert_eq!(output.status, RenderStatus::Complete);
+    assert!(output.sample_counts.iter().all(|&n| n == 4));
+}
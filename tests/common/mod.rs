@@ -0,0 +1,28 @@
+// Settings and a render of the default scene shared by the integration tests. Each test
+// crate uses only some of these.
+#![allow(dead_code)]
+use parallel_raytracing_rs::{render, scene, Filter, Real, RenderError, RenderOutput, RenderSettings, SamplerKind};
+
+// One thread, deterministic samples and a box filter, so a pixel with n samples matches the
+// same pixel rendered with `samples_per_pixel: n`, and every sample stays in its own tile
+pub fn settings(image_width: u32, image_height: u32, samples_per_pixel: u32) -> RenderSettings {
+    RenderSettings {
+        image_width,
+        image_height,
+        samples_per_pixel,
+        max_depth: 4,
+        num_threads: 1,
+        sampler: SamplerKind::Sobol,
+        filter: Filter::Box { radius: 0.5 },
+        ..RenderSettings::default()
+    }
+}
+
+// The default scene framed for the image size in `settings`
+pub fn default_scene(settings: &RenderSettings) -> scene::Scene {
+    scene::default_scene(settings.image_width as Real / settings.image_height as Real, 20.0)
+}
+
+pub fn render_default(settings: &RenderSettings) -> Result<RenderOutput, RenderError> {
+    render(default_scene(settings), settings)
+}
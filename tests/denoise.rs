@@ -0,0 +1,85 @@
+// The a-trous denoiser leaves flat images alone, stops at edges in the albedo and normal
+// guides, and smooths noise inside a flat region.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use parallel_raytracing_rs::utils::denoise::denoise;
+use parallel_raytracing_rs::{DenoiseSettings, Vec3};
+
+const WIDTH: u32 = 32;
+const HEIGHT: u32 = 32;
+
+// Three floats per pixel, the layout of the AOV passes
+fn guide(f: impl Fn(u32, u32) -> [f32; 3]) -> Vec<f32> {
+    (0..HEIGHT).flat_map(|y| (0..WIDTH).map(move |x| (x, y))).flat_map(|(x, y)| f(x, y)).collect()
+}
+
+fn image(f: impl Fn(u32, u32) -> Vec3<f32>) -> Vec<Vec3<f32>> {
+    (0..HEIGHT).flat_map(|y| (0..WIDTH).map(move |x| (x, y))).map(|(x, y)| f(x, y)).collect()
+}
+
+fn mean_and_variance(values: impl Iterator<Item = f32> + Clone) -> (f32, f32) {
+    let n = values.clone().count() as f32;
+    let mean = values.clone().sum::<f32>() / n;
+    (mean, values.map(|v| (v - mean) * (v - mean)).sum::<f32>() / n)
+}
+
+#[test]
+fn constant_image_is_unchanged() {
+    let color = image(|_, _| Vec3::new(0.4, 0.3, 0.2));
+    let albedo = guide(|_, _| [0.8, 0.6, 0.5]);
+    let normal = guide(|_, _| [0.0, 1.0, 0.0]);
+
+    let denoised = denoise(&color, &albedo, &normal, WIDTH, HEIGHT, &DenoiseSettings::default());
+    for pixel in denoised {
+        assert!((pixel.x - 0.4).abs() < 1e-5 && (pixel.y - 0.3).abs() < 1e-5 && (pixel.z - 0.2).abs() < 1e-5, "{:?}", pixel);
+    }
+}
+
+// Irradiance steps from 1 to 1.5 halfway across. The colour tolerance is so loose that only the
+// guides can keep the two sides apart.
+fn filter_step(albedo: &[f32], normal: &[f32]) -> (f32, f32) {
+    let irradiance = |x: u32| if x < WIDTH / 2 { 1.0 } else { 1.5 };
+    let color = image(|x, y| {
+        let a = (y * WIDTH + x) as usize * 3;
+        Vec3::new(albedo[a], albedo[a + 1], albedo[a + 2]) * irradiance(x)
+    });
+    let settings = DenoiseSettings { color_sigma: 100.0, ..DenoiseSettings::default() };
+    let denoised = denoise(&color, albedo, normal, WIDTH, HEIGHT, &settings);
+
+    // The pixels on either side of the edge, as a fraction of what they were
+    let y = HEIGHT / 2;
+    let left = (y * WIDTH + WIDTH / 2 - 1) as usize;
+    (denoised[left].x / color[left].x, denoised[left + 1].x / color[left + 1].x)
+}
+
+#[test]
+fn edges_in_the_guides_survive_filtering() {
+    let flat_albedo = guide(|_, _| [0.5, 0.5, 0.5]);
+    let flat_normal = guide(|_, _| [0.0, 0.0, 1.0]);
+    let albedo_edge = guide(|x, _| if x < WIDTH / 2 { [0.5, 0.5, 0.5] } else { [0.9, 0.9, 0.9] });
+    let normal_edge = guide(|x, _| if x < WIDTH / 2 { [0.0, 0.0, 1.0] } else { [1.0, 0.0, 0.0] });
+
+    for (what, albedo, normal) in [("albedo", &albedo_edge, &flat_normal), ("normal", &flat_albedo, &normal_edge)] {
+        let (left, right) = filter_step(albedo, normal);
+        assert!((left - 1.0).abs() < 0.01 && (right - 1.0).abs() < 0.01, "{} edge blurred: {} and {}", what, left, right);
+    }
+
+    // Without an edge in either guide the same step is blurred, so the guides are what keep it
+    let (left, right) = filter_step(&flat_albedo, &flat_normal);
+    assert!(left > 1.05 && right < 0.95, "step kept without guides: {} and {}", left, right);
+}
+
+#[test]
+fn noise_in_a_flat_patch_is_reduced() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let color = image(|_, _| Vec3::new(0.5, 0.5, 0.5));
+    let color: Vec<Vec3<f32>> = color.into_iter().map(|c| c * (1.0 + rng.gen_range(-0.3..0.3))).collect();
+    let albedo = guide(|_, _| [0.5, 0.5, 0.5]);
+    let normal = guide(|_, _| [0.0, 1.0, 0.0]);
+
+    let denoised = denoise(&color, &albedo, &normal, WIDTH, HEIGHT, &DenoiseSettings::default());
+    let (mean_before, variance_before) = mean_and_variance(color.iter().map(|c| c.x));
+    let (mean_after, variance_after) = mean_and_variance(denoised.iter().map(|c| c.x));
+    assert!(variance_after < 0.1 * variance_before, "variance {} -> {}", variance_before, variance_after);
+    assert!((mean_after - mean_before).abs() < 0.01 * mean_before, "mean {} -> {}", mean_before, mean_after);
+}
@@ -0,0 +1,118 @@
+// What the renderer reports instead of panicking: settings it can't render, a render thread
+// that fails, files that can't be loaded or written.
+use std::path::Path;
+use parallel_raytracing_rs::{render_with_observer, scene, ApertureMask, Camera, EnvironmentMap, EquirectangularCamera, Framebuffer, HittableList, RenderError, RenderObserver, RenderSettings, Scene, TileUpdate, Vec3};
+use common::{default_scene, render_default, settings};
+
+mod common;
+
+fn assert_invalid(settings: RenderSettings) {
+    match render_default(&settings) {
+        Err(RenderError::InvalidSettings(_)) => {}
+        Err(e) => panic!("expected invalid settings, got {}", e),
+        Ok(_) => panic!("expected invalid settings, but the render succeeded"),
+    }
+}
+
+#[test]
+fn invalid_settings_are_rejected() {
+    assert_invalid(RenderSettings { image_width: 0, ..settings(24, 16, 2) });
+    assert_invalid(RenderSettings { image_height: 0, ..settings(24, 16, 2) });
+    assert_invalid(RenderSettings { samples_per_pixel: 0, ..settings(24, 16, 2) });
+    assert_invalid(RenderSettings { num_threads: 0, ..settings(24, 16, 2) });
+    assert_invalid(RenderSettings { image_width: 1 << 16, image_height: 1 << 16, ..settings(24, 16, 2) });
+}
+
+#[test]
+fn single_pixel_images_render() {
+    for (width, height) in [(1, 1), (1, 16), (24, 1)] {
+        let output = render_default(&RenderSettings { image_width: width, image_height: height, ..settings(24, 16, 2) }).unwrap();
+        assert!(output.image.pixels().iter().all(|c| c.x.is_finite() && c.y.is_finite() && c.z.is_finite()), "{}x{}", width, height);
+    }
+}
+
+struct FailingObserver;
+
+impl RenderObserver for FailingObserver {
+    fn tile_completed(&self, _tile: &TileUpdate) {
+        panic!("preview window closed");
+    }
+}
+
+#[test]
+fn worker_panic_is_an_error() {
+    match render_with_observer(default_scene(&settings(24, 16, 2)), &settings(24, 16, 2), &FailingObserver) {
+        Err(RenderError::Worker(message)) => assert!(message.contains("preview window closed"), "{}", message),
+        Err(e) => panic!("expected a worker failure, got {}", e),
+        Ok(_) => panic!("expected a worker failure, but the render succeeded"),
+    }
+}
+
+#[test]
+fn unwritable_output_is_saved_elsewhere() {
+    let settings = RenderSettings { output_file: "no/such/directory/errors_test_fallback.png".to_string(), ..settings(24, 16, 2) };
+    let output = render_default(&settings).unwrap();
+
+    let error = output.save(&settings).unwrap_err();
+    let RenderError::Image { path, fallback, .. } = &error else {
+        panic!("expected an image error, got {}", error);
+    };
+    assert_eq!(path, Path::new(&settings.output_file));
+    let fallback = fallback.as_ref().expect("no fallback image was written");
+    assert_eq!(fallback.extension().unwrap(), "exr");
+    assert!(error.to_string().contains(&fallback.display().to_string()));
+
+    // The linear image, not the clamped and gamma corrected one the PNG would have held
+    let saved = image::open(fallback).unwrap().to_rgb32f();
+    std::fs::remove_file(fallback).unwrap();
+    assert!(saved == output.image.to_rgb32f_image(), "the fallback differs from the rendered image");
+}
+
+#[test]
+fn image_that_fails_to_encode_is_an_image_error() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let settings = RenderSettings { output_file: dir.join("errors_test_encoding.png").to_string_lossy().into_owned(), ..settings(24, 16, 2) };
+    let mut output = render_default(&settings).unwrap();
+    // PNG can't encode an image with no pixels
+    output.image = Framebuffer::new(0, 0);
+
+    let error = output.save(&settings).unwrap_err();
+    let RenderError::Image { path, fallback, .. } = &error else {
+        panic!("expected an image error, got {}", error);
+    };
+    assert_eq!(path, Path::new(&settings.output_file));
+    // Nor can OpenEXR, so there is no fallback to report
+    assert!(fallback.is_none(), "claims a fallback at {:?}", fallback);
+    assert!(!error.to_string().contains("instead"), "{}", error);
+}
+
+#[test]
+fn unwritable_stats_file_is_an_io_error() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let settings = RenderSettings {
+        output_file: dir.join("errors_test_stats.png").to_string_lossy().into_owned(),
+        stats_file: Some("no/such/directory/stats.json".to_string()),
+        ..settings(24, 16, 2)
+    };
+    let output = render_default(&settings).unwrap();
+    assert!(matches!(output.save(&settings), Err(RenderError::Io { .. })));
+}
+
+#[test]
+fn missing_scene_files_are_scene_errors() {
+    assert!(matches!(EnvironmentMap::open("no/such/sky.hdr"), Err(RenderError::Scene(_))));
+    assert!(matches!(ApertureMask::open("no/such/mask.png"), Err(RenderError::Scene(_))));
+}
+
+#[test]
+fn autofocus_without_a_target_is_a_scene_error() {
+    assert!(scene::default_scene(1.5, 20.0).with_autofocus(0.5, 0.5).is_ok());
+
+    let camera = Camera::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0), 20.0, 1.5, 0.1, 1.0);
+    let empty = Scene::new(HittableList::new(vec![]), camera);
+    assert!(matches!(empty.with_autofocus(0.5, 0.5), Err(RenderError::Scene(_))));
+
+    let panorama = Camera::Equirectangular(EquirectangularCamera::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0)));
+    let scene = scene::default_scene(1.5, 20.0).with_camera(panorama);
+    assert!(matches!(scene.with_autofocus(0.5, 0.5), Err(RenderError::Scene(_))));
+}
@@ -0,0 +1,44 @@
+// Reconstruction filters: every filter averages a constant image back to that constant, and the
+// box gives a sample on the edge between two pixels to exactly one of them.
+use parallel_raytracing_rs::utils::film::Film;
+use parallel_raytracing_rs::{Filter, Real, Vec3};
+
+const WIDTH: u32 = 12;
+const HEIGHT: u32 = 8;
+
+fn filters() -> [Filter; 5] {
+    [
+        Filter::Box { radius: 0.5 },
+        Filter::Tent { radius: 1.0 },
+        Filter::Gaussian { radius: 1.5, sigma: 0.5 },
+        Filter::Mitchell { radius: 2.0, b: 1.0 / 3.0, c: 1.0 / 3.0 },
+        Filter::Lanczos { radius: 3.0, tau: 3.0 },
+    ]
+}
+
+#[test]
+fn filters_reconstruct_a_constant_image() {
+    let constant = Vec3::new(0.25, 0.5, 0.75);
+    for filter in filters() {
+        let mut film = Film::new(WIDTH, HEIGHT, filter);
+        // A 4x4 grid of samples in every pixel, off the pixel centres and edges
+        for y in 0..HEIGHT * 4 {
+            for x in 0..WIDTH * 4 {
+                film.add_sample((x as Real + 0.37) / 4.0, (y as Real + 0.61) / 4.0, constant);
+            }
+        }
+        for pixel in film.resolve() {
+            let error = (pixel.x - constant.x).abs().max((pixel.y - constant.y).abs()).max((pixel.z - constant.z).abs());
+            assert!(error < 1e-4, "{:?}: {:?}", filter, pixel);
+        }
+    }
+}
+
+#[test]
+fn box_gives_an_edge_sample_to_one_pixel() {
+    let mut film = Film::new(2, 1, Filter::Box { radius: 0.5 });
+    film.add_sample(1.0, 0.5, Vec3::new(1.0, 1.0, 1.0));
+    let pixels = film.resolve();
+    assert_eq!(pixels[0].x, 0.0, "the pixel left of the edge took the sample");
+    assert_eq!(pixels[1].x, 1.0, "the pixel right of the edge missed the sample");
+}
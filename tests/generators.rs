@@ -0,0 +1,68 @@
+// Every procedural scene can be picked by name and renders, the seeded ones the same way every
+// time for a seed, and names the generators don't know are reported as scene errors.
+use parallel_raytracing_rs::utils::generators::{MAX_FLAKE_DEPTH, MAX_GRID_SIZE, MAX_STRESS_SPHERES};
+use parallel_raytracing_rs::{render, Generator, Hittable, RenderError, RenderSettings, Vec3};
+
+mod common;
+
+const NAMES: [&str; 8] = ["default", "one-weekend", "next-week", "rest-of-your-life", "cornell-box", "material-grid", "sphere-flake", "stress"];
+
+fn render_generated(generator: Generator, seed: u64) -> Vec<Vec3<f32>> {
+    let settings = RenderSettings { max_depth: 3, ..common::settings(24, 16, 2) };
+    render(generator.generate(seed, 1.5).unwrap(), &settings).unwrap().image.into_pixels()
+}
+
+#[test]
+fn every_name_renders() {
+    for name in NAMES {
+        let generator = Generator::from_name(name).unwrap();
+        let image = render_generated(generator, 0);
+        assert!(image.iter().all(|p| p.x.is_finite() && p.y.is_finite() && p.z.is_finite()), "{}", name);
+        assert!(image.iter().any(|p| p.y > 0.0), "{} rendered black", name);
+    }
+}
+
+#[test]
+fn seeds_are_reproducible() {
+    for generator in [Generator::OneWeekend, Generator::NextWeek, Generator::Stress { spheres: 500 }] {
+        let pixels = |seed| render_generated(generator, seed).iter().map(|p| (p.x, p.y, p.z)).collect::<Vec<_>>();
+        assert_eq!(pixels(3), pixels(3), "{:?}", generator);
+        assert_ne!(pixels(3), pixels(4), "{:?}", generator);
+    }
+}
+
+#[test]
+fn parameters_set_the_size() {
+    let objects = |name: &str| Generator::from_name(name).unwrap().generate(0, 1.5).unwrap().world.object_count();
+    // The ground, then 1, 9 and 81 spheres for each level
+    assert_eq!(objects("sphere-flake:0"), 2);
+    assert_eq!(objects("sphere-flake:2"), 1 + 1 + 9 + 81);
+    assert_eq!(objects("stress:1234"), 1234);
+    // A coat and a core in every cell, on the ground
+    assert_eq!(objects("material-grid:3"), 1 + 2 * 9);
+    assert_eq!(Generator::from_name("sphere-flake").unwrap(), Generator::SphereFlake { depth: 3 });
+}
+
+#[test]
+fn bad_names_are_scene_errors() {
+    for name in ["", "nope", "sphere-flake:", "sphere-flake:deep", "sphere-flake:7", "stress:0", "stress:1000001", "stress:4000000000", "material-grid:17", "cornell-box:2"] {
+        assert!(matches!(Generator::from_name(name), Err(RenderError::Scene(_))), "{:?}", name);
+    }
+    let message = Generator::from_name("nope").unwrap_err().to_string();
+    assert!(message.contains("nope") && message.contains("cornell-box"), "{}", message);
+}
+
+#[test]
+fn generate_checks_the_limits() {
+    let out_of_range = [
+        Generator::SphereFlake { depth: MAX_FLAKE_DEPTH + 1 },
+        Generator::MaterialGrid { size: MAX_GRID_SIZE + 1 },
+        Generator::MaterialGrid { size: 0 },
+        Generator::Stress { spheres: MAX_STRESS_SPHERES + 1 },
+        Generator::Stress { spheres: 0 },
+    ];
+    for generator in out_of_range {
+        assert!(matches!(generator.generate(0, 1.5), Err(RenderError::Scene(_))), "{:?}", generator);
+    }
+    assert!(Generator::SphereFlake { depth: 0 }.generate(0, 1.5).is_ok());
+}
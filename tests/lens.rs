@@ -0,0 +1,58 @@
+// Aperture sampling and the tilt-shift lens: polygonal apertures keep their samples inside the
+// iris, and a lens with no tilt or shift traces the same rays as a plain thin lens.
+use parallel_raytracing_rs::utils::camera::PerspectiveCamera;
+use parallel_raytracing_rs::utils::real::consts::PI;
+use parallel_raytracing_rs::{Aperture, Real, SamplerKind, Vec3};
+
+// Corners of the polygon `sample_polygon` splits into triangles, counterclockwise
+fn corners(blades: u32, rotation: Real) -> Vec<(Real, Real)> {
+    (0..blades).map(|i| {
+        let angle = rotation.to_radians() + PI / 2.0 + 2.0 * PI * i as Real / blades as Real;
+        (angle.cos(), angle.sin())
+    }).collect()
+}
+
+#[test]
+fn polygon_samples_stay_inside_the_polygon() {
+    const N: u32 = 64;
+    for (blades, rotation) in [(3, 0.0), (5, 0.0), (6, 15.0), (8, 40.0)] {
+        let aperture = Aperture::Polygon { blades, rotation };
+        let corners = corners(blades, rotation);
+        let mut farthest: Real = 0.0;
+        for i in 0..N {
+            for j in 0..N {
+                let u = ((i as Real + 0.5) / N as Real, (j as Real + 0.5) / N as Real);
+                let (x, y) = aperture.sample(u);
+                for k in 0..blades as usize {
+                    let (a, b) = (corners[k], corners[(k + 1) % blades as usize]);
+                    let cross = (b.0 - a.0) * (y - a.1) - (b.1 - a.1) * (x - a.0);
+                    assert!(cross >= -1e-5, "{} blades rotated {}: ({}, {}) outside the edge {:?} to {:?}", blades, rotation, x, y, a, b);
+                }
+                farthest = farthest.max((x * x + y * y).sqrt());
+            }
+        }
+        // Reaching into the corners, not just a disk inside the polygon
+        assert!(farthest > 0.9, "{} blades: samples reach only {}", blades, farthest);
+    }
+}
+
+#[test]
+fn zero_tilt_and_shift_trace_the_same_rays() {
+    let camera = PerspectiveCamera::new(
+        Vec3::new(13.0, 2.0, 3.0), Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 20.0, 1.5, 0.4, 10.0,
+    );
+    let adjusted = camera.clone().with_tilt(0.0, 0.0).with_shift(0.0, 0.0);
+
+    let (mut a, mut b) = (SamplerKind::Sobol.create(16, 3), SamplerKind::Sobol.create(16, 3));
+    for (x, y) in [(0, 0), (7, 3), (29, 19)] {
+        for s in 0..16 {
+            a.start_pixel_sample(x, y, s);
+            b.start_pixel_sample(x, y, s);
+            let (u, v) = ((x as Real + 0.5) / 30.0, (y as Real + 0.5) / 20.0);
+            let (plain, tilted) = (camera.get_ray(u, v, a.as_mut()).unwrap(), adjusted.get_ray(u, v, b.as_mut()).unwrap());
+            assert!((plain.origin - tilted.origin).length() < 1e-5, "({}, {}) sample {}", x, y, s);
+            let (d, e) = (plain.direction.normalise(), tilted.direction.normalise());
+            assert!((d - e).length() < 1e-5, "({}, {}) sample {}: {:?} against {:?}", x, y, s, d, e);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+// The three-point rig from `LightList::three_point`: every light must aim at the subject from a
+// finite position wherever the camera looks from, including straight up and straight down.
+use parallel_raytracing_rs::utils::vector::Dot;
+use parallel_raytracing_rs::{Light, LightList, Real, Vec3};
+
+fn positions(lights: &LightList) -> Vec<Vec3<Real>> {
+    lights.lights.iter().map(|light| match light {
+        Light::Spot { position, .. } => *position,
+        other => panic!("expected spot lights, got {:?}", other),
+    }).collect()
+}
+
+fn finite(v: Vec3<Real>) -> bool {
+    v.x.is_finite() && v.y.is_finite() && v.z.is_finite()
+}
+
+#[test]
+fn three_point_lights_reach_the_target_from_any_camera() {
+    let target = Vec3::new(0.0, 1.0, -1.0);
+    let cameras = [
+        Vec3::new(0.0, 1.0, 4.0),
+        Vec3::new(3.0, 2.0, 2.0),
+        // Straight above and below the target, and nearly so
+        Vec3::new(0.0, 6.0, -1.0),
+        Vec3::new(0.0, -4.0, -1.0),
+        Vec3::new(0.0001, 6.0, -1.0),
+    ];
+    for camera in cameras {
+        let lights = LightList::three_point(target, camera, 100.0);
+        assert_eq!(lights.lights.len(), 3);
+        for (light, position) in lights.lights.iter().zip(positions(&lights)) {
+            assert!(finite(position), "camera {:?}: light at {:?}", camera, position);
+            assert!((position - target).length() > 0.1, "camera {:?}: light on the target", camera);
+            // Aimed at the target, so the target sits in the middle of the cone at full brightness
+            let sample = light.sample(target).unwrap_or_else(|| panic!("camera {:?}: {:?} misses the target", camera, light));
+            assert!(finite(sample.radiance) && sample.radiance.x > 0.0, "camera {:?}: {:?}", camera, sample.radiance);
+        }
+    }
+}
+
+#[test]
+fn key_and_fill_light_opposite_sides_and_the_rim_is_behind() {
+    let target = Vec3::new(0.0, 1.0, -1.0);
+    let camera = Vec3::new(0.0, 1.0, 4.0);
+    let forward = (target - camera).normalise();
+    let side = forward.cross(Vec3::new(0.0, 1.0, 0.0));
+
+    let lights = LightList::three_point(target, camera, 100.0);
+    let [key, fill, rim] = positions(&lights)[..] else { unreachable!() };
+    assert!((key - target).dot(side) > 0.0);
+    assert!((fill - target).dot(side) < 0.0);
+    assert!((key - target).dot(forward) < 0.0 && (fill - target).dot(forward) < 0.0);
+    assert!((rim - target).dot(forward) > 0.0);
+}
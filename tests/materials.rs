@@ -0,0 +1,185 @@
+// Statistical checks of every material in `validation::catalogue`: sampled directions against
+// the densities the materials report, energy conservation, and white furnaces in which an
+// object with an albedo of one must vanish into a uniform environment.
+use parallel_raytracing_rs::utils::environment::{Environment, EnvironmentMap};
+use parallel_raytracing_rs::utils::material::{scatter, schlick, Material};
+use parallel_raytracing_rs::utils::real::{to_f64, Real};
+use parallel_raytracing_rs::utils::renderer::{render, RenderSettings};
+use parallel_raytracing_rs::utils::sampler::SamplerKind;
+use parallel_raytracing_rs::utils::validation::{self, catalogue, hit_at, variant_name};
+use parallel_raytracing_rs::utils::vector::{Dot, Vec3};
+
+const SAMPLES: usize = 200_000;
+// Cosines of the angles light arrives at, from head on to grazing
+const INCIDENT_COSINES: [Real; 4] = [1.0, 0.7, 0.3, 0.05];
+// Chance of any one goodness-of-fit test failing a correct material, split between the tests
+const SIGNIFICANCE: f64 = 0.01;
+
+fn describe(material: &Material, cos_theta: Real, inside: bool) -> String {
+    let side = if inside { "inside" } else { "outside" };
+    format!("{} (albedo {:?}) at cos(theta) = {} from the {}", variant_name(material), material.albedo(), cos_theta, side)
+}
+
+// Every incidence a material is checked at. Only transmissive materials are hit from within.
+fn incidences(material: &Material) -> Vec<(Real, bool)> {
+    let sides: &[bool] = match material {
+        Material::Dielctric { .. } => &[false, true],
+        _ => &[false],
+    };
+    sides.iter().flat_map(|&inside| INCIDENT_COSINES.map(|c| (c, inside))).collect()
+}
+
+#[test]
+fn catalogue_covers_every_variant() {
+    let mut names: Vec<&str> = catalogue().iter().map(variant_name).collect();
+    names.dedup();
+    assert_eq!(names, ["lambertian", "metal", "dielectric"], "catalogue walks {:?}", names);
+    for material in catalogue() {
+        assert!(validation::examples(&material).iter().all(|example| variant_name(example) == variant_name(&material)));
+    }
+}
+
+#[test]
+fn scattered_directions_follow_pdf() {
+    let cases: Vec<(Material, Real, bool)> = catalogue().into_iter()
+        .filter(|m| !m.is_specular())
+        .flat_map(|m| incidences(&m).into_iter().map(move |(c, inside)| (m.clone(), c, inside)))
+        .collect();
+    let threshold = 1.0 - (1.0 - SIGNIFICANCE).powf(1.0 / cases.len() as f64);
+
+    for (i, (material, cos_theta, inside)) in cases.iter().enumerate() {
+        let (r_in, rec) = hit_at(material, *cos_theta, *inside);
+        let mut sampler = SamplerKind::Independent.create(1, i as u64);
+        let result = validation::chi_square(material, &r_in, &rec, sampler.as_mut(), SAMPLES).unwrap();
+        assert!(
+            result.p_value > threshold,
+            "{}: chi-square {:.1} with {} degrees of freedom, p = {:.2e} (needs > {:.2e})",
+            describe(material, *cos_theta, *inside), result.statistic, result.degrees_of_freedom, result.p_value, threshold
+        );
+    }
+}
+
+#[test]
+fn sampling_weights_match_eval() {
+    for material in catalogue() {
+        for (cos_theta, inside) in incidences(&material) {
+            let (r_in, rec) = hit_at(&material, cos_theta, inside);
+            let mut sampler = SamplerKind::Independent.create(1, 0);
+            if let Some(error) = validation::sampling_weight_error(&material, &r_in, &rec, sampler.as_mut(), 10_000) {
+                assert!(error < 1e-3, "{}: attenuation differs from eval / pdf by {}", describe(&material, cos_theta, inside), error);
+            }
+        }
+    }
+}
+
+#[test]
+fn energy_is_conserved() {
+    for material in catalogue() {
+        let white = validation::white(&material);
+        for (cos_theta, inside) in incidences(&white) {
+            let (r_in, rec) = hit_at(&white, cos_theta, inside);
+            let mut sampler = SamplerKind::Independent.create(1, 0);
+            let albedo = validation::directional_albedo(&white, &r_in, &rec, sampler.as_mut(), SAMPLES);
+            let description = describe(&white, cos_theta, inside);
+
+            for c in [albedo.x, albedo.y, albedo.z] {
+                assert!(c <= 1.0 + 1e-4, "{} reflects more than it receives: {}", description, c);
+                if !validation::absorbs_at_white(&white) {
+                    assert!(c >= 1.0 - 1e-4, "{} loses energy: {}", description, c);
+                }
+            }
+
+            // Light sampling sees the material through `eval`, which must agree with `scatter`
+            if let Some(integrated) = validation::integrated_albedo(&white, &r_in, &rec) {
+                let error = (integrated.x - albedo.x).abs();
+                assert!(error < 0.01, "{}: eval integrates to {} but scatter gives {}", description, integrated.x, albedo.x);
+            }
+        }
+    }
+}
+
+// Glass picks between its two specular lobes with the Fresnel reflectance, falling back to
+// reflection past the critical angle
+#[test]
+fn dielectric_chooses_lobes_by_fresnel() {
+    let materials: Vec<Material> = catalogue().into_iter().filter(|m| matches!(m, Material::Dielctric { .. })).collect();
+    let cases: Vec<(Material, Real, bool)> = materials.iter()
+        .flat_map(|m| incidences(m).into_iter().map(move |(c, inside)| (m.clone(), c, inside)))
+        .collect();
+    let threshold = 1.0 - (1.0 - SIGNIFICANCE).powf(1.0 / cases.len() as f64);
+
+    for (i, (material, cos_theta, inside)) in cases.iter().enumerate() {
+        let Material::Dielctric { ir } = material else { unreachable!() };
+        let (r_in, rec) = hit_at(material, *cos_theta, *inside);
+        let ratio = if *inside { *ir } else { 1.0 / *ir };
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let reflectance = if ratio * sin_theta > 1.0 { 1.0 } else { schlick(*cos_theta, ratio) as f64 };
+
+        let mut sampler = SamplerKind::Independent.create(1, i as u64);
+        let mut reflected = 0.0;
+        for _ in 0..SAMPLES {
+            let (_, scattered, _) = scatter(material, &r_in, &rec, sampler.as_mut());
+            if scattered.direction.dot(rec.normal) > 0.0 {
+                reflected += 1.0;
+            }
+        }
+        let n = SAMPLES as f64;
+        let observed = [reflected, n - reflected];
+        let expected = [n * reflectance, n * (1.0 - reflectance)];
+        let result = validation::chi_square_statistic(&observed, &expected);
+        assert!(
+            result.p_value > threshold,
+            "{}: reflected {} of {} rays, expected {:.1}, p = {:.2e}",
+            describe(material, *cos_theta, *inside), reflected, SAMPLES, expected[0], result.p_value
+        );
+    }
+}
+
+fn render_furnace(material: Material, environment: Environment, sampler: SamplerKind) -> Vec<Vec3<f32>> {
+    let (width, height) = (32, 24);
+    let settings = RenderSettings {
+        image_width: width,
+        image_height: height,
+        samples_per_pixel: 16,
+        // Deep enough for light to find its way out of glass
+        max_depth: 64,
+        num_threads: 1,
+        sampler,
+        seed: 0,
+        ..RenderSettings::default()
+    };
+    let scene = validation::furnace_scene(material, environment, width as Real / height as Real);
+    render(scene, &settings).unwrap().image.into_pixels()
+}
+
+// Without light sampling every path carries exactly the environment's radiance back, so every
+// pixel must match it
+#[test]
+fn white_furnace() {
+    let radiance = 0.5;
+    for material in catalogue().iter().map(validation::white) {
+        let image = render_furnace(material.clone(), Environment::Constant(Vec3::new(radiance, radiance, radiance)), SamplerKind::Independent);
+        for (i, pixel) in image.iter().enumerate() {
+            for c in [pixel.x, pixel.y, pixel.z].map(|c| c as Real) {
+                assert!(c <= radiance + 1e-3, "{}: pixel {} is brighter than the furnace: {}", variant_name(&material), i, c);
+                if !validation::absorbs_at_white(&material) {
+                    assert!(c >= radiance - 1e-3, "{}: pixel {} is darker than the furnace: {}", variant_name(&material), i, c);
+                }
+            }
+        }
+    }
+}
+
+// With an importance sampled environment, light sampling and scattering share the work through
+// multiple importance sampling. Individual paths vary, so compare the image's mean.
+#[test]
+fn white_furnace_with_light_sampling() {
+    let radiance = 0.5;
+    let pixels = vec![Vec3::new(radiance, radiance, radiance); 64 * 32];
+    let environment = Environment::Image(EnvironmentMap::from_pixels(64, 32, pixels));
+    for material in catalogue().iter().map(validation::white).filter(|m| !validation::absorbs_at_white(m)) {
+        let image = render_furnace(material.clone(), environment.clone(), SamplerKind::Sobol);
+        let mean = image.iter().map(|p| p.y as f64).sum::<f64>() / image.len() as f64;
+        assert!((mean - to_f64(radiance)).abs() < 0.01, "{}: furnace image averages {} instead of {}", variant_name(&material), mean, radiance);
+    }
+}
@@ -0,0 +1,67 @@
+// Checks what a `RenderObserver` is told during a render: every pixel arrives in one tile per
+// pass of samples, the last pass's tiles match the final image, and progress only moves
+// forward and ends at 100%.
+use std::sync::Mutex;
+use parallel_raytracing_rs::{render_with_observer, Framebuffer, Progress, RenderObserver, RenderSettings, TileUpdate};
+use common::default_scene;
+
+mod common;
+
+#[derive(Default)]
+struct Recorder {
+    tiles: Mutex<Vec<(u32, u32, u32, Framebuffer)>>,
+    progress: Mutex<Vec<Progress>>,
+}
+
+impl RenderObserver for Recorder {
+    fn tile_completed(&self, tile: &TileUpdate) {
+        self.tiles.lock().unwrap().push((tile.x0, tile.y0, tile.samples, tile.pixels.clone()));
+    }
+
+    fn progress(&self, progress: Progress) {
+        self.progress.lock().unwrap().push(progress);
+    }
+}
+
+// Two passes of samples, the second one short
+fn settings(num_threads: u32) -> RenderSettings {
+    RenderSettings { num_threads, ..common::settings(80, 50, 12) }
+}
+
+#[test]
+fn tiles_cover_the_image_every_pass() {
+    for num_threads in [1, 3] {
+        let settings = settings(num_threads);
+        let recorder = Recorder::default();
+        let output = render_with_observer(default_scene(&settings), &settings, &recorder).unwrap();
+
+        let mut covered = vec![0; (settings.image_width * settings.image_height) as usize];
+        let mut assembled = Framebuffer::new(settings.image_width, settings.image_height);
+        for (x0, y0, samples, tile) in recorder.tiles.lock().unwrap().iter() {
+            for y in *y0..y0 + tile.height() {
+                for x in *x0..x0 + tile.width() {
+                    covered[(y * settings.image_width + x) as usize] += 1;
+                }
+            }
+            if *samples == settings.samples_per_pixel {
+                assembled.blit(tile, *x0, *y0);
+            }
+        }
+        assert!(covered.iter().all(|&n| n == 2), "{} threads: pixels not covered once per pass", num_threads);
+        let same = assembled.pixels().iter().zip(output.image.pixels()).all(|(a, b)| (a.x, a.y, a.z) == (b.x, b.y, b.z));
+        assert!(same, "{} threads: tiles differ from the image", num_threads);
+    }
+}
+
+#[test]
+fn progress_reaches_completion() {
+    let recorder = Recorder::default();
+    render_with_observer(default_scene(&settings(2)), &settings(2), &recorder).unwrap();
+
+    let progress = recorder.progress.lock().unwrap();
+    assert!(!progress.is_empty());
+    assert!(progress.windows(2).all(|w| w[0].samples_done <= w[1].samples_done));
+    let last = progress.last().unwrap();
+    assert_eq!(last.samples_done, last.samples_total);
+    assert_eq!(last.fraction(), 1.0);
+}
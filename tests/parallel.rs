@@ -0,0 +1,162 @@
+// The thread pool and the ways of dividing the image between its threads: every strategy and
+// thread count takes the same samples, so with a one pixel box filter the images are identical,
+// except that `Strategy::Samples` adds them up in a different order. Wider filters give the same
+// image on any number of threads, but only agree to rounding between strategies.
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Duration;
+use parallel_raytracing_rs::{render_with_observer, Filter, Pass, RenderObserver, RenderOutput, RenderPool, RenderSettings, RenderStatus, Strategy, TileUpdate};
+use common::{default_scene, render_default};
+
+mod common;
+
+fn settings(strategy: Strategy, num_threads: u32) -> RenderSettings {
+    RenderSettings { strategy, num_threads, ..common::settings(70, 45, 12) }
+}
+
+fn same_image(a: &RenderOutput, b: &RenderOutput) -> bool {
+    a.image.pixels().iter().zip(b.image.pixels()).all(|(a, b)| (a.x, a.y, a.z) == (b.x, b.y, b.z))
+}
+
+fn close_image(a: &RenderOutput, b: &RenderOutput) -> bool {
+    a.image.pixels().iter().zip(b.image.pixels()).all(|(a, b)| {
+        let difference = (a.x - b.x).abs().max((a.y - b.y).abs()).max((a.z - b.z).abs());
+        difference <= 1e-5 * a.x.abs().max(a.y.abs()).max(a.z.abs()).max(1.0)
+    })
+}
+
+#[test]
+fn strategies_agree() {
+    let reference = render_default(&settings(Strategy::Tiles, 1)).unwrap();
+    for strategy in [Strategy::Rows, Strategy::Tiles, Strategy::SamplePasses] {
+        for num_threads in [1, 3] {
+            let output = render_default(&settings(strategy, num_threads)).unwrap();
+            assert!(same_image(&output, &reference), "{:?} on {} threads", strategy, num_threads);
+            assert!(output.sample_counts.iter().all(|&n| n == 12));
+            assert_eq!(output.stats.total_rays(), reference.stats.total_rays(), "{:?} on {} threads", strategy, num_threads);
+        }
+    }
+}
+
+#[test]
+fn strategies_agree_with_a_wide_filter() {
+    // Neighbouring blocks' films overlap by the filter's reach and are added up in layout order,
+    // so each strategy gives the same image on any number of threads, and the strategies agree
+    // with each other to rounding
+    let mitchell = |strategy, num_threads| RenderSettings { filter: Filter::Mitchell { radius: 2.0, b: 1.0 / 3.0, c: 1.0 / 3.0 }, ..settings(strategy, num_threads) };
+    let reference = render_default(&mitchell(Strategy::Tiles, 1)).unwrap();
+    for strategy in [Strategy::Rows, Strategy::Tiles, Strategy::SamplePasses] {
+        let single = render_default(&mitchell(strategy, 1)).unwrap();
+        assert!(close_image(&single, &reference), "{:?}", strategy);
+        assert!(same_image(&render_default(&mitchell(strategy, 3)).unwrap(), &single), "{:?} on 3 threads", strategy);
+    }
+}
+
+// Remembers which threads did the work
+#[derive(Default)]
+struct ThreadNames {
+    names: Mutex<HashSet<String>>,
+}
+
+impl RenderObserver for ThreadNames {
+    fn tile_completed(&self, _tile: &TileUpdate) {
+        let name = std::thread::current().name().unwrap_or("unnamed").to_string();
+        self.names.lock().unwrap().insert(name);
+    }
+}
+
+#[test]
+fn shared_pool_does_the_work() {
+    let pool = RenderPool::new(2, false).unwrap();
+    assert_eq!(pool.num_threads(), 2);
+    // The thread count is the pool's, whatever the settings say
+    let shared = RenderSettings { pool: Some(pool.clone()), num_threads: 0, ..settings(Strategy::Tiles, 1) };
+
+    let threads = ThreadNames::default();
+    let output = render_with_observer(default_scene(&shared), &shared, &threads).unwrap();
+    assert!(same_image(&output, &render_default(&settings(Strategy::Tiles, 1)).unwrap()));
+
+    let names = threads.names.into_inner().unwrap();
+    assert!(!names.is_empty());
+    assert!(names.iter().all(|name| name == "render-0" || name == "render-1"), "{:?}", names);
+
+    // The pool is still usable for the application's own work
+    assert_eq!(pool.install(|| 2 + 2), 4);
+}
+
+#[test]
+fn pinned_threads_render() {
+    let pinned = RenderSettings { pin_threads: true, ..settings(Strategy::Rows, 2) };
+    let output = render_default(&pinned).unwrap();
+    assert!(same_image(&output, &render_default(&settings(Strategy::Tiles, 1)).unwrap()));
+}
+
+#[test]
+fn sample_streams_match_tiles() {
+    let reference = render_default(&settings(Strategy::Tiles, 1)).unwrap();
+    // With one thread there is one stream, taking the samples in the same order as a tile
+    assert!(same_image(&render_default(&settings(Strategy::Samples, 1)).unwrap(), &reference));
+
+    // With more, each pixel's samples are added up in a different order but always the same one
+    let streams = RenderSettings { samples_per_pixel: 40, ..settings(Strategy::Samples, 3) };
+    let output = render_default(&streams).unwrap();
+    assert!(same_image(&output, &render_default(&streams).unwrap()));
+    assert!(output.sample_counts.iter().all(|&n| n == 40));
+
+    let reference = render_default(&RenderSettings { samples_per_pixel: 40, ..settings(Strategy::Tiles, 1) }).unwrap();
+    assert_eq!(output.stats.total_rays(), reference.stats.total_rays());
+    for (a, b) in output.image.pixels().iter().zip(reference.image.pixels()) {
+        let difference = (a.x - b.x).abs().max((a.y - b.y).abs()).max((a.z - b.z).abs());
+        assert!(difference <= 1e-4 * a.x.abs().max(a.y.abs()).max(a.z.abs()).max(1.0), "{:?} {:?}", (a.x, a.y, a.z), (b.x, b.y, b.z));
+    }
+}
+
+#[test]
+fn sample_streams_merge_passes() {
+    let passes = vec![Pass::Depth, Pass::ObjectId, Pass::SampleCount];
+    let output = render_default(&RenderSettings { passes: passes.clone(), samples_per_pixel: 20, ..settings(Strategy::Samples, 3) }).unwrap();
+    let reference = render_default(&RenderSettings { passes, samples_per_pixel: 20, ..settings(Strategy::Tiles, 1) }).unwrap();
+
+    assert_eq!(output.aov(Pass::ObjectId), reference.aov(Pass::ObjectId));
+    assert_eq!(output.aov(Pass::SampleCount), reference.aov(Pass::SampleCount));
+    for (a, b) in output.aov(Pass::Depth).unwrap().iter().zip(reference.aov(Pass::Depth).unwrap()) {
+        assert!(a == b || (a - b).abs() <= 1e-4 * a.abs(), "{} {}", a, b);
+    }
+}
+
+// Where each update was, its size and how many samples it had
+type Update = (u32, u32, u32, u32, u32);
+
+#[derive(Default)]
+struct Updates {
+    updates: Mutex<Vec<Update>>,
+}
+
+impl RenderObserver for Updates {
+    fn tile_completed(&self, tile: &TileUpdate) {
+        self.updates.lock().unwrap().push((tile.x0, tile.y0, tile.pixels.width(), tile.pixels.height(), tile.samples));
+    }
+}
+
+#[test]
+fn sample_streams_show_the_whole_frame() {
+    // Three threads take 24 samples a pass
+    let observer = Updates::default();
+    let streams = RenderSettings { samples_per_pixel: 60, ..settings(Strategy::Samples, 3) };
+    let output = render_with_observer(default_scene(&streams), &streams, &observer).unwrap();
+
+    let updates = observer.updates.into_inner().unwrap();
+    assert_eq!(updates, vec![(0, 0, 70, 45, 24), (0, 0, 70, 45, 48), (0, 0, 70, 45, 60)]);
+    assert_eq!(output.status, RenderStatus::Complete);
+}
+
+#[test]
+fn sample_streams_stop_early() {
+    let limited = RenderSettings { samples_per_pixel: u32::MAX, time_limit: Some(Duration::from_millis(300)), ..settings(Strategy::Samples, 3) };
+    let output = render_default(&limited).unwrap();
+
+    assert_eq!(output.status, RenderStatus::TimedOut);
+    // The streams stop wherever they are, so pixels are not evenly sampled
+    assert!(output.sample_counts.iter().any(|&n| n > 0));
+    assert!(output.image.pixels().iter().all(|c| c.x.is_finite() && c.y.is_finite() && c.z.is_finite()));
+}
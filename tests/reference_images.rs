@@ -0,0 +1,241 @@
+// Renders small, deterministic versions of the scenes and compares them with the images in
+// tests/references. A failing comparison writes the render and an amplified difference image
+// to target/tmp/reference-diffs. After an intended change to the picture, regenerate the
+// references with `UPDATE_REFERENCES=1 cargo test --release --test reference_images`.
+use std::path::{Path, PathBuf};
+use image::{Rgb, RgbImage};
+use parallel_raytracing_rs::utils::camera::Camera;
+use parallel_raytracing_rs::utils::geometry::{Hittable, HittableList, Sphere};
+use parallel_raytracing_rs::utils::material::Material;
+use parallel_raytracing_rs::utils::real::Real;
+use parallel_raytracing_rs::utils::renderer::{render, RenderSettings};
+use parallel_raytracing_rs::utils::sampler::SamplerKind;
+use parallel_raytracing_rs::utils::scene::{self, Scene};
+use parallel_raytracing_rs::utils::vector::Vec3;
+
+const WIDTH: u32 = 96;
+const HEIGHT: u32 = 64;
+const ASPECT_RATIO: Real = WIDTH as Real / HEIGHT as Real;
+
+// Renders are deterministic, so on one machine the images match exactly. The slack covers
+// floating point differences between platforms and compilers, and is well below what a change
+// to a material or the camera produces.
+#[cfg(not(feature = "f64"))]
+const MAX_RMSE: f64 = 0.003;
+// The references are f32 renders. In double precision a path that grazes an edge can take
+// another turn, which moves some of the noise around without changing the picture.
+#[cfg(feature = "f64")]
+const MAX_RMSE: f64 = 0.01;
+const MIN_SSIM: f64 = 0.995;
+const MAX_MEAN_DELTA_E: f64 = 0.15;
+
+struct Comparison {
+    rmse: f64,
+    ssim: f64,
+    mean_delta_e: f64,
+}
+
+impl Comparison {
+    fn passes(&self) -> bool {
+        self.rmse <= MAX_RMSE && self.ssim >= MIN_SSIM && self.mean_delta_e <= MAX_MEAN_DELTA_E
+    }
+}
+
+// One thread and a fixed seed, so that every sample is taken in the same order every run
+fn render_scene(scene: Scene, samples_per_pixel: u32) -> RgbImage {
+    let settings = RenderSettings {
+        image_width: WIDTH,
+        image_height: HEIGHT,
+        samples_per_pixel,
+        max_depth: 8,
+        num_threads: 1,
+        sampler: SamplerKind::Sobol,
+        seed: 0,
+        ..RenderSettings::default()
+    };
+    render(scene, &settings).unwrap().image.to_rgb_image()
+}
+
+// A sphere of the material under test on a grey ground, with a blue sphere behind it to give
+// reflections and refractions something to show
+fn material_scene(material: Material) -> Scene {
+    let ground = Material::Lambertian { albedo: Vec3::new(0.5, 0.5, 0.5) };
+    let backdrop = Material::Lambertian { albedo: Vec3::new(0.1, 0.2, 0.6) };
+    let world = HittableList::new(vec![
+        Box::new(Sphere::new(Vec3::new(0.0, -100.5, -1.0), 100.0, ground)) as Box<dyn Hittable + Sync + Send>,
+        Box::new(Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5, material)),
+        Box::new(Sphere::new(Vec3::new(-0.9, -0.1, -2.2), 0.4, backdrop)),
+    ]);
+    let camera = Camera::new(
+        Vec3::new(0.0, 0.3, 1.0),
+        Vec3::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        40.0,
+        ASPECT_RATIO,
+        0.0,
+        2.0,
+    );
+    Scene::new(world, camera)
+}
+
+fn to_unit(image: &RgbImage) -> Vec<[f64; 3]> {
+    image.pixels().map(|p| p.0.map(|c| c as f64 / 255.0)).collect()
+}
+
+fn rmse(a: &[[f64; 3]], b: &[[f64; 3]]) -> f64 {
+    let sum: f64 = a.iter().zip(b).flat_map(|(p, q)| (0..3).map(move |c| (p[c] - q[c]).powi(2))).sum();
+    (sum / (3 * a.len()) as f64).sqrt()
+}
+
+fn luma(p: &[f64; 3]) -> f64 {
+    0.2126 * p[0] + 0.7152 * p[1] + 0.0722 * p[2]
+}
+
+// Mean structural similarity of the luma over 8x8 windows, 1.0 for identical images
+fn ssim(a: &[[f64; 3]], b: &[[f64; 3]]) -> f64 {
+    const WINDOW: u32 = 8;
+    const C1: f64 = 0.01 * 0.01;
+    const C2: f64 = 0.03 * 0.03;
+
+    let mut total = 0.0;
+    let mut windows = 0;
+    for y0 in (0..=HEIGHT - WINDOW).step_by(WINDOW as usize / 2) {
+        for x0 in (0..=WIDTH - WINDOW).step_by(WINDOW as usize / 2) {
+            let pixels: Vec<(f64, f64)> = (y0..y0 + WINDOW)
+                .flat_map(|y| (x0..x0 + WINDOW).map(move |x| (y * WIDTH + x) as usize))
+                .map(|i| (luma(&a[i]), luma(&b[i])))
+                .collect();
+            let n = pixels.len() as f64;
+            let mean_a = pixels.iter().map(|p| p.0).sum::<f64>() / n;
+            let mean_b = pixels.iter().map(|p| p.1).sum::<f64>() / n;
+            let var_a = pixels.iter().map(|p| (p.0 - mean_a).powi(2)).sum::<f64>() / n;
+            let var_b = pixels.iter().map(|p| (p.1 - mean_b).powi(2)).sum::<f64>() / n;
+            let covariance = pixels.iter().map(|p| (p.0 - mean_a) * (p.1 - mean_b)).sum::<f64>() / n;
+
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            windows += 1;
+        }
+    }
+    total / windows as f64
+}
+
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+fn to_lab(p: &[f64; 3]) -> [f64; 3] {
+    let [r, g, b] = p.map(srgb_to_linear);
+    // XYZ relative to the D65 white point
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.9505;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.089;
+    let f = |t: f64| if t > 0.008856 { t.cbrt() } else { 7.787 * t + 16.0 / 116.0 };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+// 3x3 binomial blur, standing in for the loss of fine detail at a normal viewing distance
+fn blur(image: &[[f64; 3]]) -> Vec<[f64; 3]> {
+    let at = |x: i64, y: i64| {
+        let (x, y) = (x.clamp(0, WIDTH as i64 - 1), y.clamp(0, HEIGHT as i64 - 1));
+        image[(y * WIDTH as i64 + x) as usize]
+    };
+    (0..HEIGHT as i64).flat_map(|y| (0..WIDTH as i64).map(move |x| (x, y))).map(|(x, y)| {
+        let mut sum = [0.0; 3];
+        for dy in -1..=1i64 {
+            for dx in -1..=1i64 {
+                let weight = ((2 - dx.abs()) * (2 - dy.abs())) as f64 / 16.0;
+                let p = at(x + dx, y + dy);
+                for c in 0..3 {
+                    sum[c] += weight * p[c];
+                }
+            }
+        }
+        sum
+    }).collect()
+}
+
+// A simplified take on FLIP: the mean CIELAB colour difference after blurring both images. A
+// difference of around 2.3 is just noticeable.
+fn mean_delta_e(a: &[[f64; 3]], b: &[[f64; 3]]) -> f64 {
+    let (a, b) = (blur(a), blur(b));
+    let sum: f64 = a.iter().zip(b.iter()).map(|(p, q)| {
+        let (p, q) = (to_lab(p), to_lab(q));
+        ((p[0] - q[0]).powi(2) + (p[1] - q[1]).powi(2) + (p[2] - q[2]).powi(2)).sqrt()
+    }).sum();
+    sum / a.len() as f64
+}
+
+fn compare(image: &RgbImage, reference: &RgbImage) -> Comparison {
+    let (a, b) = (to_unit(image), to_unit(reference));
+    Comparison { rmse: rmse(&a, &b), ssim: ssim(&a, &b), mean_delta_e: mean_delta_e(&a, &b) }
+}
+
+// Per channel differences scaled up by 4 so that small changes are visible
+fn diff_image(image: &RgbImage, reference: &RgbImage) -> RgbImage {
+    RgbImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        let (p, q) = (image.get_pixel(x, y), reference.get_pixel(x, y));
+        Rgb(std::array::from_fn(|c| (p[c].abs_diff(q[c]) as u32 * 4).min(255) as u8))
+    })
+}
+
+fn reference_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("references").join(format!("{}.png", name))
+}
+
+fn check(name: &str, image: RgbImage) {
+    let reference_path = reference_path(name);
+    if std::env::var_os("UPDATE_REFERENCES").is_some() {
+        std::fs::create_dir_all(reference_path.parent().unwrap()).unwrap();
+        image.save(&reference_path).unwrap();
+        return;
+    }
+
+    let reference = image::open(&reference_path)
+        .unwrap_or_else(|e| panic!("no reference image at {} ({}), run with UPDATE_REFERENCES=1 to create it", reference_path.display(), e))
+        .to_rgb8();
+    assert_eq!(reference.dimensions(), image.dimensions(), "{} has changed size", name);
+
+    let comparison = compare(&image, &reference);
+    if !comparison.passes() {
+        let diff_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("reference-diffs");
+        std::fs::create_dir_all(&diff_dir).unwrap();
+        image.save(diff_dir.join(format!("{}.render.png", name))).unwrap();
+        diff_image(&image, &reference).save(diff_dir.join(format!("{}.diff.png", name))).unwrap();
+        panic!(
+            "{} differs from its reference: RMSE {:.4} (max {}), SSIM {:.4} (min {}), mean ΔE {:.3} (max {}). Render and difference written to {}",
+            name, comparison.rmse, MAX_RMSE, comparison.ssim, MIN_SSIM, comparison.mean_delta_e, MAX_MEAN_DELTA_E, diff_dir.display()
+        );
+    }
+}
+
+#[test]
+fn default_scene() {
+    check("default_scene", render_scene(scene::default_scene(ASPECT_RATIO, 20.0), 16));
+}
+
+#[test]
+fn random_scene() {
+    check("random_scene", render_scene(scene::seeded_random_scene(0, ASPECT_RATIO, 20.0), 8));
+}
+
+#[test]
+fn lambertian() {
+    check("lambertian", render_scene(material_scene(Material::Lambertian { albedo: Vec3::new(0.7, 0.3, 0.3) }), 16));
+}
+
+#[test]
+fn metal() {
+    check("metal", render_scene(material_scene(Material::Metal { albedo: Vec3::new(0.8, 0.8, 0.8), fuzz: 0.0 }), 16));
+}
+
+#[test]
+fn fuzzy_metal() {
+    check("fuzzy_metal", render_scene(material_scene(Material::Metal { albedo: Vec3::new(0.8, 0.6, 0.2), fuzz: 0.4 }), 16));
+}
+
+#[test]
+fn dielectric() {
+    check("dielectric", render_scene(material_scene(Material::Dielctric { ir: 1.5 }), 16));
+}
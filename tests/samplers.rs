@@ -0,0 +1,31 @@
+// Low-discrepancy samples converge faster than independent ones: at 4 samples per pixel the
+// Sobol sampler's render of the default scene is closer to a converged one than independent
+// sampling's.
+use parallel_raytracing_rs::{RenderSettings, SamplerKind, Vec3};
+use common::settings;
+
+mod common;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 40;
+
+fn render_sampled(sampler: SamplerKind, samples_per_pixel: u32) -> Vec<Vec3<f32>> {
+    let settings = RenderSettings { sampler, ..settings(WIDTH, HEIGHT, samples_per_pixel) };
+    common::render_default(&settings).unwrap().image.into_pixels()
+}
+
+// Over colour channels clamped to 0..1, so a rare bright path doesn't decide the comparison
+fn rmse(a: &[Vec3<f32>], b: &[Vec3<f32>]) -> f64 {
+    let channels = |p: &Vec3<f32>| [p.x, p.y, p.z].map(|c| c.clamp(0.0, 1.0) as f64);
+    let sum: f64 = a.iter().zip(b).flat_map(|(a, b)| channels(a).into_iter().zip(channels(b))).map(|(a, b)| (a - b) * (a - b)).sum();
+    (sum / (3 * a.len()) as f64).sqrt()
+}
+
+#[test]
+fn sobol_beats_independent_at_four_samples() {
+    let reference = render_sampled(SamplerKind::Sobol, 1024);
+    let independent = rmse(&render_sampled(SamplerKind::Independent, 4), &reference);
+    let sobol = rmse(&render_sampled(SamplerKind::Sobol, 4), &reference);
+    // About 17% lower at this size; the README quotes it
+    assert!(sobol < 0.9 * independent, "Sobol's RMSE {} is not clearly below independent sampling's {}", sobol, independent);
+}
@@ -0,0 +1,83 @@
+// Rays spawned from a hit leave the surface cleanly at every scale: none of those sent outwards
+// hit it again, and those sent inwards find the far side instead of slipping through. A fixed
+// minimum distance along the ray gets the smallest of these scenes wrong one way and the
+// largest the other.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use parallel_raytracing_rs::utils::vector::Dot;
+use parallel_raytracing_rs::{render, Bvh, Camera, Environment, Hittable, HittableList, Integrator, Light, Material, Ray, Real, RenderSettings, Scene, Sphere, SphereBatch, Vec3};
+
+mod common;
+
+const SCALES: [Real; 5] = [1e-4, 1e-2, 1.0, 1e2, 1e4];
+
+fn grey() -> Material {
+    Material::Lambertian { albedo: Vec3::new(0.5, 0.5, 0.5) }
+}
+
+// Off the origin, as the ground sphere is, so the hit points carry some absolute error too
+fn sphere(radius: Real) -> Sphere {
+    Sphere::new(Vec3::new(0.3, -1.0, 0.2) * radius, radius, grey())
+}
+
+fn random_unit(rng: &mut StdRng) -> Vec3<Real> {
+    Vec3::on_unit_sphere((rng.gen(), rng.gen()))
+}
+
+fn check_spawned_rays(object: &dyn Hittable, center: Vec3<Real>, radius: Real) {
+    let mut rng = StdRng::seed_from_u64(3);
+    for _ in 0..2000 {
+        // From outside, at a random point of the sphere
+        let origin = center + random_unit(&mut rng) * (3.0 * radius);
+        let target = center + random_unit(&mut rng) * (0.9 * radius);
+        let rec = object.hit(&Ray::new(origin, target - origin), 0.0, Real::INFINITY).expect("the ray was aimed at the sphere");
+
+        let direction = random_unit(&mut rng);
+        let cos_theta = direction.dot(rec.normal);
+        let again = object.hit(&rec.spawn_ray(direction), 0.0, Real::INFINITY);
+        if cos_theta > 0.0 {
+            assert!(again.is_none(), "radius {}: a ray leaving at cos {} hit the sphere again at t = {}", radius, cos_theta, again.unwrap().t);
+        } else if cos_theta < -0.1 {
+            // The chord is at least a fifth of the radius long
+            let t = again.map(|rec| rec.t);
+            assert!(t.is_some_and(|t| t > 0.1 * radius), "radius {}: a ray entering at cos {} found {:?} instead of the far side", radius, cos_theta, t);
+        }
+    }
+}
+
+#[test]
+fn spawned_rays_leave_the_surface() {
+    for radius in SCALES {
+        let center = sphere(radius).center;
+        check_spawned_rays(&sphere(radius), center, radius);
+        check_spawned_rays(&SphereBatch::new(vec![sphere(radius)]), center, radius);
+        check_spawned_rays(&Bvh::new(vec![sphere(radius)]), center, radius);
+    }
+}
+
+// A directional light over a ground sphere much larger than the view, everything scaled by
+// `scale`. Under a black sky the ground is lit the same everywhere; a ray that hits the surface
+// it left would leave a dark speck, and one that skips past it would leave the image black.
+fn ground_scene(scale: Real) -> Scene {
+    let ground = Sphere::new(Vec3::new(0.0, -1000.0, 0.0) * scale, 1000.0 * scale, grey());
+    let world = HittableList::new(vec![Box::new(ground)]);
+    let camera = Camera::new(Vec3::new(0.0, 2.0, 2.0) * scale, Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 30.0, 1.0, 0.0, 2.8 * scale);
+    Scene::new(world, camera)
+        .with_environment(Environment::Constant(Vec3::new(0.0, 0.0, 0.0)))
+        .with_light(Light::directional(Vec3::new(-0.3, -1.0, -0.5), Vec3::new(1.0, 1.0, 1.0), 2.0))
+}
+
+#[test]
+fn ground_is_evenly_lit_at_every_scale() {
+    for integrator in [Integrator::Megakernel, Integrator::Wavefront] {
+        for scale in SCALES {
+            let settings = RenderSettings { max_depth: 3, integrator, ..common::settings(24, 24, 4) };
+            let image = render(ground_scene(scale), &settings).unwrap().image.into_pixels();
+            let mean = image.iter().map(|p| p.y).sum::<f32>() / image.len() as f32;
+            assert!(mean > 0.0, "{:?} at scale {}: the ground is missing", integrator, scale);
+            for (i, p) in image.iter().enumerate() {
+                assert!((p.y - mean).abs() <= 0.01 * mean, "{:?} at scale {}: pixel {} is {} against a mean of {}", integrator, scale, i, p.y, mean);
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+// The wavefront integrator against the recursive megakernel: it takes the same random numbers
+// for every path and adds up the same light, so the images agree to rounding, and the rays
+// traced and what each camera ray hit first are exactly the same.
+use std::sync::Arc;
+use parallel_raytracing_rs::{render, scene, CancellationToken, Environment, Integrator, Light, Pass, Real, RenderOutput, RenderSettings, RenderStatus, SamplerKind, Scene, Sky, Strategy, Vec3};
+
+mod common;
+
+const WIDTH: u32 = 60;
+const HEIGHT: u32 = 40;
+
+fn settings(integrator: Integrator) -> RenderSettings {
+    RenderSettings { max_depth: 5, num_threads: 2, integrator, ..common::settings(WIDTH, HEIGHT, 6) }
+}
+
+// Point and directional lights and a sampled sky, so that every kind of shadow ray is traced
+fn lit_scene() -> Scene {
+    scene::default_scene(WIDTH as Real / HEIGHT as Real, 20.0)
+        .with_environment(Environment::Sky(Sky::new(Vec3::new(1.0, 0.6, 0.5), 3.0, Vec3::new(0.3, 0.3, 0.3))))
+        .with_light(Light::point(Vec3::new(0.0, 3.0, 1.0), Vec3::new(1.0, 0.9, 0.8), 20.0))
+        .with_light(Light::directional(Vec3::new(-1.0, -1.0, -0.5), Vec3::new(1.0, 1.0, 1.0), 0.5))
+}
+
+fn assert_close(wavefront: &RenderOutput, megakernel: &RenderOutput, what: &str) {
+    for (a, b) in wavefront.image.pixels().iter().zip(megakernel.image.pixels()) {
+        let difference = (a.x - b.x).abs().max((a.y - b.y).abs()).max((a.z - b.z).abs());
+        let scale = b.x.abs().max(b.y.abs()).max(b.z.abs()).max(1.0);
+        assert!(difference <= 1e-4 * scale, "{}: {:?} against {:?}", what, (a.x, a.y, a.z), (b.x, b.y, b.z));
+    }
+    assert_eq!(wavefront.stats.total_rays(), megakernel.stats.total_rays(), "{}", what);
+    assert_eq!(wavefront.stats.shadow_rays, megakernel.stats.shadow_rays, "{}", what);
+}
+
+#[test]
+fn matches_the_megakernel() {
+    let scenes = [
+        ("default scene", scene::default_scene(WIDTH as Real / HEIGHT as Real, 20.0)),
+        ("lit scene", lit_scene()),
+        ("random scene", scene::seeded_random_scene(7, WIDTH as Real / HEIGHT as Real, 20.0)),
+    ];
+    for (name, scene) in scenes {
+        let scene = Arc::new(scene);
+        let wavefront = render(scene.clone(), &settings(Integrator::Wavefront)).unwrap();
+        let megakernel = render(scene, &settings(Integrator::Megakernel)).unwrap();
+        assert_close(&wavefront, &megakernel, name);
+    }
+}
+
+// Every sampler keeps its place in each path's numbers while the wave is shaded out of order
+#[test]
+fn every_sampler_resumes() {
+    for sampler in [SamplerKind::Independent, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol, SamplerKind::BlueNoise] {
+        let wavefront = render(lit_scene(), &RenderSettings { sampler, ..settings(Integrator::Wavefront) }).unwrap();
+        let megakernel = render(lit_scene(), &RenderSettings { sampler, ..settings(Integrator::Megakernel) }).unwrap();
+        assert_close(&wavefront, &megakernel, &format!("{:?}", sampler));
+    }
+}
+
+#[test]
+fn first_hits_match() {
+    let passes = vec![Pass::Depth, Pass::Normal, Pass::ObjectId, Pass::MaterialId, Pass::SampleCount];
+    let wavefront = render(lit_scene(), &RenderSettings { passes: passes.clone(), ..settings(Integrator::Wavefront) }).unwrap();
+    let megakernel = render(lit_scene(), &RenderSettings { passes: passes.clone(), ..settings(Integrator::Megakernel) }).unwrap();
+    for pass in passes {
+        assert_eq!(wavefront.aov(pass), megakernel.aov(pass), "{:?}", pass);
+    }
+}
+
+#[test]
+fn strategies_agree() {
+    let reference = render(lit_scene(), &settings(Integrator::Wavefront)).unwrap();
+    for strategy in [Strategy::Rows, Strategy::Tiles, Strategy::SamplePasses] {
+        let output = render(lit_scene(), &RenderSettings { strategy, num_threads: 3, ..settings(Integrator::Wavefront) }).unwrap();
+        let same = output.image.pixels().iter().zip(reference.image.pixels()).all(|(a, b)| (a.x, a.y, a.z) == (b.x, b.y, b.z));
+        assert!(same, "{:?}", strategy);
+    }
+}
+
+#[test]
+fn cancelled_wavefront_stops() {
+    let token = CancellationToken::new();
+    token.cancel();
+    let output = render(lit_scene(), &RenderSettings { cancel: Some(token), ..settings(Integrator::Wavefront) }).unwrap();
+    assert_eq!(output.status, RenderStatus::Cancelled);
+    assert!(output.sample_counts.iter().all(|&n| n == 0));
+}